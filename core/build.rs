@@ -0,0 +1,36 @@
+//! Generates the LED gamma correction table in `$OUT_DIR/gamma.rs`.
+//!
+//! The eye's response to brightness is roughly a power law, so linear PWM
+//! duty steps look uneven: most of the visible change happens near zero.
+//! Mapping perceived levels through `duty = level ^ GAMMA` evens them out.
+
+use std::{env, fmt::Write, fs, path::Path};
+
+// sRGB's 2.2 assumes a display that already darkens its low end; an LED's
+// light is linear in duty, so it needs a steeper curve for dim steps to look even.
+const GAMMA: f64 = 2.8;
+// One entry per 8-bit brightness level.
+const LEVELS: usize = 256;
+
+fn main() {
+    let mut table = String::new();
+    writeln!(table, "/// Gamma used to generate [`GAMMA_TABLE`].").unwrap();
+    writeln!(table, "pub const GAMMA: f32 = {:?};", GAMMA as f32).unwrap();
+    writeln!(
+        table,
+        "/// 16-bit duty for each 8-bit brightness level, `(level / 255) ^ {}`.",
+        GAMMA
+    )
+    .unwrap();
+    writeln!(table, "pub const GAMMA_TABLE: [u16; {}] = [", LEVELS).unwrap();
+    for level in 0..LEVELS {
+        let linear = level as f64 / (LEVELS - 1) as f64;
+        let duty = (linear.powf(GAMMA) * u16::MAX as f64).round() as u16;
+        writeln!(table, "    {},", duty).unwrap();
+    }
+    writeln!(table, "];").unwrap();
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("gamma.rs");
+    fs::write(out, table).unwrap();
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,471 @@
+//! ADC acquisition settings for the ADC DMA example.
+//!
+//! [`AcquisitionConfig`] holds the inputs, resolution, sample time, ADC clock
+//! and sample rate, and checks them against the ADC's clock limits and the
+//! time a frame of conversions takes. Console lines are parsed into
+//! [`AcquisitionCommand`]s, each of which changes one setting.
+//!
+//! Two internal channels, VREFINT and the temperature sensor, follow the
+//! inputs in every frame at a fixed sample time, and count towards its
+//! conversion time.
+
+use core::fmt;
+
+use crate::command::CommandError;
+
+/// Internal channels converted after the inputs in each frame.
+pub const INTERNAL_CHANNELS: u32 = 2;
+/// Sample time of the internal channels, long enough for the temperature
+/// sensor's 10 us minimum at any ADC clock.
+pub const INTERNAL_SAMPLE_TIME: SampleTime = SampleTime::Cycles480;
+
+/// The ADC clock range with VDDA from 2.4 V to 3.6 V.
+pub const ADC_CLOCK_RANGE_HZ: core::ops::RangeInclusive<u32> = 600_000..=36_000_000;
+
+/// A pin that can be sampled as a mic input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AnalogInput {
+    Pa1,
+    Pa2,
+    Pa3,
+}
+
+impl AnalogInput {
+    /// Parses a pin name like `pa1`, in either case.
+    pub fn parse(name: &str) -> Option<Self> {
+        [Self::Pa1, Self::Pa2, Self::Pa3]
+            .into_iter()
+            .find(|input| input.name().eq_ignore_ascii_case(name))
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Pa1 => "PA1",
+            Self::Pa2 => "PA2",
+            Self::Pa3 => "PA3",
+        }
+    }
+}
+
+impl fmt::Display for AnalogInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Bits per conversion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Resolution {
+    Six,
+    Eight,
+    Ten,
+    Twelve,
+}
+
+impl Resolution {
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        [Self::Six, Self::Eight, Self::Ten, Self::Twelve]
+            .into_iter()
+            .find(|resolution| resolution.bits() == bits)
+    }
+
+    /// Also the ADC clock cycles a conversion takes after sampling.
+    pub const fn bits(self) -> u32 {
+        match self {
+            Self::Six => 6,
+            Self::Eight => 8,
+            Self::Ten => 10,
+            Self::Twelve => 12,
+        }
+    }
+}
+
+/// ADC clock cycles spent sampling each channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SampleTime {
+    Cycles3,
+    Cycles15,
+    Cycles28,
+    Cycles56,
+    Cycles84,
+    Cycles112,
+    Cycles144,
+    Cycles480,
+}
+
+impl SampleTime {
+    pub fn from_cycles(cycles: u32) -> Option<Self> {
+        [
+            Self::Cycles3,
+            Self::Cycles15,
+            Self::Cycles28,
+            Self::Cycles56,
+            Self::Cycles84,
+            Self::Cycles112,
+            Self::Cycles144,
+            Self::Cycles480,
+        ]
+        .into_iter()
+        .find(|sample_time| sample_time.cycles() == cycles)
+    }
+
+    pub const fn cycles(self) -> u32 {
+        match self {
+            Self::Cycles3 => 3,
+            Self::Cycles15 => 15,
+            Self::Cycles28 => 28,
+            Self::Cycles56 => 56,
+            Self::Cycles84 => 84,
+            Self::Cycles112 => 112,
+            Self::Cycles144 => 144,
+            Self::Cycles480 => 480,
+        }
+    }
+}
+
+/// The ADC clock's divider from PCLK2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ClockDivider {
+    Two,
+    Four,
+    Six,
+    Eight,
+}
+
+impl ClockDivider {
+    pub fn from_divisor(divisor: u32) -> Option<Self> {
+        [Self::Two, Self::Four, Self::Six, Self::Eight]
+            .into_iter()
+            .find(|divider| divider.divisor() == divisor)
+    }
+
+    pub const fn divisor(self) -> u32 {
+        match self {
+            Self::Two => 2,
+            Self::Four => 4,
+            Self::Six => 6,
+            Self::Eight => 8,
+        }
+    }
+}
+
+/// What the hardware allows, for [`AcquisitionConfig::validate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// The clock the ADC clock is divided from.
+    pub pclk2_hz: u32,
+    /// The lowest sample rate, e.g. for blocks to keep up with a watchdog.
+    pub min_rate_hz: u32,
+    /// The highest sample rate, e.g. what the UART can send.
+    pub max_rate_hz: u32,
+}
+
+/// Why an [`AcquisitionConfig`] can't be used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AcquisitionError {
+    /// Both inputs are the same pin.
+    SameInputs,
+    /// The divided clock is outside [`ADC_CLOCK_RANGE_HZ`].
+    AdcClock { adc_clock_hz: u32 },
+    /// The sample rate is outside the [`Limits`].
+    SampleRate { min_hz: u32, max_hz: u32 },
+    /// A frame of conversions doesn't fit in a sample period.
+    TooSlow { frame_us: u32, period_us: u32 },
+}
+
+impl fmt::Display for AcquisitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AcquisitionError::SameInputs => f.write_str("the inputs must be different pins"),
+            AcquisitionError::AdcClock { adc_clock_hz } => write!(
+                f,
+                "ADC clock {} Hz is outside {}..={} Hz",
+                adc_clock_hz,
+                ADC_CLOCK_RANGE_HZ.start(),
+                ADC_CLOCK_RANGE_HZ.end()
+            ),
+            AcquisitionError::SampleRate { min_hz, max_hz } => {
+                write!(f, "sample rate must be {}..={} Hz", min_hz, max_hz)
+            }
+            AcquisitionError::TooSlow {
+                frame_us,
+                period_us,
+            } => write!(
+                f,
+                "a frame takes {} us, longer than the {} us sample period",
+                frame_us, period_us
+            ),
+        }
+    }
+}
+
+/// How the ADC samples the mics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AcquisitionConfig {
+    /// The mics, in the order they're converted and sent.
+    pub inputs: [AnalogInput; 2],
+    pub resolution: Resolution,
+    /// For the inputs; see [`INTERNAL_SAMPLE_TIME`].
+    pub sample_time: SampleTime,
+    pub clock_divider: ClockDivider,
+    pub sample_rate_hz: u32,
+}
+
+impl AcquisitionConfig {
+    /// The settings the example starts with.
+    pub const DEFAULT: Self = Self {
+        inputs: [AnalogInput::Pa1, AnalogInput::Pa2],
+        resolution: Resolution::Ten,
+        sample_time: SampleTime::Cycles480,
+        clock_divider: ClockDivider::Eight,
+        sample_rate_hz: 1000,
+    };
+
+    pub const fn adc_clock_hz(&self, pclk2_hz: u32) -> u32 {
+        pclk2_hz / self.clock_divider.divisor()
+    }
+
+    /// ADC clock cycles to convert a frame: the inputs and the internal
+    /// channels.
+    pub const fn frame_cycles(&self) -> u32 {
+        let bits = self.resolution.bits();
+        let inputs = self.inputs.len() as u32 * (self.sample_time.cycles() + bits);
+        inputs + INTERNAL_CHANNELS * (INTERNAL_SAMPLE_TIME.cycles() + bits)
+    }
+
+    /// Checks the settings can be used within `limits`.
+    pub fn validate(&self, limits: &Limits) -> Result<(), AcquisitionError> {
+        if self.inputs[0] == self.inputs[1] {
+            return Err(AcquisitionError::SameInputs);
+        }
+        let adc_clock_hz = self.adc_clock_hz(limits.pclk2_hz);
+        if !ADC_CLOCK_RANGE_HZ.contains(&adc_clock_hz) {
+            return Err(AcquisitionError::AdcClock { adc_clock_hz });
+        }
+        if !(limits.min_rate_hz..=limits.max_rate_hz).contains(&self.sample_rate_hz) {
+            return Err(AcquisitionError::SampleRate {
+                min_hz: limits.min_rate_hz,
+                max_hz: limits.max_rate_hz,
+            });
+        }
+        // Compared in cycles of both clocks at once, so nothing is rounded.
+        let frame_cycles = u64::from(self.frame_cycles());
+        if frame_cycles * u64::from(self.sample_rate_hz) > u64::from(adc_clock_hz) {
+            return Err(AcquisitionError::TooSlow {
+                frame_us: (frame_cycles * 1_000_000).div_ceil(u64::from(adc_clock_hz)) as u32,
+                period_us: 1_000_000 / self.sample_rate_hz,
+            });
+        }
+        Ok(())
+    }
+
+    /// A copy with the setting `command` changes. Commands that don't
+    /// change anything return it as it is.
+    pub fn with(&self, command: AcquisitionCommand) -> Self {
+        let mut config = *self;
+        match command {
+            AcquisitionCommand::Inputs(inputs) => config.inputs = inputs,
+            AcquisitionCommand::Resolution(resolution) => config.resolution = resolution,
+            AcquisitionCommand::SampleTime(sample_time) => config.sample_time = sample_time,
+            AcquisitionCommand::Divider(divider) => config.clock_divider = divider,
+            AcquisitionCommand::Rate(hz) => config.sample_rate_hz = hz,
+            AcquisitionCommand::Status | AcquisitionCommand::Help => {}
+        }
+        config
+    }
+}
+
+impl Default for AcquisitionConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl fmt::Display for AcquisitionConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "inputs {} {}, {} bits, {} cycles, PCLK2 / {}, {} Hz",
+            self.inputs[0],
+            self.inputs[1],
+            self.resolution.bits(),
+            self.sample_time.cycles(),
+            self.clock_divider.divisor(),
+            self.sample_rate_hz
+        )
+    }
+}
+
+/// A command typed at the ADC DMA example's console.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AcquisitionCommand {
+    /// `inputs <pin> <pin>`: choose the mic pins.
+    Inputs([AnalogInput; 2]),
+    /// `resolution <6|8|10|12>`: set the bits per conversion.
+    Resolution(Resolution),
+    /// `sampletime <cycles>`: set the inputs' sample time.
+    SampleTime(SampleTime),
+    /// `divider <2|4|6|8>`: set the ADC clock divider.
+    Divider(ClockDivider),
+    /// `rate <hz>`: set the sample rate.
+    Rate(u32),
+    /// `status`: report the current settings.
+    Status,
+    /// `help`: list the commands.
+    Help,
+}
+
+/// The reply to `help`.
+pub const HELP: &str = "commands: inputs <pin> <pin>, resolution <6|8|10|12>, \
+     sampletime <3|15|28|56|84|112|144|480>, divider <2|4|6|8>, rate <hz>, status, help";
+
+/// Parses a line of input. Leading and trailing whitespace is ignored.
+pub fn parse(line: &str) -> Result<AcquisitionCommand, CommandError> {
+    let mut words = line.split_ascii_whitespace();
+    let name = words.next().ok_or(CommandError::Unknown)?;
+    let mut number = || -> Result<u32, CommandError> {
+        let word = words.next().ok_or(CommandError::MissingArgument)?;
+        word.parse().map_err(|_| CommandError::InvalidArgument)
+    };
+
+    let command = match name {
+        "inputs" => {
+            let mut input = || {
+                let word = words.next().ok_or(CommandError::MissingArgument)?;
+                AnalogInput::parse(word).ok_or(CommandError::InvalidArgument)
+            };
+            AcquisitionCommand::Inputs([input()?, input()?])
+        }
+        "resolution" => AcquisitionCommand::Resolution(
+            Resolution::from_bits(number()?).ok_or(CommandError::InvalidArgument)?,
+        ),
+        "sampletime" => AcquisitionCommand::SampleTime(
+            SampleTime::from_cycles(number()?).ok_or(CommandError::InvalidArgument)?,
+        ),
+        "divider" => AcquisitionCommand::Divider(
+            ClockDivider::from_divisor(number()?).ok_or(CommandError::InvalidArgument)?,
+        ),
+        "rate" => AcquisitionCommand::Rate(number()?),
+        "status" => AcquisitionCommand::Status,
+        "help" => AcquisitionCommand::Help,
+        _ => return Err(CommandError::Unknown),
+    };
+
+    if words.next().is_some() {
+        return Err(CommandError::TooManyArguments);
+    }
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The example's clocks and limits.
+    const LIMITS: Limits = Limits {
+        pclk2_hz: 21_000_000,
+        min_rate_hz: 80,
+        max_rate_hz: 1536,
+    };
+
+    #[test]
+    fn default_config_is_valid() {
+        let config = AcquisitionConfig::DEFAULT;
+        assert_eq!(config.validate(&LIMITS), Ok(()));
+        assert_eq!(config.adc_clock_hz(LIMITS.pclk2_hz), 2_625_000);
+        assert_eq!(config.frame_cycles(), 4 * (480 + 10));
+        assert_eq!(
+            config.to_string(),
+            "inputs PA1 PA2, 10 bits, 480 cycles, PCLK2 / 8, 1000 Hz"
+        );
+    }
+
+    #[test]
+    fn frames_must_fit_in_the_sample_period() {
+        // 1960 cycles at 2.625 MHz is 747 us, too long at 1500 Hz...
+        let config = AcquisitionConfig::DEFAULT.with(AcquisitionCommand::Rate(1500));
+        assert_eq!(
+            config.validate(&LIMITS),
+            Err(AcquisitionError::TooSlow {
+                frame_us: 747,
+                period_us: 666
+            })
+        );
+        // ...but fine with a faster clock, or shorter input sample times.
+        let faster = config.with(AcquisitionCommand::Divider(ClockDivider::Four));
+        assert_eq!(faster.validate(&LIMITS), Ok(()));
+        let shorter = config.with(AcquisitionCommand::SampleTime(SampleTime::Cycles28));
+        assert_eq!(shorter.validate(&LIMITS), Ok(()));
+    }
+
+    #[test]
+    fn limits_are_enforced() {
+        let config = AcquisitionConfig::DEFAULT;
+        let rate = config.with(AcquisitionCommand::Rate(2000));
+        assert_eq!(
+            rate.validate(&LIMITS),
+            Err(AcquisitionError::SampleRate {
+                min_hz: 80,
+                max_hz: 1536
+            })
+        );
+        assert_eq!(
+            rate.validate(&LIMITS).unwrap_err().to_string(),
+            "sample rate must be 80..=1536 Hz"
+        );
+
+        let same = config.with(AcquisitionCommand::Inputs([AnalogInput::Pa2; 2]));
+        assert_eq!(same.validate(&LIMITS), Err(AcquisitionError::SameInputs));
+
+        // A faster PCLK2 can take the ADC clock over its limit.
+        let fast_bus = Limits {
+            pclk2_hz: 84_000_000,
+            ..LIMITS
+        };
+        let divided = config.with(AcquisitionCommand::Divider(ClockDivider::Two));
+        assert_eq!(
+            divided.validate(&fast_bus),
+            Err(AcquisitionError::AdcClock {
+                adc_clock_hz: 42_000_000
+            })
+        );
+    }
+
+    #[test]
+    fn commands_parse() {
+        assert_eq!(
+            parse("inputs pa3 PA1"),
+            Ok(AcquisitionCommand::Inputs([
+                AnalogInput::Pa3,
+                AnalogInput::Pa1
+            ]))
+        );
+        assert_eq!(
+            parse(" resolution 12 "),
+            Ok(AcquisitionCommand::Resolution(Resolution::Twelve))
+        );
+        assert_eq!(
+            parse("sampletime 56"),
+            Ok(AcquisitionCommand::SampleTime(SampleTime::Cycles56))
+        );
+        assert_eq!(
+            parse("divider 6"),
+            Ok(AcquisitionCommand::Divider(ClockDivider::Six))
+        );
+        assert_eq!(parse("rate 500"), Ok(AcquisitionCommand::Rate(500)));
+        assert_eq!(parse("status"), Ok(AcquisitionCommand::Status));
+
+        assert_eq!(parse("resolution 11"), Err(CommandError::InvalidArgument));
+        assert_eq!(parse("inputs pa1"), Err(CommandError::MissingArgument));
+        assert_eq!(parse("inputs pa1 pb0"), Err(CommandError::InvalidArgument));
+        assert_eq!(parse("rate 500 600"), Err(CommandError::TooManyArguments));
+        assert_eq!(parse("gain 2"), Err(CommandError::Unknown));
+    }
+}
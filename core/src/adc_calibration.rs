@@ -0,0 +1,188 @@
+//! Scaling ADC counts with the chip's factory calibration.
+//!
+//! The ADC measures against VDDA, nominally 3.3 V but different on every
+//! board and with every supply, so raw counts can't be compared between
+//! them. During manufacture ST converts the internal reference, VREFINT, and
+//! the temperature sensor at a VDDA of exactly 3.3 V, and stores the counts
+//! in system memory. Converting VREFINT again now gives the actual VDDA,
+//! which scales any count to millivolts, and the sensor counts at 30 °C and
+//! 110 °C give a line from its counts to degrees.
+//!
+//! The stored counts are 12-bit; readings at lower resolutions are scaled up
+//! to match. Everything is integer arithmetic.
+
+use core::fmt;
+
+/// VDDA when the factory values were measured.
+pub const FACTORY_VDDA_MV: u32 = 3300;
+/// Resolution of the factory values.
+pub const FACTORY_BITS: u32 = 12;
+/// Temperatures of the two factory sensor readings.
+pub const TS_CAL1_CELSIUS: i32 = 30;
+pub const TS_CAL2_CELSIUS: i32 = 110;
+
+/// The factory counts from system memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FactoryCalibration {
+    /// VREFINT.
+    pub vrefint: u16,
+    /// The temperature sensor at [`TS_CAL1_CELSIUS`].
+    pub ts_cal1: u16,
+    /// The temperature sensor at [`TS_CAL2_CELSIUS`].
+    pub ts_cal2: u16,
+}
+
+impl FactoryCalibration {
+    /// VDDA in millivolts from a `bits`-bit VREFINT reading, or `None` for a
+    /// reading of zero.
+    pub fn vdda_mv(&self, vrefint: u16, bits: u32) -> Option<u32> {
+        let reading = to_factory_bits(vrefint, bits);
+        if reading == 0 {
+            return None;
+        }
+        Some(FACTORY_VDDA_MV * u32::from(self.vrefint) / reading)
+    }
+
+    /// Chip temperature in thousandths of a degree Celsius from a `bits`-bit
+    /// sensor reading taken with VDDA at `vdda_mv`, or `None` if the two
+    /// factory readings are equal.
+    pub fn millicelsius(&self, sensor: u16, bits: u32, vdda_mv: u32) -> Option<i32> {
+        let span = i64::from(self.ts_cal2) - i64::from(self.ts_cal1);
+        if span == 0 {
+            return None;
+        }
+        // The reading as it would have been at the factory VDDA, times that
+        // VDDA, so nothing is rounded before the final division.
+        let reading = i64::from(to_factory_bits(sensor, bits)) * i64::from(vdda_mv);
+        let above_cal1 = reading - i64::from(self.ts_cal1) * i64::from(FACTORY_VDDA_MV);
+        let degrees = i64::from(TS_CAL2_CELSIUS - TS_CAL1_CELSIUS);
+        let millicelsius = above_cal1 * degrees * 1000 / (span * i64::from(FACTORY_VDDA_MV));
+        Some(TS_CAL1_CELSIUS * 1000 + millicelsius as i32)
+    }
+}
+
+/// A `bits`-bit count in millivolts, with VDDA at `vdda_mv`.
+pub fn millivolts(count: u16, bits: u32, vdda_mv: u32) -> u32 {
+    u32::from(count) * vdda_mv / ((1 << bits) - 1)
+}
+
+fn to_factory_bits(count: u16, bits: u32) -> u32 {
+    u32::from(count) << FACTORY_BITS.saturating_sub(bits)
+}
+
+/// The latest VDDA and chip temperature, updated from periodic readings of
+/// the internal channels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdcCalibration {
+    factory: FactoryCalibration,
+    vdda_mv: u32,
+    millicelsius: Option<i32>,
+}
+
+impl AdcCalibration {
+    /// Assumes the factory VDDA until the first [`update`](Self::update).
+    pub const fn new(factory: FactoryCalibration) -> Self {
+        Self {
+            factory,
+            vdda_mv: FACTORY_VDDA_MV,
+            millicelsius: None,
+        }
+    }
+
+    /// Takes `bits`-bit readings of VREFINT and the temperature sensor,
+    /// converted together. A reading of zero leaves the values unchanged.
+    pub fn update(&mut self, vrefint: u16, sensor: u16, bits: u32) {
+        let Some(vdda_mv) = self.factory.vdda_mv(vrefint, bits) else {
+            return;
+        };
+        self.vdda_mv = vdda_mv;
+        self.millicelsius = self.factory.millicelsius(sensor, bits, vdda_mv);
+    }
+
+    pub fn vdda_mv(&self) -> u32 {
+        self.vdda_mv
+    }
+
+    /// `None` until the first update.
+    pub fn millicelsius(&self) -> Option<i32> {
+        self.millicelsius
+    }
+
+    /// A `bits`-bit count in millivolts at the latest VDDA.
+    pub fn millivolts(&self, count: u16, bits: u32) -> u32 {
+        millivolts(count, bits, self.vdda_mv)
+    }
+}
+
+impl fmt::Display for AdcCalibration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "VDDA {} mV", self.vdda_mv)?;
+        if let Some(millicelsius) = self.millicelsius {
+            // Tenths, rounded towards zero like the rest.
+            let tenths = millicelsius / 100;
+            let sign = if tenths < 0 { "-" } else { "" };
+            let tenths = tenths.unsigned_abs();
+            write!(f, ", chip {}{}.{} C", sign, tenths / 10, tenths % 10)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Round numbers near a real F407's: VREFINT is 1.21 V, and the sensor
+    // rises 3 counts a degree from 0.76 V at 30 °C.
+    const FACTORY: FactoryCalibration = FactoryCalibration {
+        vrefint: 1500,
+        ts_cal1: 940,
+        ts_cal2: 1180,
+    };
+
+    #[test]
+    fn vrefint_gives_vdda() {
+        assert_eq!(FACTORY.vdda_mv(1500, 12), Some(3300));
+        assert_eq!(FACTORY.vdda_mv(1650, 12), Some(3000));
+        // 10-bit readings are scaled up to match.
+        assert_eq!(FACTORY.vdda_mv(375, 10), Some(3300));
+        assert_eq!(FACTORY.vdda_mv(0, 12), None);
+    }
+
+    #[test]
+    fn sensor_gives_temperature() {
+        assert_eq!(FACTORY.millicelsius(940, 12, 3300), Some(30_000));
+        assert_eq!(FACTORY.millicelsius(1180, 12, 3300), Some(110_000));
+        assert_eq!(FACTORY.millicelsius(955, 12, 3300), Some(35_000));
+        assert_eq!(FACTORY.millicelsius(930, 12, 3300), Some(26_667));
+        // At a lower VDDA the same voltage gives more counts.
+        assert_eq!(FACTORY.millicelsius(1166, 12, 3000), Some(70_000));
+
+        let flat = FactoryCalibration {
+            ts_cal2: 940,
+            ..FACTORY
+        };
+        assert_eq!(flat.millicelsius(955, 12, 3300), None);
+    }
+
+    #[test]
+    fn counts_scale_to_millivolts() {
+        assert_eq!(millivolts(4095, 12, 3300), 3300);
+        assert_eq!(millivolts(1023, 10, 3000), 3000);
+        assert_eq!(millivolts(512, 10, 3000), 1501);
+
+        let mut calibration = AdcCalibration::new(FACTORY);
+        assert_eq!(calibration.to_string(), "VDDA 3300 mV");
+        calibration.update(450, 318, 10);
+        assert_eq!(calibration.vdda_mv(), 2750);
+        assert_eq!(calibration.millicelsius(), Some(70_000));
+        assert_eq!(calibration.millivolts(1023, 10), 2750);
+        assert_eq!(calibration.to_string(), "VDDA 2750 mV, chip 70.0 C");
+
+        // A zero reading keeps the last values.
+        calibration.update(0, 0, 10);
+        assert_eq!(calibration.vdda_mv(), 2750);
+    }
+}
@@ -0,0 +1,283 @@
+//! Button-controlled blink rate and program state for the UART example.
+//!
+//! The program goes inactive after a number of presses and comes back after
+//! an inactivity period, or on the `activate` command. [`next_state`] is the
+//! whole transition table; the example supplies the timer and reports the
+//! [`Transition`]s.
+
+use core::fmt;
+
+/// Whether the program is responding to button presses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum State {
+    Active,
+    Inactive,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            State::Active => "active",
+            State::Inactive => "inactive",
+        })
+    }
+}
+
+/// Something that can change the program state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Input {
+    /// The allowed number of presses is used up.
+    PressLimit,
+    /// The inactivity timer ran out.
+    TimerExpired,
+    /// The `activate` command.
+    Activate,
+}
+
+/// The transition table: the state after `input`, or `None` if `input`
+/// doesn't apply in `state`, e.g. a stale timer expiry once the program is
+/// active again.
+pub const fn next_state(state: State, input: Input) -> Option<State> {
+    match (state, input) {
+        (State::Active, Input::PressLimit) => Some(State::Inactive),
+        (State::Inactive, Input::TimerExpired | Input::Activate) => Some(State::Active),
+        _ => None,
+    }
+}
+
+/// A change of state, for notifying the user and driving the timer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Transition {
+    pub from: State,
+    pub to: State,
+    pub input: Input,
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.input {
+            Input::PressLimit => "press limit reached",
+            Input::TimerExpired => "inactivity period over",
+            Input::Activate => "activate command",
+        };
+        write!(f, "{} -> {} ({})", self.from, self.to, reason)
+    }
+}
+
+/// What happens to the press count when the program becomes active again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PressReset {
+    /// Start from zero, allowing another full set of presses.
+    OnReactivate,
+    /// Keep counting, so the next press deactivates the program again.
+    Never,
+}
+
+/// When the program deactivates and comes back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ActivityConfig {
+    /// Presses before entering the inactive state.
+    pub allowed_presses: u8,
+    /// How long to stay inactive before returning to active.
+    pub inactive_ms: u32,
+    pub press_reset: PressReset,
+}
+
+impl ActivityConfig {
+    pub const DEFAULT: Self = Self {
+        allowed_presses: BlinkControl::ALLOWED_PRESSES,
+        inactive_ms: 10_000,
+        press_reset: PressReset::OnReactivate,
+    };
+}
+
+impl Default for ActivityConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Blink rate, press count and state, updated on each button press.
+pub struct BlinkControl {
+    /// Time between LED toggles.
+    pub toggle_delay_ms: u32,
+    /// Count button presses.
+    pub num_button_presses: u8,
+    /// Current program state.
+    pub state: State,
+    pub config: ActivityConfig,
+}
+
+impl BlinkControl {
+    /// Time between toggles at startup.
+    pub const INITIAL_DELAY_MS: u32 = 350;
+    /// Amount the delay is reduced by on each press.
+    pub const DELAY_STEP_MS: u32 = 150;
+    /// When the delay drops below this it's reset to the initial value.
+    pub const MIN_DELAY_MS: u32 = 50;
+    /// Default # presses before entering inactive state.
+    pub const ALLOWED_PRESSES: u8 = 5;
+
+    pub const fn new() -> Self {
+        Self::with_config(ActivityConfig::DEFAULT)
+    }
+
+    pub const fn with_config(config: ActivityConfig) -> Self {
+        Self {
+            toggle_delay_ms: Self::INITIAL_DELAY_MS,
+            num_button_presses: 0,
+            state: State::Active,
+            config,
+        }
+    }
+
+    /// Handles a debounced button press.
+    ///
+    /// Returns `true` if the press was accepted, i.e. the program was active,
+    /// in which case the press is counted and the blink delay reduced.
+    pub fn on_press(&mut self) -> bool {
+        if self.state != State::Active {
+            return false;
+        }
+
+        self.num_button_presses = self.num_button_presses.wrapping_add(1);
+
+        // Reduce LED toggle delay on button press.
+        self.toggle_delay_ms = self.toggle_delay_ms.saturating_sub(Self::DELAY_STEP_MS);
+        // When delay reaches minimum reset to initial.
+        if self.toggle_delay_ms < Self::MIN_DELAY_MS {
+            self.toggle_delay_ms = Self::INITIAL_DELAY_MS;
+        }
+
+        true
+    }
+
+    /// Enters the inactive state once the allowed number of presses is used up.
+    ///
+    /// Returns the transition only on the call that makes it. The caller
+    /// should then start a timer for [`ActivityConfig::inactive_ms`] that
+    /// feeds [`Input::TimerExpired`] to [`handle`](Self::handle).
+    pub fn check_deactivate(&mut self) -> Option<Transition> {
+        if self.num_button_presses >= self.config.allowed_presses {
+            self.handle(Input::PressLimit)
+        } else {
+            None
+        }
+    }
+
+    /// Applies `input` according to [`next_state`], returning the
+    /// transition if the state changed.
+    pub fn handle(&mut self, input: Input) -> Option<Transition> {
+        let from = self.state;
+        let to = next_state(from, input)?;
+        if to == State::Active && self.config.press_reset == PressReset::OnReactivate {
+            self.num_button_presses = 0;
+        }
+        self.state = to;
+        Some(Transition { from, to, input })
+    }
+}
+
+impl Default for BlinkControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_cycles_through_rates() {
+        let mut control = BlinkControl::new();
+
+        assert!(control.on_press());
+        assert_eq!(control.toggle_delay_ms, 200);
+        assert!(control.on_press());
+        assert_eq!(control.toggle_delay_ms, 50);
+        assert!(control.on_press());
+        assert_eq!(control.toggle_delay_ms, BlinkControl::INITIAL_DELAY_MS);
+        assert_eq!(control.num_button_presses, 3);
+    }
+
+    #[test]
+    fn deactivates_after_allowed_presses() {
+        let mut control = BlinkControl::new();
+
+        for _ in 1..BlinkControl::ALLOWED_PRESSES {
+            control.on_press();
+            assert_eq!(control.check_deactivate(), None);
+        }
+
+        control.on_press();
+        assert_eq!(
+            control.check_deactivate(),
+            Some(Transition {
+                from: State::Active,
+                to: State::Inactive,
+                input: Input::PressLimit
+            })
+        );
+        assert_eq!(control.state, State::Inactive);
+
+        // Only reported once, and further presses are ignored.
+        assert_eq!(control.check_deactivate(), None);
+        assert!(!control.on_press());
+        assert_eq!(control.num_button_presses, BlinkControl::ALLOWED_PRESSES);
+    }
+
+    #[test]
+    fn transition_table() {
+        use Input::*;
+        use State::*;
+        assert_eq!(next_state(Active, PressLimit), Some(Inactive));
+        assert_eq!(next_state(Active, TimerExpired), None);
+        assert_eq!(next_state(Active, Activate), None);
+        assert_eq!(next_state(Inactive, PressLimit), None);
+        assert_eq!(next_state(Inactive, TimerExpired), Some(Active));
+        assert_eq!(next_state(Inactive, Activate), Some(Active));
+    }
+
+    #[test]
+    fn timer_reactivates_and_clears_presses() {
+        let mut control = BlinkControl::new();
+        for _ in 0..BlinkControl::ALLOWED_PRESSES {
+            control.on_press();
+        }
+        control.check_deactivate().unwrap();
+
+        let transition = control.handle(Input::TimerExpired).unwrap();
+        assert_eq!(
+            transition.to_string(),
+            "inactive -> active (inactivity period over)"
+        );
+        assert_eq!(control.num_button_presses, 0);
+        assert!(control.on_press());
+        assert_eq!(control.check_deactivate(), None);
+
+        // A late expiry after reactivating by command does nothing.
+        assert_eq!(control.handle(Input::TimerExpired), None);
+    }
+
+    #[test]
+    fn keeping_presses_deactivates_on_next_press() {
+        let mut control = BlinkControl::with_config(ActivityConfig {
+            allowed_presses: 2,
+            inactive_ms: 1000,
+            press_reset: PressReset::Never,
+        });
+        control.on_press();
+        control.on_press();
+        control.check_deactivate().unwrap();
+
+        control.handle(Input::Activate).unwrap();
+        assert_eq!(control.num_button_presses, 2);
+        assert!(control.on_press());
+        assert!(control.check_deactivate().is_some());
+    }
+}
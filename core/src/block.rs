@@ -0,0 +1,175 @@
+//! Blocks of samples from a circular DMA buffer.
+//!
+//! The DMA buffer holds two blocks of `FRAMES` frames, each frame one sample
+//! per channel in the order the ADC scans them. DMA fills the halves in turn
+//! without stopping, with an interrupt as each one completes, and
+//! [`HalfTracker`] works out from the interrupt flags which half is ready to
+//! read, and whether any were overwritten before the handler got to them.
+
+use core::fmt;
+
+/// `FRAMES` frames of `CHANNELS` samples, interleaved as the ADC scans them.
+pub type Block<const FRAMES: usize, const CHANNELS: usize> = [[u16; CHANNELS]; FRAMES];
+
+/// The circular DMA buffer: two blocks, filled in turn.
+pub type DmaBuffer<const FRAMES: usize, const CHANNELS: usize> = [Block<FRAMES, CHANNELS>; 2];
+
+/// One channel's samples from a block, oldest first.
+pub fn channel<const FRAMES: usize, const CHANNELS: usize>(
+    block: &Block<FRAMES, CHANNELS>,
+    channel: usize,
+) -> impl Iterator<Item = u16> + '_ {
+    block.iter().map(move |frame| frame[channel])
+}
+
+/// The mean of one channel's samples from a block, rounded down.
+pub fn channel_mean<const FRAMES: usize, const CHANNELS: usize>(
+    block: &Block<FRAMES, CHANNELS>,
+    channel: usize,
+) -> u16 {
+    let sum: u32 = self::channel(block, channel).map(u32::from).sum();
+    sum.checked_div(FRAMES as u32).unwrap_or(0) as u16
+}
+
+/// A half of the DMA buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Half {
+    First,
+    Second,
+}
+
+impl Half {
+    /// Index into a [`DmaBuffer`].
+    pub const fn index(self) -> usize {
+        match self {
+            Half::First => 0,
+            Half::Second => 1,
+        }
+    }
+
+    const fn other(self) -> Half {
+        match self {
+            Half::First => Half::Second,
+            Half::Second => Half::First,
+        }
+    }
+}
+
+impl fmt::Display for Half {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Half::First => "first",
+            Half::Second => "second",
+        })
+    }
+}
+
+/// Follows the DMA around the buffer from its half-transfer and
+/// transfer-complete flags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HalfTracker {
+    // The half the DMA should complete next.
+    next: Half,
+    lost: u32,
+}
+
+impl HalfTracker {
+    pub const fn new() -> Self {
+        Self {
+            next: Half::First,
+            lost: 0,
+        }
+    }
+
+    /// Takes the flags read, and cleared, in the DMA interrupt handler, and
+    /// returns the half that's ready to read, if any. It can be read until
+    /// the DMA completes the other half.
+    ///
+    /// Both flags set means the handler ran at least a whole half late: the
+    /// older of the two halves is already being overwritten, so it's counted
+    /// as lost and only the newer one returned. Whole laps missed on top of
+    /// that can't be told from the flags.
+    pub fn on_interrupt(&mut self, half_transfer: bool, transfer_complete: bool) -> Option<Half> {
+        let ready = match (half_transfer, transfer_complete) {
+            (false, false) => return None,
+            (true, true) => {
+                self.lost = self.lost.saturating_add(1);
+                self.next.other()
+            }
+            (true, false) => Half::First,
+            (false, true) => Half::Second,
+        };
+        self.next = ready.other();
+        Some(ready)
+    }
+
+    /// For when the DMA starts again from the first half. The lost count
+    /// carries on.
+    pub fn restart(&mut self) {
+        self.next = Half::First;
+    }
+
+    /// Blocks overwritten before they were read.
+    pub fn lost(&self) -> u32 {
+        self.lost
+    }
+}
+
+impl Default for HalfTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halves_alternate() {
+        let mut tracker = HalfTracker::new();
+        assert_eq!(tracker.on_interrupt(false, false), None);
+        for _ in 0..3 {
+            assert_eq!(tracker.on_interrupt(true, false), Some(Half::First));
+            assert_eq!(tracker.on_interrupt(false, true), Some(Half::Second));
+        }
+        assert_eq!(tracker.lost(), 0);
+        assert_eq!(Half::Second.index(), 1);
+    }
+
+    #[test]
+    fn late_handler_loses_the_older_half() {
+        let mut tracker = HalfTracker::new();
+
+        // Both halves completed since the last interrupt, so the DMA is
+        // writing the first one again.
+        assert_eq!(tracker.on_interrupt(true, true), Some(Half::Second));
+        assert_eq!(tracker.lost(), 1);
+        assert_eq!(tracker.on_interrupt(true, false), Some(Half::First));
+
+        // And the other way round.
+        assert_eq!(tracker.on_interrupt(true, true), Some(Half::First));
+        assert_eq!(tracker.lost(), 2);
+        assert_eq!(tracker.on_interrupt(false, true), Some(Half::Second));
+        assert_eq!(tracker.lost(), 2);
+
+        // Restarted part way through the second half.
+        assert_eq!(tracker.on_interrupt(true, false), Some(Half::First));
+        tracker.restart();
+        assert_eq!(tracker.on_interrupt(true, true), Some(Half::Second));
+        assert_eq!(tracker.lost(), 3);
+    }
+
+    #[test]
+    fn channels_are_deinterleaved() {
+        let block: Block<3, 2> = [[1, 10], [2, 20], [3, 30]];
+        assert!(channel(&block, 0).eq([1, 2, 3]));
+        assert!(channel(&block, 1).eq([10, 20, 30]));
+        assert_eq!(channel_mean(&block, 1), 20);
+        assert_eq!(channel_mean(&[[1], [2]], 0), 1);
+
+        let buffer: DmaBuffer<3, 2> = [block, [[0; 2]; 3]];
+        assert_eq!(core::mem::size_of_val(&buffer), 2 * 3 * 2 * 2);
+    }
+}
@@ -0,0 +1,426 @@
+//! Debounced pushbutton press and gesture detection.
+
+use debouncr::{Debouncer, DebouncerStateful, Edge, Repeat3, debounce_3, debounce_stateful_3};
+
+/// Detects presses of the B1 user button from raw pin samples.
+///
+/// Wraps a `debouncr` debouncer that requires three consecutive equal
+/// samples before it reports an edge.
+pub struct PressDetector {
+    debouncer: Debouncer<u8, Repeat3>,
+}
+
+impl PressDetector {
+    pub fn new() -> Self {
+        // Hiari: Initialize debouncer to false because button is
+        // active low. Chose 3 consecutive states based on testing.
+        Self {
+            debouncer: debounce_3(false),
+        }
+    }
+
+    /// Feeds one sample of the pin's `is_low()` state to the debouncer.
+    ///
+    /// Returns `true` when the debounced signal has a falling edge, which is
+    /// what we count as a button press.
+    pub fn update(&mut self, is_low: bool) -> bool {
+        self.debouncer.update(is_low) == Some(Edge::Falling)
+    }
+}
+
+impl Default for PressDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Something the user did with the button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ButtonEvent {
+    Press,
+    Release,
+    /// Held for [`GestureConfig::long_press_ms`]. Comes before the release.
+    LongPress,
+    /// Pressed again within [`GestureConfig::double_click_ms`] of a release.
+    /// Comes right after that second `Press`.
+    DoubleClick,
+    /// Still held after a long press, every
+    /// [`GestureConfig::repeat_interval_ms`].
+    Repeat,
+}
+
+/// Gesture timings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GestureConfig {
+    pub long_press_ms: u32,
+    pub double_click_ms: u32,
+    pub repeat_interval_ms: u32,
+}
+
+impl GestureConfig {
+    pub const DEFAULT: Self = Self {
+        long_press_ms: 800,
+        double_click_ms: 300,
+        repeat_interval_ms: 200,
+    };
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+// Events that haven't been taken with `next_event` yet.
+const EVENT_QUEUE_LEN: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum GestureState {
+    Released {
+        // When the last short press ended, while a double click is possible.
+        last_release_ms: Option<u32>,
+    },
+    Held {
+        since_ms: u32,
+        // When the next long press or repeat event is due.
+        next_event_ms: u32,
+        long_press: bool,
+        double_click: bool,
+    },
+}
+
+/// Turns raw button samples into [`ButtonEvent`]s.
+///
+/// Feed it a sample of the pin with the current time every few milliseconds
+/// with [`update`](Self::update), then take the events with
+/// [`next_event`](Self::next_event). Samples are debounced like
+/// [`PressDetector`]'s, but edges must alternate, so a bounce while the
+/// button is held or a glitch while it's released isn't reported. Time only
+/// comes from the caller.
+pub struct Gestures {
+    debouncer: DebouncerStateful<u8, Repeat3>,
+    config: GestureConfig,
+    state: GestureState,
+    events: [ButtonEvent; EVENT_QUEUE_LEN],
+    head: usize,
+    len: usize,
+}
+
+impl Gestures {
+    pub fn new(config: GestureConfig) -> Self {
+        Self {
+            debouncer: debounce_stateful_3(false),
+            config,
+            state: GestureState::Released {
+                last_release_ms: None,
+            },
+            events: [ButtonEvent::Press; EVENT_QUEUE_LEN],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Feeds one sample of whether the button is pressed, taken at `now_ms`.
+    pub fn update(&mut self, pressed: bool, now_ms: u32) {
+        match self.debouncer.update(pressed) {
+            Some(Edge::Rising) => self.on_press(now_ms),
+            Some(Edge::Falling) => self.on_release(now_ms),
+            None => self.on_hold(now_ms),
+        }
+    }
+
+    /// Takes the oldest event. Events are dropped if more than a few pile up.
+    pub fn next_event(&mut self) -> Option<ButtonEvent> {
+        if self.len == 0 {
+            return None;
+        }
+        let event = self.events[self.head];
+        self.head = (self.head + 1) % EVENT_QUEUE_LEN;
+        self.len -= 1;
+        Some(event)
+    }
+
+    /// Whether the debounced button is released and every recent sample
+    /// agrees, so the samples can stop until the pin changes again. A
+    /// pending double click only needs the next press.
+    pub fn is_idle(&self) -> bool {
+        matches!(self.state, GestureState::Released { .. }) && self.debouncer.is_low()
+    }
+
+    fn on_press(&mut self, now_ms: u32) {
+        self.emit(ButtonEvent::Press);
+        let double_click = matches!(
+            self.state,
+            GestureState::Released { last_release_ms: Some(released_ms) }
+                if now_ms.wrapping_sub(released_ms) <= self.config.double_click_ms
+        );
+        if double_click {
+            self.emit(ButtonEvent::DoubleClick);
+        }
+        self.state = GestureState::Held {
+            since_ms: now_ms,
+            next_event_ms: now_ms.wrapping_add(self.config.long_press_ms),
+            long_press: false,
+            double_click,
+        };
+    }
+
+    fn on_release(&mut self, now_ms: u32) {
+        self.emit(ButtonEvent::Release);
+        // Only a short, single click can start a double click, so a triple
+        // click is one double click.
+        let last_release_ms = match self.state {
+            GestureState::Held {
+                long_press: false,
+                double_click: false,
+                ..
+            } => Some(now_ms),
+            _ => None,
+        };
+        self.state = GestureState::Released { last_release_ms };
+    }
+
+    fn on_hold(&mut self, now_ms: u32) {
+        let GestureState::Held {
+            since_ms,
+            next_event_ms,
+            long_press,
+            double_click,
+        } = self.state
+        else {
+            return;
+        };
+        // Compare elapsed times so the clock can wrap.
+        if now_ms.wrapping_sub(since_ms) < next_event_ms.wrapping_sub(since_ms) {
+            return;
+        }
+        self.emit(if long_press {
+            ButtonEvent::Repeat
+        } else {
+            ButtonEvent::LongPress
+        });
+        self.state = GestureState::Held {
+            since_ms,
+            next_event_ms: next_event_ms.wrapping_add(self.config.repeat_interval_ms),
+            long_press: true,
+            double_click,
+        };
+    }
+
+    fn emit(&mut self, event: ButtonEvent) {
+        if self.len == EVENT_QUEUE_LEN {
+            return;
+        }
+        self.events[(self.head + self.len) % EVENT_QUEUE_LEN] = event;
+        self.len += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn press_needs_three_stable_samples() {
+        let mut detector = PressDetector::new();
+
+        // Settle at "low".
+        for _ in 0..3 {
+            assert!(!detector.update(true));
+        }
+
+        // A single glitch isn't a press.
+        assert!(!detector.update(false));
+        assert!(!detector.update(true));
+
+        // Three consecutive samples are.
+        assert!(!detector.update(false));
+        assert!(!detector.update(false));
+        assert!(detector.update(false));
+
+        // And it only fires once per edge.
+        assert!(!detector.update(false));
+    }
+
+    const TICK_MS: u32 = 5;
+
+    // Feeds `pressed` for `duration_ms` worth of ticks, collecting events.
+    fn hold(
+        gestures: &mut Gestures,
+        now_ms: &mut u32,
+        pressed: bool,
+        duration_ms: u32,
+    ) -> Vec<ButtonEvent> {
+        let mut events = Vec::new();
+        for _ in 0..duration_ms / TICK_MS {
+            *now_ms += TICK_MS;
+            gestures.update(pressed, *now_ms);
+            while let Some(event) = gestures.next_event() {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn short_click() {
+        let mut gestures = Gestures::new(GestureConfig::DEFAULT);
+        let mut now = 0;
+        assert_eq!(
+            hold(&mut gestures, &mut now, true, 100),
+            [ButtonEvent::Press]
+        );
+        assert!(!gestures.is_idle());
+        assert_eq!(
+            hold(&mut gestures, &mut now, false, 500),
+            [ButtonEvent::Release]
+        );
+        assert!(gestures.is_idle());
+    }
+
+    // Samples like the firmware's `Button`: only from the first edge until
+    // the gestures go idle, collecting events.
+    fn sample_until_idle(
+        gestures: &mut Gestures,
+        now_ms: &mut u32,
+        pressed: bool,
+        duration_ms: u32,
+    ) -> (Vec<ButtonEvent>, bool) {
+        let mut events = Vec::new();
+        let mut sampling = true;
+        for _ in 0..duration_ms / TICK_MS {
+            *now_ms += TICK_MS;
+            if sampling {
+                gestures.update(pressed, *now_ms);
+                sampling = !gestures.is_idle();
+            }
+            events.extend(core::iter::from_fn(|| gestures.next_event()));
+        }
+        (events, sampling)
+    }
+
+    #[test]
+    fn clean_edges_keep_sampling_until_released() {
+        let mut gestures = Gestures::new(GestureConfig::DEFAULT);
+        let mut now = 0;
+        assert!(gestures.is_idle());
+
+        // No bounce at all: the first pressed sample mustn't look idle.
+        assert_eq!(
+            sample_until_idle(&mut gestures, &mut now, true, 100),
+            (vec![ButtonEvent::Press], true)
+        );
+        assert_eq!(
+            sample_until_idle(&mut gestures, &mut now, false, 100),
+            (vec![ButtonEvent::Release], false)
+        );
+    }
+
+    #[test]
+    fn ignores_bounces() {
+        let mut gestures = Gestures::new(GestureConfig::DEFAULT);
+        let mut now = 0;
+        let mut events = Vec::new();
+        let mut feed = |samples: &[bool]| {
+            for &pressed in samples {
+                now += TICK_MS;
+                gestures.update(pressed, now);
+                events.extend(core::iter::from_fn(|| gestures.next_event()));
+            }
+        };
+        // Bounces on the way down.
+        feed(&[true, false, true, false, false, true, true, true, true]);
+        // A bounce while held, long enough to fill the window again.
+        feed(&[false, true, true, true, true]);
+        // Released, then a glitch long enough to fill the window.
+        feed(&[false, false, false, false, true, false, false, false]);
+        assert_eq!(events, [ButtonEvent::Press, ButtonEvent::Release]);
+    }
+
+    #[test]
+    fn long_press_then_repeats() {
+        let mut gestures = Gestures::new(GestureConfig::DEFAULT);
+        let mut now = 0;
+        // Debouncing delays the press by two ticks.
+        assert_eq!(
+            hold(&mut gestures, &mut now, true, 800),
+            [ButtonEvent::Press]
+        );
+        assert_eq!(
+            hold(&mut gestures, &mut now, true, 20),
+            [ButtonEvent::LongPress]
+        );
+        assert_eq!(
+            hold(&mut gestures, &mut now, true, 400),
+            [ButtonEvent::Repeat, ButtonEvent::Repeat]
+        );
+        assert_eq!(
+            hold(&mut gestures, &mut now, false, 50),
+            [ButtonEvent::Release]
+        );
+
+        // A long press doesn't count towards a double click.
+        assert_eq!(
+            hold(&mut gestures, &mut now, true, 50),
+            [ButtonEvent::Press]
+        );
+    }
+
+    #[test]
+    fn double_click() {
+        let mut gestures = Gestures::new(GestureConfig::DEFAULT);
+        let mut now = 0;
+        let mut events = Vec::new();
+        for _ in 0..3 {
+            events.extend(hold(&mut gestures, &mut now, true, 80));
+            events.extend(hold(&mut gestures, &mut now, false, 80));
+        }
+        use ButtonEvent::*;
+        // The third click is a new first click.
+        assert_eq!(
+            events,
+            [Press, Release, Press, DoubleClick, Release, Press, Release]
+        );
+    }
+
+    #[test]
+    fn slow_second_click_is_not_double() {
+        let mut gestures = Gestures::new(GestureConfig::DEFAULT);
+        let mut now = 0;
+        hold(&mut gestures, &mut now, true, 80);
+        hold(&mut gestures, &mut now, false, 400);
+        assert_eq!(
+            hold(&mut gestures, &mut now, true, 80),
+            [ButtonEvent::Press]
+        );
+    }
+
+    #[test]
+    fn clock_can_wrap() {
+        let mut gestures = Gestures::new(GestureConfig::DEFAULT);
+        let mut now = u32::MAX - 400;
+        let mut events = Vec::new();
+        for _ in 0..200 {
+            now = now.wrapping_add(TICK_MS);
+            gestures.update(true, now);
+            events.extend(core::iter::from_fn(|| gestures.next_event()));
+        }
+        assert_eq!(events, [ButtonEvent::Press, ButtonEvent::LongPress]);
+    }
+
+    #[test]
+    fn drops_events_nobody_takes() {
+        let mut gestures = Gestures::new(GestureConfig::DEFAULT);
+        let mut now = 0;
+        for _ in 0..5 {
+            for pressed in [true, true, true, false, false, false] {
+                now += TICK_MS;
+                gestures.update(pressed, now);
+            }
+        }
+        let events: Vec<_> = core::iter::from_fn(|| gestures.next_event()).collect();
+        assert_eq!(events.len(), EVENT_QUEUE_LEN);
+        assert_eq!(events[0], ButtonEvent::Press);
+    }
+}
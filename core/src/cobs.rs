@@ -0,0 +1,140 @@
+//! Consistent Overhead Byte Stuffing.
+//!
+//! COBS removes all zero bytes from a packet at the cost of at most one byte
+//! per 254, so that a zero can be used to mark the end of each packet. A
+//! receiver that loses bytes can resynchronize at the next zero.
+
+/// Upper bound on the encoded length of `len` bytes, not including the
+/// terminating zero.
+pub const fn max_encoded_len(len: usize) -> usize {
+    len + len / 254 + 1
+}
+
+/// Error returned when encoding or decoding COBS data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CobsError {
+    /// The output buffer is too small.
+    BufferTooSmall,
+    /// The input contains a zero byte or a code that runs past its end.
+    Malformed,
+}
+
+/// Encodes `data` into `out`, returning the encoded length.
+///
+/// The terminating zero is not written.
+pub fn encode(data: &[u8], out: &mut [u8]) -> Result<usize, CobsError> {
+    if out.len() < max_encoded_len(data.len()) {
+        return Err(CobsError::BufferTooSmall);
+    }
+
+    let mut code_idx = 0;
+    let mut out_idx = 1;
+    let mut code: u8 = 1;
+
+    for &byte in data {
+        if byte == 0 {
+            out[code_idx] = code;
+            code_idx = out_idx;
+            out_idx += 1;
+            code = 1;
+        } else {
+            out[out_idx] = byte;
+            out_idx += 1;
+            code += 1;
+            if code == 0xFF {
+                out[code_idx] = code;
+                code_idx = out_idx;
+                out_idx += 1;
+                code = 1;
+            }
+        }
+    }
+    out[code_idx] = code;
+
+    Ok(out_idx)
+}
+
+/// Decodes `data`, which must not include the terminating zero, into `out`,
+/// returning the decoded length.
+pub fn decode(data: &[u8], out: &mut [u8]) -> Result<usize, CobsError> {
+    let mut in_idx = 0;
+    let mut out_idx = 0;
+
+    while in_idx < data.len() {
+        let code = data[in_idx] as usize;
+        if code == 0 || in_idx + code > data.len() {
+            return Err(CobsError::Malformed);
+        }
+        in_idx += 1;
+
+        for _ in 1..code {
+            let byte = data[in_idx];
+            if byte == 0 {
+                return Err(CobsError::Malformed);
+            }
+            *out.get_mut(out_idx).ok_or(CobsError::BufferTooSmall)? = byte;
+            in_idx += 1;
+            out_idx += 1;
+        }
+
+        // A code below 0xFF stands for a zero, unless it ends the packet.
+        if code < 0xFF && in_idx < data.len() {
+            *out.get_mut(out_idx).ok_or(CobsError::BufferTooSmall)? = 0;
+            out_idx += 1;
+        }
+    }
+
+    Ok(out_idx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let mut encoded = vec![0; max_encoded_len(data.len())];
+        let len = encode(data, &mut encoded).unwrap();
+        encoded.truncate(len);
+        assert!(!encoded.contains(&0));
+
+        let mut decoded = vec![0; data.len()];
+        let len = decode(&encoded, &mut decoded).unwrap();
+        assert_eq!(&decoded[..len], data);
+
+        encoded
+    }
+
+    #[test]
+    fn encodes_reference_examples() {
+        // Examples from the Wikipedia article on COBS.
+        assert_eq!(round_trip(&[0x00]), [0x01, 0x01]);
+        assert_eq!(round_trip(&[0x00, 0x00]), [0x01, 0x01, 0x01]);
+        assert_eq!(
+            round_trip(&[0x11, 0x22, 0x00, 0x33]),
+            [0x03, 0x11, 0x22, 0x02, 0x33]
+        );
+        assert_eq!(
+            round_trip(&[0x11, 0x00, 0x00, 0x00]),
+            [0x02, 0x11, 0x01, 0x01, 0x01]
+        );
+    }
+
+    #[test]
+    fn handles_long_runs_without_zeros() {
+        let data: Vec<u8> = (1..=255).cycle().take(600).collect();
+        let encoded = round_trip(&data);
+        assert!(encoded.len() <= max_encoded_len(data.len()));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let mut out = [0; 8];
+        assert_eq!(decode(&[0x05, 0x11], &mut out), Err(CobsError::Malformed));
+        assert_eq!(decode(&[0x02, 0x00], &mut out), Err(CobsError::Malformed));
+        assert_eq!(
+            decode(&[0x03, 0x11, 0x22], &mut [0; 1]),
+            Err(CobsError::BufferTooSmall)
+        );
+    }
+}
@@ -0,0 +1,239 @@
+//! Command console for the UART example.
+//!
+//! Lines typed into the serial terminal are parsed into [`Command`]s, which
+//! are then applied to the example's [`BlinkControl`].
+
+use core::fmt;
+
+use crate::{
+    blink::{BlinkControl, Input, State, Transition},
+    datetime::DateTime,
+};
+
+/// A command typed at the console.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Command {
+    /// `rate <ms>`: set the time between LED toggles.
+    Rate(u32),
+    /// `reset`: restore the startup blink rate, press count and state.
+    Reset,
+    /// `status`: report the current settings.
+    Status,
+    /// `activate`: leave the inactive state, clearing the press count as
+    /// configured.
+    Activate,
+    /// `time [YYYY-MM-DD HH:MM:SS]`: report or set the RTC.
+    Time(Option<DateTime>),
+    /// `help`: list the commands.
+    Help,
+}
+
+/// Why a line couldn't be parsed as a command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CommandError {
+    Unknown,
+    MissingArgument,
+    InvalidArgument,
+    TooManyArguments,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            CommandError::Unknown => "unknown command; try 'help'",
+            CommandError::MissingArgument => "missing argument",
+            CommandError::InvalidArgument => "invalid argument",
+            CommandError::TooManyArguments => "too many arguments",
+        };
+        f.write_str(msg)
+    }
+}
+
+/// Shortest and longest toggle period accepted by `rate`.
+pub const RATE_RANGE_MS: core::ops::RangeInclusive<u32> = 10..=5000;
+
+/// Parses a line of input. Leading and trailing whitespace is ignored.
+pub fn parse(line: &str) -> Result<Command, CommandError> {
+    let mut words = line.split_ascii_whitespace();
+    let name = words.next().ok_or(CommandError::Unknown)?;
+
+    let command = match name {
+        "rate" => {
+            let ms = words.next().ok_or(CommandError::MissingArgument)?;
+            let ms: u32 = ms.parse().map_err(|_| CommandError::InvalidArgument)?;
+            if !RATE_RANGE_MS.contains(&ms) {
+                return Err(CommandError::InvalidArgument);
+            }
+            Command::Rate(ms)
+        }
+        "reset" => Command::Reset,
+        "status" => Command::Status,
+        "activate" => Command::Activate,
+        "time" => match words.next() {
+            None => Command::Time(None),
+            Some(date) => {
+                let time = match words.next() {
+                    Some(time) => DateTime::parse_parts(date, time),
+                    None => DateTime::parse(date),
+                };
+                Command::Time(Some(time.map_err(|_| CommandError::InvalidArgument)?))
+            }
+        },
+        "help" => Command::Help,
+        _ => return Err(CommandError::Unknown),
+    };
+
+    if words.next().is_some() {
+        return Err(CommandError::TooManyArguments);
+    }
+    Ok(command)
+}
+
+/// What to print in response to a command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reply {
+    Ok,
+    Status {
+        delay_ms: u32,
+        presses: u8,
+        state: State,
+    },
+    /// The command changed the program state.
+    Transition(Transition),
+    Help,
+}
+
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reply::Ok => f.write_str("ok"),
+            Reply::Status {
+                delay_ms,
+                presses,
+                state,
+            } => write!(f, "rate {} ms, {} presses, {}", delay_ms, presses, state),
+            Reply::Transition(transition) => write!(f, "state {}", transition),
+            Reply::Help => f.write_str(
+                "commands: rate <ms>, reset, status, activate, time [YYYY-MM-DD HH:MM:SS], help",
+            ),
+        }
+    }
+}
+
+/// Applies a command to the example's state. [`Command::Time`] is left to
+/// the caller, which owns the RTC.
+pub fn execute(command: Command, control: &mut BlinkControl) -> Reply {
+    match command {
+        Command::Rate(ms) => {
+            control.toggle_delay_ms = ms;
+            Reply::Ok
+        }
+        Command::Reset => {
+            let transition = control.handle(Input::Activate);
+            *control = BlinkControl::with_config(control.config);
+            transition.map_or(Reply::Ok, Reply::Transition)
+        }
+        Command::Status => Reply::Status {
+            delay_ms: control.toggle_delay_ms,
+            presses: control.num_button_presses,
+            state: control.state,
+        },
+        Command::Activate => control
+            .handle(Input::Activate)
+            .map_or(Reply::Ok, Reply::Transition),
+        Command::Time(_) => Reply::Ok,
+        Command::Help => Reply::Help,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse("rate 250"), Ok(Command::Rate(250)));
+        assert_eq!(parse("  status "), Ok(Command::Status));
+        assert_eq!(parse("reset"), Ok(Command::Reset));
+        assert_eq!(parse("activate"), Ok(Command::Activate));
+        assert_eq!(parse("help"), Ok(Command::Help));
+        assert_eq!(parse("time"), Ok(Command::Time(None)));
+
+        let time = DateTime::parse("2026-10-18 09:05:03").ok();
+        assert_eq!(parse("time 2026-10-18 09:05:03"), Ok(Command::Time(time)));
+        assert_eq!(parse("time 2026-10-18T09:05:03"), Ok(Command::Time(time)));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert_eq!(parse(""), Err(CommandError::Unknown));
+        assert_eq!(parse("blink"), Err(CommandError::Unknown));
+        assert_eq!(parse("rate"), Err(CommandError::MissingArgument));
+        assert_eq!(parse("rate fast"), Err(CommandError::InvalidArgument));
+        assert_eq!(parse("rate -5"), Err(CommandError::InvalidArgument));
+        assert_eq!(parse("rate 5"), Err(CommandError::InvalidArgument));
+        assert_eq!(parse("rate 5001"), Err(CommandError::InvalidArgument));
+        assert_eq!(parse("status now"), Err(CommandError::TooManyArguments));
+        assert_eq!(
+            parse("time 2026-02-30 00:00:00"),
+            Err(CommandError::InvalidArgument)
+        );
+        assert_eq!(
+            parse("time 2026-10-18 09:05:03 UTC"),
+            Err(CommandError::TooManyArguments)
+        );
+    }
+
+    #[test]
+    fn rate_and_status_round_trip() {
+        let mut control = BlinkControl::new();
+        assert_eq!(execute(Command::Rate(120), &mut control), Reply::Ok);
+        assert_eq!(
+            execute(Command::Status, &mut control),
+            Reply::Status {
+                delay_ms: 120,
+                presses: 0,
+                state: State::Active
+            }
+        );
+    }
+
+    #[test]
+    fn activate_and_reset_restore_state() {
+        let mut control = BlinkControl::new();
+        for _ in 0..BlinkControl::ALLOWED_PRESSES {
+            control.on_press();
+        }
+        assert!(control.check_deactivate().is_some());
+
+        assert!(matches!(
+            execute(Command::Activate, &mut control),
+            Reply::Transition(_)
+        ));
+        assert_eq!(control.state, State::Active);
+        assert_eq!(execute(Command::Activate, &mut control), Reply::Ok);
+        assert_eq!(control.check_deactivate(), None);
+        assert!(control.on_press());
+
+        execute(Command::Rate(10), &mut control);
+        execute(Command::Reset, &mut control);
+        assert_eq!(control.toggle_delay_ms, BlinkControl::INITIAL_DELAY_MS);
+        assert_eq!(control.num_button_presses, 0);
+    }
+
+    #[test]
+    fn formats_replies() {
+        let reply = Reply::Status {
+            delay_ms: 350,
+            presses: 5,
+            state: State::Inactive,
+        };
+        assert_eq!(reply.to_string(), "rate 350 ms, 5 presses, inactive");
+        assert_eq!(
+            CommandError::MissingArgument.to_string(),
+            "missing argument"
+        );
+    }
+}
@@ -0,0 +1,386 @@
+//! Calendar dates and times for the RTC.
+//!
+//! The RTC counts in BCD registers with a two-digit year, so [`DateTime`]
+//! converts to and from those registers, to and from Unix time for
+//! arithmetic, and to text for timestamps and the console's `time` command.
+//! Years are limited to 2000–2099, which is what the RTC's leap year rule
+//! gets right.
+
+use core::fmt;
+
+const MS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
+/// Why a date and time couldn't be parsed or stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DateTimeError {
+    /// Not `YYYY-MM-DD HH:MM:SS`.
+    Format,
+    /// A field is out of range, e.g. February 30th or a year before 2000.
+    OutOfRange,
+}
+
+impl fmt::Display for DateTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DateTimeError::Format => "expected YYYY-MM-DD HH:MM:SS",
+            DateTimeError::OutOfRange => "date or time out of range",
+        })
+    }
+}
+
+pub const fn is_leap_year(year: u16) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+/// Days in `month` (1–12) of `year`.
+pub const fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Milliseconds since midnight, formatted as `HH:MM:SS.mmm`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimeOfDay(pub u32);
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = self.0;
+        write!(
+            f,
+            "{:02}:{:02}:{:02}.{:03}",
+            ms / 3_600_000,
+            ms / 60_000 % 60,
+            ms / 1000 % 60,
+            ms % 1000
+        )
+    }
+}
+
+/// A date and time to the millisecond, with no time zone; the board keeps
+/// whatever the host sets, normally UTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub millis: u16,
+}
+
+impl DateTime {
+    /// What the RTC reads before it has been set.
+    pub const RTC_RESET: Self = Self {
+        year: 2000,
+        month: 1,
+        day: 1,
+        hour: 0,
+        minute: 0,
+        second: 0,
+        millis: 0,
+    };
+
+    /// Checks that every field is in range.
+    pub fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Result<Self, DateTimeError> {
+        let valid = (2000..=2099).contains(&year)
+            && (1..=12).contains(&month)
+            && (1..=days_in_month(year, month)).contains(&day)
+            && hour < 24
+            && minute < 60
+            && second < 60;
+        if !valid {
+            return Err(DateTimeError::OutOfRange);
+        }
+        Ok(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            millis: 0,
+        })
+    }
+
+    /// Parses `YYYY-MM-DD HH:MM:SS`, with a space or `T` in the middle and
+    /// optionally milliseconds after the seconds, as in the
+    /// [`Display`](fmt::Display) output.
+    pub fn parse(s: &str) -> Result<Self, DateTimeError> {
+        let (date, time) = s.split_once([' ', 'T']).ok_or(DateTimeError::Format)?;
+        Self::parse_parts(date, time)
+    }
+
+    /// Parses a `YYYY-MM-DD` date and a `HH:MM:SS` or `HH:MM:SS.mmm` time.
+    pub fn parse_parts(date: &str, time: &str) -> Result<Self, DateTimeError> {
+        let (time, millis) = match time.split_once('.') {
+            Some((time, millis)) => (time, number(millis, 3)?),
+            None => (time, 0),
+        };
+        let [year, month, day] = fields(date, b'-', [4, 2, 2])?;
+        let [hour, minute, second] = fields(time, b':', [2, 2, 2])?;
+        let mut datetime = Self::new(
+            year as u16,
+            month as u8,
+            day as u8,
+            hour as u8,
+            minute as u8,
+            second as u8,
+        )?;
+        datetime.millis = millis as u16;
+        Ok(datetime)
+    }
+
+    pub const fn time_of_day(&self) -> TimeOfDay {
+        TimeOfDay(
+            ((self.hour as u32 * 60 + self.minute as u32) * 60 + self.second as u32) * 1000
+                + self.millis as u32,
+        )
+    }
+
+    /// Day of the week, from 1 for Monday to 7 for Sunday, as the RTC
+    /// counts them.
+    pub fn weekday(&self) -> u8 {
+        // 1970-01-01 was a Thursday.
+        ((days_from_civil(self.year, self.month, self.day) + 3) % 7 + 1) as u8
+    }
+
+    /// Milliseconds since 1970-01-01 00:00:00.
+    pub fn to_unix_ms(&self) -> u64 {
+        days_from_civil(self.year, self.month, self.day) * MS_PER_DAY
+            + u64::from(self.time_of_day().0)
+    }
+
+    /// The inverse of [`to_unix_ms`](Self::to_unix_ms). Fails outside
+    /// 2000–2099.
+    pub fn from_unix_ms(ms: u64) -> Result<Self, DateTimeError> {
+        let (year, month, day) = civil_from_days(ms / MS_PER_DAY);
+        let ms = (ms % MS_PER_DAY) as u32;
+        let mut datetime = Self::new(
+            year,
+            month,
+            day,
+            (ms / 3_600_000) as u8,
+            (ms / 60_000 % 60) as u8,
+            (ms / 1000 % 60) as u8,
+        )?;
+        datetime.millis = (ms % 1000) as u16;
+        Ok(datetime)
+    }
+
+    /// This time plus `ms`.
+    pub fn add_ms(&self, ms: u64) -> Result<Self, DateTimeError> {
+        Self::from_unix_ms(self.to_unix_ms() + ms)
+    }
+
+    /// The RTC_TR and RTC_DR register values for this time, in 24 hour
+    /// format. The RTC has no register for milliseconds.
+    pub fn to_rtc(&self) -> Result<(u32, u32), DateTimeError> {
+        if !(2000..=2099).contains(&self.year) {
+            return Err(DateTimeError::OutOfRange);
+        }
+        let tr = (bcd(self.hour) << 16) | (bcd(self.minute) << 8) | bcd(self.second);
+        let dr = (bcd((self.year - 2000) as u8) << 16)
+            | (u32::from(self.weekday()) << 13)
+            | (bcd(self.month) << 8)
+            | bcd(self.day);
+        Ok((tr, dr))
+    }
+
+    /// Reads RTC_TR, RTC_DR and the RTC_SSR subsecond counter, which counts
+    /// down from the synchronous prescaler `prediv_s`.
+    pub fn from_rtc(tr: u32, dr: u32, ssr: u32, prediv_s: u32) -> Self {
+        Self {
+            year: 2000 + from_bcd(dr >> 16) as u16,
+            month: from_bcd((dr >> 8) & 0x1f),
+            day: from_bcd(dr & 0x3f),
+            hour: from_bcd((tr >> 16) & 0x3f),
+            minute: from_bcd((tr >> 8) & 0x7f),
+            second: from_bcd(tr & 0x7f),
+            millis: (prediv_s.saturating_sub(ssr) * 1000 / (prediv_s + 1)) as u16,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {}",
+            self.year,
+            self.month,
+            self.day,
+            self.time_of_day()
+        )
+    }
+}
+
+// Splits `s` at `sep` into exactly three numbers with the given digit counts.
+fn fields(s: &str, sep: u8, digits: [usize; 3]) -> Result<[u32; 3], DateTimeError> {
+    let mut values = [0; 3];
+    let mut parts = s.split(sep as char);
+    for (value, digits) in values.iter_mut().zip(digits) {
+        *value = number(parts.next().ok_or(DateTimeError::Format)?, digits)?;
+    }
+    if parts.next().is_some() {
+        return Err(DateTimeError::Format);
+    }
+    Ok(values)
+}
+
+// Parses exactly `digits` decimal digits.
+fn number(s: &str, digits: usize) -> Result<u32, DateTimeError> {
+    if s.len() != digits || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(DateTimeError::Format);
+    }
+    s.parse().map_err(|_| DateTimeError::Format)
+}
+
+const fn bcd(value: u8) -> u32 {
+    (((value / 10) << 4) | (value % 10)) as u32
+}
+
+const fn from_bcd(bits: u32) -> u8 {
+    (((bits >> 4) & 0xf) * 10 + (bits & 0xf)) as u8
+}
+
+// Days since 1970-01-01, from Howard Hinnant's `days_from_civil`, for years
+// from 1970 on.
+fn days_from_civil(year: u16, month: u8, day: u8) -> u64 {
+    let year = u64::from(year) - u64::from(month <= 2);
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let month = u64::from(month);
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + u64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// The inverse of `days_from_civil`.
+fn civil_from_days(days: u64) -> (u16, u8, u8) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year as u16, month as u8, day as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datetime(s: &str) -> DateTime {
+        DateTime::parse(s).unwrap()
+    }
+
+    #[test]
+    fn parses_and_formats() {
+        let time = datetime("2026-10-18 09:05:03");
+        assert_eq!(time.to_string(), "2026-10-18 09:05:03.000");
+        assert_eq!(datetime("2026-10-18T09:05:03"), time);
+
+        let time = datetime("2026-10-18 09:05:03.042");
+        assert_eq!(time.millis, 42);
+        assert_eq!(datetime(&time.to_string()), time);
+        assert_eq!(TimeOfDay(45_296_789).to_string(), "12:34:56.789");
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        use DateTimeError::*;
+        for (s, err) in [
+            ("2026-10-18", Format),
+            ("2026-1-18 09:05:03", Format),
+            ("2026-10-18 09:05", Format),
+            ("2026-10-18 09:05:03:01", Format),
+            ("2026-10-18 +9:05:03", Format),
+            ("2026-10-18 09:05:03.5", Format),
+            ("2026-13-01 00:00:00", OutOfRange),
+            ("2026-02-29 00:00:00", OutOfRange),
+            ("2026-10-18 24:00:00", OutOfRange),
+            ("1999-12-31 23:59:59", OutOfRange),
+            ("2100-01-01 00:00:00", OutOfRange),
+        ] {
+            assert_eq!(DateTime::parse(s), Err(err), "{s}");
+        }
+        assert!(DateTime::parse("2024-02-29 00:00:00").is_ok());
+    }
+
+    #[test]
+    fn leap_years() {
+        assert!(is_leap_year(2000));
+        assert!(is_leap_year(2024));
+        assert!(!is_leap_year(2026));
+        assert!(!is_leap_year(2100));
+        assert_eq!(days_in_month(2024, 2), 29);
+        assert_eq!(days_in_month(2026, 2), 28);
+        assert_eq!(days_in_month(2026, 4), 30);
+    }
+
+    #[test]
+    fn unix_time_round_trips() {
+        let time = datetime("2026-10-18 09:05:03");
+        assert_eq!(time.to_unix_ms(), 1_792_314_303_000);
+        assert_eq!(DateTime::from_unix_ms(1_792_314_303_000), Ok(time));
+        assert_eq!(DateTime::RTC_RESET.to_unix_ms(), 946_684_800_000);
+        assert_eq!(DateTime::from_unix_ms(0), Err(DateTimeError::OutOfRange));
+    }
+
+    #[test]
+    fn adds_across_month_and_year_ends() {
+        let time = datetime("2024-02-28 23:59:59");
+        assert_eq!(
+            time.add_ms(1500).unwrap().to_string(),
+            "2024-02-29 00:00:00.500"
+        );
+        let time = datetime("2099-12-31 23:59:59");
+        assert_eq!(time.add_ms(999).unwrap().second, 59);
+        assert_eq!(time.add_ms(1000), Err(DateTimeError::OutOfRange));
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(datetime("2000-01-01 00:00:00").weekday(), 6);
+        assert_eq!(datetime("2026-10-18 00:00:00").weekday(), 7);
+        assert_eq!(datetime("2026-10-19 00:00:00").weekday(), 1);
+    }
+
+    #[test]
+    fn rtc_registers_round_trip() {
+        let time = datetime("2026-10-18 21:05:39");
+        let (tr, dr) = time.to_rtc().unwrap();
+        assert_eq!(tr, 0x0021_0539);
+        // Sunday in the weekday bits.
+        assert_eq!(dr, 0x0026_f018);
+
+        // Halfway through the second with the LSE prescaler.
+        let read = DateTime::from_rtc(tr, dr, 127, 255);
+        assert_eq!(read.millis, 500);
+        assert_eq!(DateTime { millis: 0, ..read }, time);
+    }
+}
@@ -0,0 +1,353 @@
+//! Differential pair processing for the two mic channels.
+//!
+//! The mic's two outputs swing in opposite directions around their bias
+//! level, so the signal is their difference, and anything they have in
+//! common, like supply noise, cancels. That only works if each channel's DC
+//! offset is removed and their gains match, so [`Calibration`] holds both,
+//! and [`Calibrator`] measures them from live samples: offsets first, in
+//! quiet, then gains while a steady sound plays.
+//!
+//! Everything is integer arithmetic, with gains in fixed point.
+
+use core::fmt;
+
+/// Fractional bits in [`Calibration::gain`].
+pub const GAIN_SHIFT: u32 = 14;
+/// A gain of one.
+pub const UNITY_GAIN: i32 = 1 << GAIN_SHIFT;
+
+/// Per-channel corrections applied before taking the difference.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Calibration {
+    /// Each channel's DC level in ADC counts.
+    pub offset: [i32; 2],
+    /// Each channel's gain, where [`UNITY_GAIN`] is one.
+    pub gain: [i32; 2],
+}
+
+impl Calibration {
+    /// Offsets at mid-scale of a `bits`-bit ADC and equal gains, for before
+    /// the pair is calibrated.
+    pub const fn centred(bits: u32) -> Self {
+        let mid = 1 << (bits - 1);
+        Self {
+            offset: [mid, mid],
+            gain: [UNITY_GAIN, UNITY_GAIN],
+        }
+    }
+
+    /// The differential and common-mode signal for one frame. Products are
+    /// taken in 64 bits, so any gain is safe, and the results saturate.
+    pub fn apply(&self, frame: [u16; 2]) -> DiffSample {
+        let [a, b] = [0, 1].map(|i| {
+            let level = i64::from(frame[i]) - i64::from(self.offset[i]);
+            (level * i64::from(self.gain[i])) >> GAIN_SHIFT
+        });
+        let saturate = |value: i64| value.clamp(i32::MIN.into(), i32::MAX.into()) as i32;
+        DiffSample {
+            differential: saturate(a - b),
+            common_mode: saturate((a + b) / 2),
+        }
+    }
+}
+
+impl fmt::Display for Calibration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [g0, g1] = self
+            .gain
+            .map(|gain| i64::from(gain) * 1000 / i64::from(UNITY_GAIN));
+        write!(
+            f,
+            "offsets {}, {}; gains {}.{:03}, {}.{:03}",
+            self.offset[0],
+            self.offset[1],
+            g0 / 1000,
+            g0 % 1000,
+            g1 / 1000,
+            g1 % 1000
+        )
+    }
+}
+
+/// One frame of the pair, in ADC counts after calibration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DiffSample {
+    /// The first channel minus the second: the signal.
+    pub differential: i32,
+    /// The mean of the two channels, ideally zero. A large value means
+    /// interference, or a mic that's biased differently from its offsets.
+    pub common_mode: i32,
+}
+
+impl DiffSample {
+    /// Both values as `bits`-bit offset binary, clamped to fit, for sample
+    /// frames and WAV files. The difference of two `n`-bit channels needs
+    /// `n + 1` bits, plus some headroom for gains above one.
+    pub fn to_offset_binary(&self, bits: u32) -> [u16; 2] {
+        let mid = 1 << (bits - 1);
+        let max = (1 << bits) - 1;
+        [self.differential, self.common_mode]
+            .map(|value| value.saturating_add(mid).clamp(0, max) as u16)
+    }
+}
+
+/// What [`Calibrator`] is measuring.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CalibrationPhase {
+    /// Each channel's mean, which needs quiet.
+    Offsets,
+    /// Each channel's mean deviation from its offset, which needs a steady
+    /// sound.
+    Gains,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CalibrationError {
+    /// A channel barely moved while measuring gains, so they weren't
+    /// matched. Holds the new offsets with equal gains.
+    TooQuiet(Calibration),
+    /// One channel moved more than [`Calibrator::MAX_GAIN_RATIO`] times as
+    /// much as the other, e.g. because a mic is dead or disconnected, so
+    /// the gains weren't matched. Holds the new offsets with equal gains.
+    Mismatched(Calibration),
+}
+
+impl CalibrationError {
+    /// The offsets that were measured, with equal gains.
+    pub fn offsets(&self) -> Calibration {
+        match *self {
+            CalibrationError::TooQuiet(offsets) | CalibrationError::Mismatched(offsets) => offsets,
+        }
+    }
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::TooQuiet(_) => f.write_str("too quiet to match gains"),
+            CalibrationError::Mismatched(_) => f.write_str("channels too different to match gains"),
+        }
+    }
+}
+
+/// Measures a [`Calibration`] from a fixed number of frames per phase.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Calibrator {
+    frames: u32,
+    phase: CalibrationPhase,
+    count: u32,
+    sums: [u64; 2],
+    offset: [i32; 2],
+}
+
+impl Calibrator {
+    /// Mean deviation, in ADC counts, that each channel needs for its gain to
+    /// be measured.
+    pub const MIN_DEVIATION: u64 = 2;
+    /// Most one channel's mean deviation may be of the other's for their
+    /// gains to be matched. This keeps the gains between 0.625 and 2.5.
+    pub const MAX_GAIN_RATIO: u64 = 4;
+
+    /// Measures over `frames` frames, at least one, for each phase.
+    pub const fn new(frames: u32) -> Self {
+        Self {
+            frames: if frames == 0 { 1 } else { frames },
+            phase: CalibrationPhase::Offsets,
+            count: 0,
+            sums: [0; 2],
+            offset: [0; 2],
+        }
+    }
+
+    pub fn phase(&self) -> CalibrationPhase {
+        self.phase
+    }
+
+    /// Adds a frame, returning the result once both phases are done. Each
+    /// channel's gain is set so that its mean deviation becomes the mean of
+    /// the two.
+    pub fn push(&mut self, frame: [u16; 2]) -> Option<Result<Calibration, CalibrationError>> {
+        for (i, sum) in self.sums.iter_mut().enumerate() {
+            let sample = i32::from(frame[i]);
+            *sum += match self.phase {
+                CalibrationPhase::Offsets => sample as u64,
+                CalibrationPhase::Gains => sample.abs_diff(self.offset[i]) as u64,
+            };
+        }
+        self.count += 1;
+        if self.count < self.frames {
+            return None;
+        }
+
+        let frames = u64::from(self.frames);
+        let sums = core::mem::take(&mut self.sums);
+        self.count = 0;
+        match self.phase {
+            CalibrationPhase::Offsets => {
+                self.offset = sums.map(|sum| ((sum + frames / 2) / frames) as i32);
+                self.phase = CalibrationPhase::Gains;
+                None
+            }
+            CalibrationPhase::Gains => {
+                let calibration = Calibration {
+                    offset: self.offset,
+                    gain: [UNITY_GAIN; 2],
+                };
+                if sums.iter().any(|&sum| sum < Self::MIN_DEVIATION * frames) {
+                    return Some(Err(CalibrationError::TooQuiet(calibration)));
+                }
+                let [low, high] = [sums[0].min(sums[1]), sums[0].max(sums[1])];
+                if high > low * Self::MAX_GAIN_RATIO {
+                    return Some(Err(CalibrationError::Mismatched(calibration)));
+                }
+                let mean = (sums[0] + sums[1]) / 2;
+                Some(Ok(Calibration {
+                    gain: sums.map(|sum| (UNITY_GAIN as u64 * mean / sum) as i32),
+                    ..calibration
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A square wave of `amplitude` around `level`, inverted on the second
+    // channel like the mic's outputs.
+    fn tone(level: [u16; 2], amplitude: [u16; 2], n: usize) -> impl Iterator<Item = [u16; 2]> {
+        (0..n).map(move |i| match i % 2 {
+            0 => [level[0] + amplitude[0], level[1] - amplitude[1]],
+            _ => [level[0] - amplitude[0], level[1] + amplitude[1]],
+        })
+    }
+
+    #[test]
+    fn centred_pair_gives_difference_and_common_mode() {
+        let calibration = Calibration::centred(10);
+        assert_eq!(
+            calibration.apply([600, 424]),
+            DiffSample {
+                differential: 176,
+                common_mode: 0
+            }
+        );
+        assert_eq!(
+            calibration.apply([600, 600]),
+            DiffSample {
+                differential: 0,
+                common_mode: 88
+            }
+        );
+    }
+
+    #[test]
+    fn calibration_removes_offsets_and_matches_gains() {
+        let mut calibrator = Calibrator::new(100);
+        for frame in tone([500, 530], [0, 0], 100) {
+            assert_eq!(calibrator.push(frame), None);
+        }
+        assert_eq!(calibrator.phase(), CalibrationPhase::Gains);
+
+        // The second channel is half as loud.
+        let mut result = None;
+        for frame in tone([500, 530], [100, 50], 100) {
+            result = calibrator.push(frame);
+        }
+        let calibration = result.unwrap().unwrap();
+        assert_eq!(calibration.offset, [500, 530]);
+        assert_eq!(
+            calibration.to_string(),
+            "offsets 500, 530; gains 0.750, 1.500"
+        );
+
+        // Both halves of the pair now contribute equally.
+        assert_eq!(
+            calibration.apply([600, 480]),
+            DiffSample {
+                differential: 150,
+                common_mode: 0
+            }
+        );
+    }
+
+    #[test]
+    fn quiet_gain_phase_keeps_the_offsets() {
+        let mut calibrator = Calibrator::new(10);
+        let result = tone([512, 512], [0, 0], 19)
+            .chain(tone([520, 505], [1, 1], 1))
+            .find_map(|frame| calibrator.push(frame));
+        let expected = Calibration {
+            offset: [512, 512],
+            gain: [UNITY_GAIN; 2],
+        };
+        assert_eq!(result, Some(Err(CalibrationError::TooQuiet(expected))));
+    }
+
+    #[test]
+    fn lopsided_pair_keeps_the_offsets() {
+        // A dead mic on the first channel, with 2 counts of noise, and a loud
+        // one on the second, in 12-bit mode.
+        let mut calibrator = Calibrator::new(10);
+        let result = tone([2048, 2048], [0, 0], 10)
+            .chain(tone([2048, 2048], [2, 1500], 10))
+            .find_map(|frame| calibrator.push(frame));
+        let expected = Calibration {
+            offset: [2048, 2048],
+            gain: [UNITY_GAIN; 2],
+        };
+        assert_eq!(result, Some(Err(CalibrationError::Mismatched(expected))));
+        assert_eq!(result.unwrap().unwrap_err().offsets(), expected);
+
+        // Exactly the largest ratio is still matched.
+        let mut calibrator = Calibrator::new(10);
+        let result = tone([2048, 2048], [0, 0], 10)
+            .chain(tone([2048, 2048], [100, 400], 10))
+            .find_map(|frame| calibrator.push(frame));
+        assert_eq!(
+            result.unwrap().unwrap().to_string(),
+            "offsets 2048, 2048; gains 2.500, 0.625"
+        );
+
+        // Even wild gains set by hand saturate rather than overflowing.
+        let wild = Calibration {
+            offset: [i32::MIN, i32::MAX],
+            gain: [i32::MAX, i32::MAX],
+        };
+        assert_eq!(
+            wild.apply([0, 0]),
+            DiffSample {
+                differential: i32::MAX,
+                common_mode: 65535
+            }
+        );
+        assert_eq!(
+            wild.to_string(),
+            "offsets -2147483648, 2147483647; gains 131071.999, 131071.999"
+        );
+    }
+
+    #[test]
+    fn offset_binary_output_clamps() {
+        let sample = DiffSample {
+            differential: -300,
+            common_mode: 5000,
+        };
+        assert_eq!(sample.to_offset_binary(12), [1748, 4095]);
+        let sample = DiffSample {
+            differential: -3000,
+            common_mode: 0,
+        };
+        assert_eq!(sample.to_offset_binary(12), [0, 2048]);
+        let sample = DiffSample {
+            differential: i32::MAX,
+            common_mode: i32::MIN,
+        };
+        assert_eq!(sample.to_offset_binary(16), [65535, 0]);
+    }
+}
@@ -0,0 +1,197 @@
+//! Brightness curves for dimmable LEDs.
+//!
+//! Like [`pattern`](crate::pattern) for on/off LEDs, a [`Curve`]'s level is a
+//! pure function of time, and a [`Fader`] runs one curve per LED from a
+//! single tick. Levels are perceived brightness; pass them through
+//! [`gamma::duty`](crate::gamma::duty) to get PWM duty cycles.
+
+/// How an LED's brightness changes over time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Curve {
+    /// Steady brightness.
+    Level(u8),
+    /// Linear fade, then holding `to`.
+    Fade { from: u8, to: u8, duration_ms: u32 },
+    /// Endless fade between `min` and `max` and back every `period_ms`.
+    Breathe { min: u8, max: u8, period_ms: u32 },
+}
+
+impl Curve {
+    /// Brightness `t_ms` after the curve started.
+    pub fn level_at(&self, t_ms: u32) -> u8 {
+        match *self {
+            Curve::Level(level) => level,
+            Curve::Fade {
+                from,
+                to,
+                duration_ms,
+            } => {
+                if t_ms >= duration_ms {
+                    to
+                } else {
+                    lerp(from, to, t_ms, duration_ms)
+                }
+            }
+            Curve::Breathe {
+                min,
+                max,
+                period_ms,
+            } => {
+                let half_ms = period_ms / 2;
+                if half_ms == 0 {
+                    return max;
+                }
+                let t_ms = t_ms % (2 * half_ms);
+                if t_ms < half_ms {
+                    lerp(min, max, t_ms, half_ms)
+                } else {
+                    lerp(max, min, t_ms - half_ms, half_ms)
+                }
+            }
+        }
+    }
+}
+
+// `from` to `to`, `t` of the way through `duration`.
+fn lerp(from: u8, to: u8, t: u32, duration: u32) -> u8 {
+    let (from, to) = (from as i64, to as i64);
+    (from + (to - from) * t as i64 / duration as i64) as u8
+}
+
+#[derive(Clone, Copy)]
+struct Channel {
+    curve: Curve,
+    start_ms: u32,
+}
+
+/// Runs a brightness curve on each of `N` LEDs from a shared clock.
+pub struct Fader<const N: usize> {
+    channels: [Channel; N],
+    now_ms: u32,
+}
+
+impl<const N: usize> Fader<N> {
+    /// All LEDs off.
+    pub const fn new() -> Self {
+        Self {
+            channels: [Channel {
+                curve: Curve::Level(0),
+                start_ms: 0,
+            }; N],
+            now_ms: 0,
+        }
+    }
+
+    /// Starts `curve` on LED `led` from the current time.
+    pub fn set(&mut self, led: usize, curve: Curve) {
+        self.channels[led] = Channel {
+            curve,
+            start_ms: self.now_ms,
+        };
+    }
+
+    /// Fades LED `led` from its current brightness to `to`.
+    pub fn fade_to(&mut self, led: usize, to: u8, duration_ms: u32) {
+        let from = self.levels()[led];
+        self.set(
+            led,
+            Curve::Fade {
+                from,
+                to,
+                duration_ms,
+            },
+        );
+    }
+
+    /// Advances the clock by `elapsed_ms` and returns each LED's brightness.
+    pub fn advance(&mut self, elapsed_ms: u32) -> [u8; N] {
+        self.now_ms = self.now_ms.wrapping_add(elapsed_ms);
+        self.levels()
+    }
+
+    /// Each LED's brightness now.
+    pub fn levels(&self) -> [u8; N] {
+        self.channels.map(|channel| {
+            channel
+                .curve
+                .level_at(self.now_ms.wrapping_sub(channel.start_ms))
+        })
+    }
+}
+
+impl<const N: usize> Default for Fader<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fade_up_and_down() {
+        let up = Curve::Fade {
+            from: 0,
+            to: 200,
+            duration_ms: 100,
+        };
+        let levels: Vec<_> = (0..=150).step_by(25).map(|t| up.level_at(t)).collect();
+        assert_eq!(levels, [0, 50, 100, 150, 200, 200, 200]);
+
+        let down = Curve::Fade {
+            from: 255,
+            to: 55,
+            duration_ms: 100,
+        };
+        assert_eq!(down.level_at(50), 155);
+        assert_eq!(down.level_at(100), 55);
+    }
+
+    #[test]
+    fn breathing_is_a_triangle() {
+        let breathe = Curve::Breathe {
+            min: 10,
+            max: 110,
+            period_ms: 200,
+        };
+        let levels: Vec<_> = (0..=400).step_by(50).map(|t| breathe.level_at(t)).collect();
+        assert_eq!(levels, [10, 60, 110, 60, 10, 60, 110, 60, 10]);
+    }
+
+    #[test]
+    fn zero_durations() {
+        let fade = Curve::Fade {
+            from: 0,
+            to: 9,
+            duration_ms: 0,
+        };
+        assert_eq!(fade.level_at(0), 9);
+        let breathe = Curve::Breathe {
+            min: 0,
+            max: 9,
+            period_ms: 1,
+        };
+        assert_eq!(breathe.level_at(5), 9);
+    }
+
+    #[test]
+    fn fader_runs_leds_independently() {
+        let mut fader = Fader::<2>::new();
+        fader.set(0, Curve::Level(7));
+        fader.advance(100);
+        fader.fade_to(0, 107, 100);
+        fader.set(
+            1,
+            Curve::Breathe {
+                min: 0,
+                max: 100,
+                period_ms: 100,
+            },
+        );
+        assert_eq!(fader.levels(), [7, 0]);
+        assert_eq!(fader.advance(50), [57, 100]);
+        assert_eq!(fader.advance(50), [107, 0]);
+        assert_eq!(fader.advance(1000), [107, 0]);
+    }
+}
@@ -0,0 +1,287 @@
+//! Decoding Cortex-M4 fault status registers and recording crashes.
+//!
+//! The HardFault handler reads the System Control Block fault registers and
+//! the registers the core stacked on exception entry, and stores them in a
+//! [`CrashRecord`] that survives a reset. Everything here is plain data, so
+//! the decoding can be tested on the host.
+
+use core::fmt;
+
+use crate::crc::crc32;
+
+/// Registers pushed onto the stack by the core on exception entry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct StackedRegisters {
+    pub r0: u32,
+    pub r1: u32,
+    pub r2: u32,
+    pub r3: u32,
+    pub r12: u32,
+    pub lr: u32,
+    pub pc: u32,
+    pub xpsr: u32,
+}
+
+/// Contents of the SCB fault status and address registers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct FaultStatus {
+    /// Configurable Fault Status Register: MMFSR, BFSR and UFSR.
+    pub cfsr: u32,
+    /// HardFault Status Register.
+    pub hfsr: u32,
+    /// MemManage Fault Address Register.
+    pub mmfar: u32,
+    /// BusFault Address Register.
+    pub bfar: u32,
+}
+
+/// One cause of a fault, from a single status bit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FaultReason {
+    // MemManage faults, CFSR bits 0-7.
+    InstructionAccessViolation,
+    DataAccessViolation,
+    MemManageOnUnstacking,
+    MemManageOnStacking,
+    MemManageOnFpLazyStacking,
+    // Bus faults, CFSR bits 8-15.
+    InstructionBusError,
+    PreciseDataBusError,
+    ImpreciseDataBusError,
+    BusFaultOnUnstacking,
+    BusFaultOnStacking,
+    BusFaultOnFpLazyStacking,
+    // Usage faults, CFSR bits 16-31.
+    UndefinedInstruction,
+    InvalidState,
+    InvalidPcLoad,
+    NoCoprocessor,
+    UnalignedAccess,
+    DivideByZero,
+    // HardFault status.
+    VectorTableRead,
+    Forced,
+    DebugEvent,
+}
+
+impl FaultReason {
+    pub fn description(self) -> &'static str {
+        match self {
+            FaultReason::InstructionAccessViolation => "instruction access violation",
+            FaultReason::DataAccessViolation => "data access violation",
+            FaultReason::MemManageOnUnstacking => "MemManage fault on exception return",
+            FaultReason::MemManageOnStacking => "MemManage fault on exception entry",
+            FaultReason::MemManageOnFpLazyStacking => "MemManage fault on FP lazy stacking",
+            FaultReason::InstructionBusError => "bus error on instruction fetch",
+            FaultReason::PreciseDataBusError => "precise data bus error",
+            FaultReason::ImpreciseDataBusError => "imprecise data bus error",
+            FaultReason::BusFaultOnUnstacking => "bus fault on exception return",
+            FaultReason::BusFaultOnStacking => "bus fault on exception entry",
+            FaultReason::BusFaultOnFpLazyStacking => "bus fault on FP lazy stacking",
+            FaultReason::UndefinedInstruction => "undefined instruction",
+            FaultReason::InvalidState => "invalid EPSR state (e.g. Thumb bit clear)",
+            FaultReason::InvalidPcLoad => "invalid PC load on exception return",
+            FaultReason::NoCoprocessor => "coprocessor access with coprocessor disabled",
+            FaultReason::UnalignedAccess => "unaligned memory access",
+            FaultReason::DivideByZero => "divide by zero",
+            FaultReason::VectorTableRead => "bus fault reading the vector table",
+            FaultReason::Forced => "escalated from a configurable fault",
+            FaultReason::DebugEvent => "debug event",
+        }
+    }
+}
+
+impl fmt::Display for FaultReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+// CFSR bit positions.
+const CFSR_BITS: [(u32, FaultReason); 17] = [
+    (0, FaultReason::InstructionAccessViolation),
+    (1, FaultReason::DataAccessViolation),
+    (3, FaultReason::MemManageOnUnstacking),
+    (4, FaultReason::MemManageOnStacking),
+    (5, FaultReason::MemManageOnFpLazyStacking),
+    (8, FaultReason::InstructionBusError),
+    (9, FaultReason::PreciseDataBusError),
+    (10, FaultReason::ImpreciseDataBusError),
+    (11, FaultReason::BusFaultOnUnstacking),
+    (12, FaultReason::BusFaultOnStacking),
+    (13, FaultReason::BusFaultOnFpLazyStacking),
+    (16, FaultReason::UndefinedInstruction),
+    (17, FaultReason::InvalidState),
+    (18, FaultReason::InvalidPcLoad),
+    (19, FaultReason::NoCoprocessor),
+    (24, FaultReason::UnalignedAccess),
+    (25, FaultReason::DivideByZero),
+];
+
+// HFSR bit positions.
+const HFSR_BITS: [(u32, FaultReason); 3] = [
+    (1, FaultReason::VectorTableRead),
+    (30, FaultReason::Forced),
+    (31, FaultReason::DebugEvent),
+];
+
+const CFSR_MMARVALID: u32 = 1 << 7;
+const CFSR_BFARVALID: u32 = 1 << 15;
+
+impl FaultStatus {
+    /// Every reason flagged in CFSR and HFSR, configurable faults first.
+    pub fn reasons(&self) -> impl Iterator<Item = FaultReason> + '_ {
+        let cfsr = CFSR_BITS
+            .iter()
+            .filter(|(bit, _)| self.cfsr & (1 << bit) != 0);
+        let hfsr = HFSR_BITS
+            .iter()
+            .filter(|(bit, _)| self.hfsr & (1 << bit) != 0);
+        cfsr.chain(hfsr).map(|&(_, reason)| reason)
+    }
+
+    /// The faulting data address, if MMFAR holds one.
+    pub fn mem_manage_address(&self) -> Option<u32> {
+        (self.cfsr & CFSR_MMARVALID != 0).then_some(self.mmfar)
+    }
+
+    /// The faulting data address, if BFAR holds one.
+    pub fn bus_fault_address(&self) -> Option<u32> {
+        (self.cfsr & CFSR_BFARVALID != 0).then_some(self.bfar)
+    }
+}
+
+/// What we know about a HardFault, kept in RAM that isn't cleared on reset.
+///
+/// A magic number and CRC tell a record written by the fault handler apart
+/// from whatever the RAM held at power-on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct CrashRecord {
+    magic: u32,
+    pub registers: StackedRegisters,
+    pub status: FaultStatus,
+    crc: u32,
+}
+
+impl CrashRecord {
+    const MAGIC: u32 = 0xC4A5_4F17;
+
+    pub fn new(registers: StackedRegisters, status: FaultStatus) -> Self {
+        let mut record = Self {
+            magic: Self::MAGIC,
+            registers,
+            status,
+            crc: 0,
+        };
+        record.crc = record.checksum();
+        record
+    }
+
+    /// Whether this was written by [`CrashRecord::new`] and not corrupted since.
+    pub fn is_valid(&self) -> bool {
+        self.magic == Self::MAGIC && self.crc == self.checksum()
+    }
+
+    /// Makes the record invalid, so it's only reported once.
+    pub fn clear(&mut self) {
+        self.magic = 0;
+    }
+
+    fn checksum(&self) -> u32 {
+        let r = &self.registers;
+        let s = &self.status;
+        let words = [
+            self.magic, r.r0, r.r1, r.r2, r.r3, r.r12, r.lr, r.pc, r.xpsr, s.cfsr, s.hfsr, s.mmfar,
+            s.bfar,
+        ];
+        let mut bytes = [0u8; 4 * 13];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        crc32(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_forced_divide_by_zero() {
+        // A usage fault escalated to HardFault because it wasn't enabled.
+        let status = FaultStatus {
+            cfsr: 1 << 25,
+            hfsr: 1 << 30,
+            ..Default::default()
+        };
+        let reasons: Vec<_> = status.reasons().collect();
+        assert_eq!(reasons, [FaultReason::DivideByZero, FaultReason::Forced]);
+        assert_eq!(status.mem_manage_address(), None);
+        assert_eq!(status.bus_fault_address(), None);
+    }
+
+    #[test]
+    fn reports_valid_fault_addresses() {
+        // Precise data bus error with BFAR valid, as from reading 0xFFFF_FFF0.
+        let status = FaultStatus {
+            cfsr: 0x0000_8200,
+            hfsr: 0x4000_0000,
+            mmfar: 0xFFFF_FFF0,
+            bfar: 0xFFFF_FFF0,
+        };
+        let reasons: Vec<_> = status.reasons().collect();
+        assert_eq!(
+            reasons,
+            [FaultReason::PreciseDataBusError, FaultReason::Forced]
+        );
+        assert_eq!(status.bus_fault_address(), Some(0xFFFF_FFF0));
+        assert_eq!(status.mem_manage_address(), None);
+
+        // Data access violation with MMFAR valid.
+        let status = FaultStatus {
+            cfsr: 0x0000_0082,
+            mmfar: 0x2000_0000,
+            ..Default::default()
+        };
+        assert_eq!(
+            status.reasons().collect::<Vec<_>>(),
+            [FaultReason::DataAccessViolation]
+        );
+        assert_eq!(status.mem_manage_address(), Some(0x2000_0000));
+    }
+
+    #[test]
+    fn decodes_every_status_bit() {
+        let status = FaultStatus {
+            cfsr: 0xFFFF_FFFF,
+            hfsr: 0xFFFF_FFFF,
+            ..Default::default()
+        };
+        assert_eq!(status.reasons().count(), 20);
+    }
+
+    #[test]
+    fn crash_record_validation() {
+        let registers = StackedRegisters {
+            pc: 0x0800_1234,
+            lr: 0x0800_0F01,
+            ..Default::default()
+        };
+        let mut record = CrashRecord::new(registers, FaultStatus::default());
+        assert!(record.is_valid());
+
+        let mut corrupted = record;
+        corrupted.registers.pc ^= 1;
+        assert!(!corrupted.is_valid());
+
+        record.clear();
+        assert!(!record.is_valid());
+    }
+}
@@ -0,0 +1,308 @@
+//! FIR filters for sampled signals.
+//!
+//! [`Fir`] is a finite impulse response filter with `TAPS` coefficients: each
+//! output is the sum of the last `TAPS` inputs, each weighted by its
+//! coefficient. It comes in `f32`, as [`FirF32`], and in Q15 fixed point, as
+//! [`FirQ15`], for when floating point is too slow or the samples are already
+//! integers. Q15 values are `i16`s standing for -1 to just under 1.
+//!
+//! Samples go through either one at a time, in direct form, or a block at a
+//! time, e.g. one DMA block. Both keep the past inputs the next output needs,
+//! so a signal can be split into blocks anywhere, and the two can be mixed,
+//! without changing the output.
+
+/// The arithmetic a [`Fir`] needs from its sample type.
+pub trait Sample: Copy + Default {
+    /// The running sum of products.
+    type Acc: Copy;
+
+    fn zero() -> Self::Acc;
+    /// Adds `coeff * x` to `acc`.
+    fn mac(acc: Self::Acc, coeff: Self, x: Self) -> Self::Acc;
+    /// The output sample for a sum.
+    fn output(acc: Self::Acc) -> Self;
+}
+
+impl Sample for f32 {
+    type Acc = f32;
+
+    fn zero() -> f32 {
+        0.0
+    }
+
+    fn mac(acc: f32, coeff: f32, x: f32) -> f32 {
+        acc + coeff * x
+    }
+
+    fn output(acc: f32) -> f32 {
+        acc
+    }
+}
+
+/// Q15. Products are summed exactly in 64 bits, so only the output is
+/// rounded, to nearest, and it saturates rather than wrapping.
+impl Sample for i16 {
+    type Acc = i64;
+
+    fn zero() -> i64 {
+        0
+    }
+
+    fn mac(acc: i64, coeff: i16, x: i16) -> i64 {
+        acc + i64::from(coeff) * i64::from(x)
+    }
+
+    fn output(acc: i64) -> i16 {
+        ((acc + (1 << 14)) >> 15).clamp(i16::MIN.into(), i16::MAX.into()) as i16
+    }
+}
+
+/// An FIR filter with `TAPS` coefficients and the inputs it's seen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fir<T, const TAPS: usize> {
+    coeffs: [T; TAPS],
+    // Past inputs, newest first: `history[k]` is `k + 1` samples ago.
+    history: [T; TAPS],
+}
+
+/// An `f32` FIR filter.
+pub type FirF32<const TAPS: usize> = Fir<f32, TAPS>;
+/// A Q15 FIR filter.
+pub type FirQ15<const TAPS: usize> = Fir<i16, TAPS>;
+
+impl<T: Sample, const TAPS: usize> Fir<T, TAPS> {
+    /// A filter with no past inputs, as if it had only seen zeros.
+    pub fn new(coeffs: [T; TAPS]) -> Self {
+        Self {
+            coeffs,
+            history: [T::default(); TAPS],
+        }
+    }
+
+    pub fn coeffs(&self) -> &[T; TAPS] {
+        &self.coeffs
+    }
+
+    /// Forgets the past inputs.
+    pub fn reset(&mut self) {
+        self.history = [T::default(); TAPS];
+    }
+
+    /// Filters one sample, in direct form.
+    pub fn process(&mut self, x: T) -> T {
+        let mut acc = T::zero();
+        if let Some((&first, rest)) = self.coeffs.split_first() {
+            acc = T::mac(acc, first, x);
+            for (&coeff, &past) in rest.iter().zip(&self.history) {
+                acc = T::mac(acc, coeff, past);
+            }
+        }
+        if TAPS > 0 {
+            self.history.copy_within(..TAPS - 1, 1);
+            self.history[0] = x;
+        }
+        T::output(acc)
+    }
+
+    /// Filters `input` into `output`, which must be the same length. The
+    /// inputs are read where they are, so the past inputs are only moved
+    /// once per block rather than once per sample.
+    pub fn process_block(&mut self, input: &[T], output: &mut [T]) {
+        assert_eq!(input.len(), output.len());
+
+        for (n, y) in output.iter_mut().enumerate() {
+            let mut acc = T::zero();
+            for (k, &coeff) in self.coeffs.iter().enumerate() {
+                // Input `n - k`, from before this block if that's negative.
+                let x = match n.checked_sub(k) {
+                    Some(i) => input[i],
+                    None => self.history[k - n - 1],
+                };
+                acc = T::mac(acc, coeff, x);
+            }
+            *y = T::output(acc);
+        }
+
+        // Keep the newest inputs, newest first.
+        let len = input.len().min(TAPS);
+        self.history.copy_within(..TAPS - len, len);
+        for (past, &x) in self.history.iter_mut().zip(input.iter().rev()) {
+            *past = x;
+        }
+    }
+}
+
+/// `x`, from -1 up to 1, in Q15, rounded to nearest and saturated.
+pub const fn to_q15(x: f32) -> i16 {
+    let scaled = x * 32768.0;
+    // `as` rounds towards zero and saturates.
+    (if scaled < 0.0 {
+        scaled - 0.5
+    } else {
+        scaled + 0.5
+    }) as i16
+}
+
+pub const fn from_q15(x: i16) -> f32 {
+    x as f32 / 32768.0
+}
+
+/// Coefficients in Q15, e.g. for a [`FirQ15`] from an `f32` design.
+pub const fn coeffs_to_q15<const TAPS: usize>(coeffs: [f32; TAPS]) -> [i16; TAPS] {
+    let mut q15 = [0; TAPS];
+    let mut i = 0;
+    while i < TAPS {
+        q15[i] = to_q15(coeffs[i]);
+        i += 1;
+    }
+    q15
+}
+
+/// A `bits`-bit ADC count in Q15, with mid-scale as zero, for `bits` from 1
+/// to 15.
+pub const fn count_to_q15(count: u16, bits: u32) -> i16 {
+    ((count as i32 - (1 << (bits - 1))) << (16 - bits)) as i16
+}
+
+/// The inverse of [`count_to_q15`], rounded to nearest.
+pub const fn q15_to_count(x: i16, bits: u32) -> u16 {
+    let shift = 16 - bits;
+    let count = ((x as i32 + (1 << (shift - 1))) >> shift) + (1 << (bits - 1));
+    let max = (1 << bits) - 1;
+    (if count > max { max } else { count }) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reference vectors computed independently: the outputs are exact
+    // convolutions of these decimal inputs, worked out with rationals, and the
+    // Q15 outputs with exact integers and the same rounding.
+
+    // A 7-tap low pass: a Hamming windowed sinc cut off at a fifth of the
+    // sample rate, with unity gain at DC.
+    const COEFFS: [f32; 7] = [
+        -0.00545737,
+        0.03172097,
+        0.25497236,
+        0.43752807,
+        0.25497236,
+        0.03172097,
+        -0.00545737,
+    ];
+
+    // A tone at a twentieth of the sample rate plus a smaller one at
+    // Nyquist, which the low pass removes.
+    const INPUT: [f32; 24] = [
+        0.300000, -0.145492, 0.593893, 0.104508, 0.775528, 0.200000, 0.775528, 0.104508, 0.593893,
+        -0.145492, 0.300000, -0.454508, 0.006107, -0.704508, -0.175528, -0.800000, -0.175528,
+        -0.704508, 0.006107, -0.454508, 0.300000, -0.145492, 0.593893, 0.104508,
+    ];
+
+    const OUTPUT: [f32; 24] = [
+        -0.0016372, 0.0103103, 0.0686355, 0.1124305, 0.1633439, 0.2824204, 0.3907492, 0.4606194,
+        0.48313, 0.4606194, 0.3907492, 0.2849008, 0.1488932, 0.0005820, -0.1500572, -0.2837368,
+        -0.3919132, -0.4594554, -0.484294, -0.4594554, -0.3919132, -0.2837368, -0.1500572,
+        0.0005820,
+    ];
+
+    const COEFFS_Q15: [i16; 7] = [-179, 1039, 8355, 14337, 8355, 1039, -179];
+
+    const INPUT_Q15: [i16; 24] = [
+        9830, -4767, 19461, 3425, 25413, 6554, 25413, 3425, 19461, -4767, 9830, -14893, 200,
+        -23085, -5752, -26214, -5752, -23085, 200, -14893, 9830, -4767, 19461, 3425,
+    ];
+
+    const OUTPUT_Q15: [i16; 24] = [
+        -54, 338, 2249, 3684, 5353, 9254, 12804, 15094, 15831, 15094, 12804, 9336, 4879, 19, -4917,
+        -9297, -12842, -15055, -15869, -15055, -12842, -9297, -4917, 19,
+    ];
+
+    // Full scale steps, which overshoot and saturate.
+    const STEPS_Q15: [i16; 12] = [
+        32767, 32767, 32767, 32767, 32767, 32767, -32768, -32768, -32768, -32768, -32768, -32768,
+    ];
+    const STEPS_OUTPUT_Q15: [i16; 12] = [
+        -179, 860, 9215, 23551, 31906, 32767, 32767, 31046, 14336, -14337, -31047, -32768,
+    ];
+
+    // Filters `input` in blocks of the given lengths, then one at a time.
+    fn split<T: Sample, const TAPS: usize>(
+        filter: &mut Fir<T, TAPS>,
+        input: &[T],
+        blocks: &[usize],
+    ) -> Vec<T> {
+        let mut output = vec![T::default(); input.len()];
+        let mut start = 0;
+        for &len in blocks {
+            let end = start + len;
+            filter.process_block(&input[start..end], &mut output[start..end]);
+            start = end;
+        }
+        for (x, y) in input[start..].iter().zip(&mut output[start..]) {
+            *y = filter.process(*x);
+        }
+        output
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        for (n, (a, e)) in actual.iter().zip(expected).enumerate() {
+            assert!((a - e).abs() < 1e-6, "output {}: {} != {}", n, a, e);
+        }
+    }
+
+    #[test]
+    fn impulse_response_is_the_coefficients() {
+        let mut filter = FirF32::new(COEFFS);
+        let mut impulse = [0.0; 10];
+        impulse[0] = 1.0;
+        let response = split(&mut filter, &impulse, &[]);
+        assert_eq!(response[..7], COEFFS);
+        assert_eq!(response[7..], [0.0; 3]);
+    }
+
+    #[test]
+    fn f32_matches_the_reference() {
+        let direct = split(&mut FirF32::new(COEFFS), &INPUT, &[]);
+        assert_close(&direct, &OUTPUT);
+
+        // Blocks shorter and longer than the filter, then single samples.
+        let blocks = split(&mut FirF32::new(COEFFS), &INPUT, &[3, 8, 1, 9]);
+        assert_close(&blocks, &OUTPUT);
+        let whole = split(&mut FirF32::new(COEFFS), &INPUT, &[24]);
+        assert_close(&whole, &OUTPUT);
+    }
+
+    #[test]
+    fn q15_matches_the_reference_exactly() {
+        assert_eq!(coeffs_to_q15(COEFFS), COEFFS_Q15);
+        assert_eq!(INPUT.map(to_q15), INPUT_Q15);
+
+        let mut filter = FirQ15::new(COEFFS_Q15);
+        assert_eq!(split(&mut filter, &INPUT_Q15, &[]), OUTPUT_Q15);
+        let mut filter = FirQ15::new(COEFFS_Q15);
+        assert_eq!(split(&mut filter, &INPUT_Q15, &[8, 8, 2, 5]), OUTPUT_Q15);
+
+        // The state carries on from one signal into the next until reset.
+        assert_ne!(split(&mut filter, &STEPS_Q15, &[8]), STEPS_OUTPUT_Q15);
+        filter.reset();
+        assert_eq!(split(&mut filter, &STEPS_Q15, &[8]), STEPS_OUTPUT_Q15);
+    }
+
+    #[test]
+    fn q15_conversions() {
+        assert_eq!(to_q15(0.5), 16384);
+        assert_eq!(to_q15(-1.0), i16::MIN);
+        assert_eq!(to_q15(1.0), i16::MAX);
+        assert_eq!(from_q15(-16384), -0.5);
+
+        assert_eq!(count_to_q15(512, 10), 0);
+        assert_eq!(count_to_q15(0, 10), i16::MIN);
+        assert_eq!(count_to_q15(1023, 10), 511 << 6);
+        for count in [0, 1, 511, 512, 1023] {
+            assert_eq!(q15_to_count(count_to_q15(count, 10), 10), count);
+        }
+        assert_eq!(q15_to_count(i16::MAX, 12), 4095);
+    }
+}
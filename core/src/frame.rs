@@ -0,0 +1,366 @@
+//! Binary, framed and checksummed ADC sample protocol.
+//!
+//! Each frame carries one sample per channel and is laid out as
+//!
+//! ```text
+//! | seq: u16 | channels: u8 | time_ms: u32 | samples: [u16; channels] | crc: u16 |
+//! ```
+//!
+//! with all multi-byte fields little endian and the CRC-16 computed over
+//! everything before it. `time_ms` is the RTC time of day in milliseconds and
+//! is only there if the top bit of `channels`, [`TIMESTAMP_FLAG`], is set.
+//! The frame is then COBS encoded and terminated with a zero byte, so a
+//! receiver that drops or corrupts bytes loses at most the frames they were
+//! in, and can tell from the sequence number how many.
+
+use crate::{
+    cobs::{self, CobsError},
+    crc::crc16,
+};
+
+/// Most channels a single frame can carry.
+pub const MAX_CHANNELS: usize = 8;
+
+/// Set in the channels byte when the frame carries a timestamp.
+pub const TIMESTAMP_FLAG: u8 = 0x80;
+
+/// Bytes in a frame before COBS encoding.
+pub const fn raw_frame_len(channels: usize, timestamped: bool) -> usize {
+    2 + 1 + if timestamped { 4 } else { 0 } + 2 * channels + 2
+}
+
+// Largest frame before COBS encoding.
+const MAX_RAW_FRAME_LEN: usize = raw_frame_len(MAX_CHANNELS, true);
+
+/// Largest frame on the wire, including the terminating zero.
+pub const MAX_FRAME_LEN: usize = cobs::max_encoded_len(MAX_RAW_FRAME_LEN) + 1;
+
+/// Error produced when encoding or decoding a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameError {
+    /// More than [`MAX_CHANNELS`] samples were given.
+    TooManyChannels,
+    /// The output buffer can't hold the encoded frame.
+    BufferTooSmall,
+    /// Bytes between delimiters weren't valid COBS data.
+    Cobs,
+    /// The decoded length doesn't match the channel count.
+    Length,
+    /// The checksum doesn't match.
+    Crc,
+    /// More bytes than [`MAX_FRAME_LEN`] arrived without a delimiter.
+    Overflow,
+}
+
+impl From<CobsError> for FrameError {
+    fn from(err: CobsError) -> Self {
+        match err {
+            CobsError::BufferTooSmall => FrameError::BufferTooSmall,
+            CobsError::Malformed => FrameError::Cobs,
+        }
+    }
+}
+
+/// One decoded frame of samples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SampleFrame {
+    pub seq: u16,
+    /// When the samples were taken, as RTC milliseconds since midnight.
+    pub time_ms: Option<u32>,
+    channels: u8,
+    samples: [u16; MAX_CHANNELS],
+}
+
+impl SampleFrame {
+    /// Panics if there are more than [`MAX_CHANNELS`] samples.
+    pub fn new(seq: u16, samples: &[u16]) -> Self {
+        assert!(samples.len() <= MAX_CHANNELS);
+        let mut frame = Self {
+            seq,
+            time_ms: None,
+            channels: samples.len() as u8,
+            samples: [0; MAX_CHANNELS],
+        };
+        frame.samples[..samples.len()].copy_from_slice(samples);
+        frame
+    }
+
+    /// One sample per channel.
+    pub fn samples(&self) -> &[u16] {
+        &self.samples[..self.channels as usize]
+    }
+}
+
+/// Encodes a frame into `out`, including the terminating zero, and returns
+/// the number of bytes written.
+pub fn encode_frame(
+    seq: u16,
+    time_ms: Option<u32>,
+    samples: &[u16],
+    out: &mut [u8],
+) -> Result<usize, FrameError> {
+    if samples.len() > MAX_CHANNELS {
+        return Err(FrameError::TooManyChannels);
+    }
+
+    let mut raw = [0u8; MAX_RAW_FRAME_LEN];
+    raw[0..2].copy_from_slice(&seq.to_le_bytes());
+    raw[2] = samples.len() as u8;
+    let mut len = 3;
+    if let Some(time_ms) = time_ms {
+        raw[2] |= TIMESTAMP_FLAG;
+        raw[3..7].copy_from_slice(&time_ms.to_le_bytes());
+        len += 4;
+    }
+    for sample in samples {
+        raw[len..len + 2].copy_from_slice(&sample.to_le_bytes());
+        len += 2;
+    }
+    let crc = crc16(&raw[..len]);
+    raw[len..len + 2].copy_from_slice(&crc.to_le_bytes());
+    len += 2;
+
+    let encoded = cobs::encode(&raw[..len], out)?;
+    *out.get_mut(encoded).ok_or(FrameError::BufferTooSmall)? = 0;
+    Ok(encoded + 1)
+}
+
+/// Decodes one frame, given the bytes between two delimiters.
+pub fn decode_frame(data: &[u8]) -> Result<SampleFrame, FrameError> {
+    let mut raw = [0u8; MAX_RAW_FRAME_LEN];
+    let len = cobs::decode(data, &mut raw).map_err(|_| FrameError::Cobs)?;
+    if len < raw_frame_len(0, false) {
+        return Err(FrameError::Length);
+    }
+
+    let timestamped = raw[2] & TIMESTAMP_FLAG != 0;
+    let channels = (raw[2] & !TIMESTAMP_FLAG) as usize;
+    if channels > MAX_CHANNELS || len != raw_frame_len(channels, timestamped) {
+        return Err(FrameError::Length);
+    }
+
+    let crc = u16::from_le_bytes([raw[len - 2], raw[len - 1]]);
+    if crc16(&raw[..len - 2]) != crc {
+        return Err(FrameError::Crc);
+    }
+
+    let time_ms = timestamped.then(|| u32::from_le_bytes([raw[3], raw[4], raw[5], raw[6]]));
+    let start = raw_frame_len(0, timestamped) - 2;
+    let mut samples = [0u16; MAX_CHANNELS];
+    for (i, sample) in samples.iter_mut().take(channels).enumerate() {
+        *sample = u16::from_le_bytes([raw[start + 2 * i], raw[start + 1 + 2 * i]]);
+    }
+
+    Ok(SampleFrame {
+        seq: u16::from_le_bytes([raw[0], raw[1]]),
+        time_ms,
+        channels: channels as u8,
+        samples,
+    })
+}
+
+/// Numbers outgoing frames.
+#[derive(Default)]
+pub struct FrameEncoder {
+    seq: u16,
+}
+
+impl FrameEncoder {
+    pub const fn new() -> Self {
+        Self { seq: 0 }
+    }
+
+    /// Encodes the next frame into `out`, with a timestamp if given, and
+    /// returns the bytes to send.
+    pub fn encode<'a>(
+        &mut self,
+        time_ms: Option<u32>,
+        samples: &[u16],
+        out: &'a mut [u8; MAX_FRAME_LEN],
+    ) -> Result<&'a [u8], FrameError> {
+        let len = encode_frame(self.seq, time_ms, samples, out)?;
+        self.seq = self.seq.wrapping_add(1);
+        Ok(&out[..len])
+    }
+}
+
+/// A frame received by [`FrameDecoder`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Received {
+    pub frame: SampleFrame,
+    /// Frames missing between the previous good frame and this one.
+    pub dropped: u16,
+}
+
+/// Reassembles frames from a byte stream.
+pub struct FrameDecoder {
+    buf: [u8; MAX_FRAME_LEN],
+    len: usize,
+    overflow: bool,
+    next_seq: Option<u16>,
+}
+
+impl FrameDecoder {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_FRAME_LEN],
+            len: 0,
+            overflow: false,
+            next_seq: None,
+        }
+    }
+
+    /// Feeds one received byte to the decoder.
+    ///
+    /// Returns `None` until a delimiter arrives, then the decoded frame or the
+    /// reason the bytes since the previous delimiter were discarded.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Received, FrameError>> {
+        if byte != 0 {
+            if self.len < self.buf.len() {
+                self.buf[self.len] = byte;
+                self.len += 1;
+            } else {
+                self.overflow = true;
+            }
+            return None;
+        }
+
+        let len = core::mem::take(&mut self.len);
+        if core::mem::take(&mut self.overflow) {
+            return Some(Err(FrameError::Overflow));
+        }
+        // Back-to-back delimiters, e.g. when starting mid-stream.
+        if len == 0 {
+            return None;
+        }
+
+        Some(decode_frame(&self.buf[..len]).map(|frame| {
+            let dropped = match self.next_seq {
+                Some(expected) => frame.seq.wrapping_sub(expected),
+                None => 0,
+            };
+            self.next_seq = Some(frame.seq.wrapping_add(1));
+            Received { frame, dropped }
+        }))
+    }
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(decoder: &mut FrameDecoder, bytes: &[u8]) -> Vec<Result<Received, FrameError>> {
+        bytes.iter().filter_map(|&b| decoder.push(b)).collect()
+    }
+
+    #[test]
+    fn round_trips_samples() {
+        let mut encoder = FrameEncoder::new();
+        let mut decoder = FrameDecoder::new();
+        let mut out = [0; MAX_FRAME_LEN];
+
+        for i in 0..3u16 {
+            // Include zero bytes in the payload.
+            let samples = [i * 256, 1023 - i];
+            let bytes = encoder.encode(None, &samples, &mut out).unwrap();
+            assert_eq!(bytes.iter().filter(|&&b| b == 0).count(), 1);
+
+            let received = decode_all(&mut decoder, bytes);
+            let received = received[0].unwrap();
+            assert_eq!(received.frame.seq, i);
+            assert_eq!(received.frame.samples(), samples);
+            assert_eq!(received.dropped, 0);
+        }
+    }
+
+    #[test]
+    fn two_channel_frame_is_compact() {
+        let mut out = [0; MAX_FRAME_LEN];
+        let len = encode_frame(0x0102, None, &[0x0304, 0x0506], &mut out).unwrap();
+        // 9 raw bytes, one COBS code byte and the delimiter.
+        assert_eq!(len, raw_frame_len(2, false) + 2);
+    }
+
+    #[test]
+    fn carries_optional_timestamp() {
+        let mut decoder = FrameDecoder::new();
+        let mut out = [0; MAX_FRAME_LEN];
+
+        let len = encode_frame(3, Some(45_296_789), &[1, 2], &mut out).unwrap();
+        let frame = decode_all(&mut decoder, &out[..len])[0].unwrap().frame;
+        assert_eq!(frame.time_ms, Some(45_296_789));
+        assert_eq!(frame.samples(), [1, 2]);
+
+        let len = encode_frame(4, None, &[1, 2], &mut out).unwrap();
+        let frame = decode_all(&mut decoder, &out[..len])[0].unwrap().frame;
+        assert_eq!(frame.time_ms, None);
+    }
+
+    #[test]
+    fn reports_dropped_frames() {
+        let mut decoder = FrameDecoder::new();
+        let mut out = [0; MAX_FRAME_LEN];
+
+        let len = encode_frame(7, None, &[1, 2], &mut out).unwrap();
+        assert_eq!(decode_all(&mut decoder, &out[..len])[0].unwrap().dropped, 0);
+
+        let len = encode_frame(10, None, &[1, 2], &mut out).unwrap();
+        assert_eq!(decode_all(&mut decoder, &out[..len])[0].unwrap().dropped, 2);
+
+        // Sequence numbers wrap.
+        let len = encode_frame(0, None, &[1, 2], &mut out).unwrap();
+        assert_eq!(
+            decode_all(&mut decoder, &out[..len])[0].unwrap().dropped,
+            u16::MAX - 10
+        );
+    }
+
+    #[test]
+    fn detects_corruption_and_resynchronizes() {
+        let mut decoder = FrameDecoder::new();
+        let mut stream = Vec::new();
+        let mut out = [0; MAX_FRAME_LEN];
+
+        let len = encode_frame(0, None, &[100, 200], &mut out).unwrap();
+        let mut corrupted = out[..len].to_vec();
+        corrupted[4] ^= 0x40;
+        stream.extend_from_slice(&corrupted);
+
+        // A partial frame, as if bytes were lost.
+        let len = encode_frame(1, None, &[100, 200], &mut out).unwrap();
+        stream.extend_from_slice(&out[3..len]);
+
+        let len = encode_frame(2, None, &[300, 400], &mut out).unwrap();
+        stream.extend_from_slice(&out[..len]);
+
+        let results = decode_all(&mut decoder, &stream);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0], Err(FrameError::Crc));
+        assert!(results[1].is_err());
+        assert_eq!(results[2].unwrap().frame.samples(), [300, 400]);
+    }
+
+    #[test]
+    fn rejects_overlong_input() {
+        let mut decoder = FrameDecoder::new();
+        let stream = [0x55; MAX_FRAME_LEN + 4];
+        assert!(decode_all(&mut decoder, &stream).is_empty());
+        assert_eq!(decoder.push(0), Some(Err(FrameError::Overflow)));
+    }
+
+    #[test]
+    fn rejects_too_many_channels() {
+        let mut out = [0; MAX_FRAME_LEN];
+        assert_eq!(
+            encode_frame(0, None, &[0; MAX_CHANNELS + 1], &mut out),
+            Err(FrameError::TooManyChannels)
+        );
+    }
+}
@@ -0,0 +1,35 @@
+//! Gamma correction for LED brightness.
+//!
+//! The lookup table is generated by `build.rs`, so changing the curve
+//! doesn't mean pasting in 256 new numbers.
+
+include!(concat!(env!("OUT_DIR"), "/gamma.rs"));
+
+/// PWM duty for a perceived brightness `level`, scaled to `max_duty`.
+pub fn duty(level: u8, max_duty: u16) -> u16 {
+    (GAMMA_TABLE[level as usize] as u32 * max_duty as u32 / u16::MAX as u32) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_endpoints_and_shape() {
+        assert_eq!(GAMMA_TABLE[0], 0);
+        assert_eq!(GAMMA_TABLE[255], u16::MAX);
+        assert!(GAMMA_TABLE.windows(2).all(|w| w[0] <= w[1]));
+        // Half brightness needs much less than half the duty.
+        let expected = 0.5f32.powf(GAMMA) * u16::MAX as f32;
+        let mid = GAMMA_TABLE[128] as f32;
+        assert!((mid - expected).abs() / expected < 0.05);
+    }
+
+    #[test]
+    fn scales_to_timer_range() {
+        assert_eq!(duty(0, 1000), 0);
+        assert_eq!(duty(255, 1000), 1000);
+        assert!(duty(128, 1000) < 200);
+        assert!(duty(1, 1000) <= 1);
+    }
+}
@@ -0,0 +1,33 @@
+//! Hardware-independent logic used by the STM32F4DISCOVERY binaries.
+//!
+//! Nothing in here touches registers, so it compiles for the board and for the
+//! host alike. Run the unit tests from this directory with `cargo test`.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod acquisition;
+pub mod adc_calibration;
+pub mod blink;
+pub mod block;
+pub mod button;
+pub mod cobs;
+pub mod command;
+pub mod crc;
+pub mod datetime;
+pub mod differential;
+pub mod fade;
+pub mod fault;
+pub mod fir;
+pub mod frame;
+pub mod gamma;
+pub mod line;
+pub mod morse;
+pub mod panic_record;
+pub mod pattern;
+pub mod power;
+pub mod profile;
+pub mod reset;
+pub mod ring;
+pub mod sample;
+pub mod schedule;
+pub mod supervisor;
@@ -0,0 +1,125 @@
+//! Assembling lines of text typed into a serial terminal.
+
+/// Error produced when a line can't be collected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LineError {
+    /// The line didn't fit in the buffer and was discarded.
+    TooLong,
+}
+
+/// A complete line of printable ASCII, without its line ending.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Line<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Line<N> {
+    pub fn as_str(&self) -> &str {
+        // Only printable ASCII is ever stored.
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or_default()
+    }
+}
+
+/// Collects bytes into lines of up to `N` characters.
+///
+/// Either `\r` or `\n` ends a line, so terminals sending `\r`, `\n` or `\r\n`
+/// all work, and empty lines are skipped. Backspace and delete remove the last
+/// character; other control characters and non-ASCII bytes are ignored.
+pub struct LineBuffer<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    overflow: bool,
+}
+
+impl<const N: usize> LineBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    /// Adds a received byte, returning the line it completes, if any.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Line<N>, LineError>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let len = core::mem::take(&mut self.len);
+                if core::mem::take(&mut self.overflow) {
+                    return Some(Err(LineError::TooLong));
+                }
+                (len > 0).then_some(Ok(Line { buf: self.buf, len }))
+            }
+            // Backspace and delete.
+            0x08 | 0x7F => {
+                self.len = self.len.saturating_sub(1);
+                None
+            }
+            b' '..=b'~' => {
+                if self.len < N {
+                    self.buf[self.len] = byte;
+                    self.len += 1;
+                } else {
+                    self.overflow = true;
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<const N: usize> Default for LineBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_all<const N: usize>(
+        buffer: &mut LineBuffer<N>,
+        bytes: &[u8],
+    ) -> Vec<Result<String, LineError>> {
+        bytes
+            .iter()
+            .filter_map(|&b| buffer.push(b))
+            .map(|r| r.map(|line| line.as_str().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn splits_on_any_line_ending() {
+        let mut buffer = LineBuffer::<16>::new();
+        assert_eq!(
+            push_all(&mut buffer, b"status\rrate 50\r\n\nreset\n"),
+            [
+                Ok("status".into()),
+                Ok("rate 50".into()),
+                Ok("reset".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn handles_backspace_and_control_characters() {
+        let mut buffer = LineBuffer::<16>::new();
+        assert_eq!(
+            push_all(&mut buffer, b"stax\x08tus\x1b\xff\r"),
+            [Ok("status".into())]
+        );
+    }
+
+    #[test]
+    fn discards_overlong_lines() {
+        let mut buffer = LineBuffer::<4>::new();
+        assert_eq!(
+            push_all(&mut buffer, b"toolong\rok\r"),
+            [Err(LineError::TooLong), Ok("ok".into())]
+        );
+    }
+}
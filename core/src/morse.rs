@@ -0,0 +1,129 @@
+//! Morse code as LED blink patterns.
+//!
+//! Uses the standard timing: a dot is one unit on and a dash three, with one
+//! unit off between the parts of a letter, three between letters and seven
+//! between words.
+
+use core::fmt;
+
+use crate::pattern::Step;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MorseError {
+    /// Only letters, digits and spaces are supported.
+    Unsupported(char),
+    /// The step buffer is too small for the text.
+    BufferTooSmall,
+}
+
+impl fmt::Display for MorseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MorseError::Unsupported(c) => write!(f, "no Morse code for {:?}", c),
+            MorseError::BufferTooSmall => f.write_str("too many Morse symbols for buffer"),
+        }
+    }
+}
+
+// Dots and dashes for A-Z, then 0-9.
+const LETTERS: [&str; 26] = [
+    ".-", "-...", "-.-.", "-..", ".", "..-.", "--.", "....", "..", ".---", "-.-", ".-..", "--",
+    "-.", "---", ".--.", "--.-", ".-.", "...", "-", "..-", "...-", ".--", "-..-", "-.--", "--..",
+];
+const DIGITS: [&str; 10] = [
+    "-----", ".----", "..---", "...--", "....-", ".....", "-....", "--...", "---..", "----.",
+];
+
+fn symbols(c: char) -> Option<&'static str> {
+    match c.to_ascii_uppercase() {
+        c @ 'A'..='Z' => Some(LETTERS[c as usize - 'A' as usize]),
+        c @ '0'..='9' => Some(DIGITS[c as usize - '0' as usize]),
+        _ => None,
+    }
+}
+
+/// Encodes `text` into `steps`, one step per dot or dash, and returns the
+/// steps used.
+///
+/// The pattern ends with a word gap, so it reads correctly when repeated.
+/// Case is ignored, and runs of spaces count as one.
+pub fn encode<'a>(
+    text: &str,
+    unit_ms: u32,
+    steps: &'a mut [Step],
+) -> Result<&'a [Step], MorseError> {
+    let mut len = 0;
+    for c in text.chars() {
+        if c == ' ' {
+            // Widen the gap after the last letter.
+            if let Some(last) = steps[..len].last_mut() {
+                last.off_ms = 7 * unit_ms;
+            }
+            continue;
+        }
+        let symbols = symbols(c).ok_or(MorseError::Unsupported(c))?;
+        if steps.len() - len < symbols.len() {
+            return Err(MorseError::BufferTooSmall);
+        }
+        for symbol in symbols.bytes() {
+            let on_ms = if symbol == b'.' { unit_ms } else { 3 * unit_ms };
+            steps[len] = Step::blink(on_ms, unit_ms, 1);
+            len += 1;
+        }
+        steps[len - 1].off_ms = 3 * unit_ms;
+    }
+    if let Some(last) = steps[..len].last_mut() {
+        last.off_ms = 7 * unit_ms;
+    }
+    Ok(&steps[..len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::Pattern;
+
+    // Samples a pattern once per unit as a string of '#' (on) and '.'.
+    fn timeline(steps: &[Step], unit_ms: u32) -> String {
+        let pattern = Pattern::new(steps);
+        (0..pattern.period_ms() / unit_ms)
+            .map(|i| {
+                if pattern.is_on_at(i * unit_ms) {
+                    '#'
+                } else {
+                    '.'
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sos() {
+        let mut buf = [Step::off(0); 16];
+        let steps = encode("SOS", 100, &mut buf).unwrap();
+        assert_eq!(steps.len(), 9);
+        assert_eq!(timeline(steps, 100), "#.#.#...###.###.###...#.#.#.......");
+    }
+
+    #[test]
+    fn word_gaps_and_case() {
+        let mut buf = [Step::off(0); 16];
+        let steps = encode("  e  t1 ", 10, &mut buf).unwrap();
+        assert_eq!(
+            timeline(steps, 10),
+            "#.......###...#.###.###.###.###......."
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut buf = [Step::off(0); 4];
+        assert_eq!(
+            encode("a?", 10, &mut buf),
+            Err(MorseError::Unsupported('?'))
+        );
+        assert_eq!(encode("0", 10, &mut buf), Err(MorseError::BufferTooSmall));
+        assert_eq!(encode("", 10, &mut buf), Ok(&[][..]));
+    }
+}
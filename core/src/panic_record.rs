@@ -0,0 +1,223 @@
+//! Panic location and message, kept in RAM across a reset.
+
+use core::fmt::{self, Write};
+
+use crate::crc::crc32;
+
+/// Bytes of the source file path kept; longer paths keep their tail.
+pub const FILE_LEN: usize = 48;
+/// Bytes of the panic message kept; longer messages keep their head.
+pub const MESSAGE_LEN: usize = 96;
+
+/// Where and why the firmware panicked.
+///
+/// Like [`CrashRecord`](crate::fault::CrashRecord) this has a magic number
+/// and CRC, so a record left by the panic handler can be told apart from
+/// whatever the RAM held at power-on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct PanicRecord {
+    magic: u32,
+    pub line: u32,
+    pub column: u32,
+    file_len: u8,
+    message_len: u8,
+    truncated: u8,
+    _reserved: u8,
+    file: [u8; FILE_LEN],
+    message: [u8; MESSAGE_LEN],
+    crc: u32,
+}
+
+const FILE_TRUNCATED: u8 = 1 << 0;
+const MESSAGE_TRUNCATED: u8 = 1 << 1;
+
+impl PanicRecord {
+    const MAGIC: u32 = 0x9A41_C0DE;
+
+    /// Pass an empty `file` if the location isn't known.
+    pub fn new(file: &str, line: u32, column: u32, message: impl fmt::Display) -> Self {
+        let mut record = Self {
+            magic: Self::MAGIC,
+            line,
+            column,
+            file_len: 0,
+            message_len: 0,
+            truncated: 0,
+            _reserved: 0,
+            file: [0; FILE_LEN],
+            message: [0; MESSAGE_LEN],
+            crc: 0,
+        };
+
+        // Keep the end of long paths, which has the file name.
+        let mut start = file.len().saturating_sub(FILE_LEN);
+        while !file.is_char_boundary(start) {
+            start += 1;
+        }
+        if start > 0 {
+            record.truncated |= FILE_TRUNCATED;
+        }
+        let tail = &file.as_bytes()[start..];
+        record.file[..tail.len()].copy_from_slice(tail);
+        record.file_len = tail.len() as u8;
+
+        let mut writer = TruncatingWriter {
+            buf: &mut record.message,
+            len: 0,
+            truncated: false,
+        };
+        // The writer never fails; it just stops storing.
+        write!(writer, "{}", message).ok();
+        let (len, truncated) = (writer.len, writer.truncated);
+        record.message_len = len as u8;
+        if truncated {
+            record.truncated |= MESSAGE_TRUNCATED;
+        }
+
+        record.crc = record.checksum();
+        record
+    }
+
+    /// Whether this was written by [`PanicRecord::new`] and not corrupted since.
+    pub fn is_valid(&self) -> bool {
+        self.magic == Self::MAGIC
+            && self.file_len as usize <= FILE_LEN
+            && self.message_len as usize <= MESSAGE_LEN
+            && self.crc == self.checksum()
+    }
+
+    /// Makes the record invalid, so it's only reported once.
+    pub fn clear(&mut self) {
+        self.magic = 0;
+    }
+
+    /// Source file of the panic, or `""` if unknown.
+    pub fn file(&self) -> &str {
+        core::str::from_utf8(&self.file[..self.file_len as usize]).unwrap_or_default()
+    }
+
+    pub fn message(&self) -> &str {
+        core::str::from_utf8(&self.message[..self.message_len as usize]).unwrap_or_default()
+    }
+
+    fn checksum(&self) -> u32 {
+        let mut bytes = [0u8; 16 + FILE_LEN + MESSAGE_LEN];
+        bytes[0..4].copy_from_slice(&self.magic.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.line.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.column.to_le_bytes());
+        bytes[12..16].copy_from_slice(&[self.file_len, self.message_len, self.truncated, 0]);
+        bytes[16..16 + FILE_LEN].copy_from_slice(&self.file);
+        bytes[16 + FILE_LEN..].copy_from_slice(&self.message);
+        crc32(&bytes)
+    }
+}
+
+/// Formats like the standard library: `panicked at src/main.rs:10:5: message`.
+impl fmt::Display for PanicRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("panicked")?;
+        if !self.file().is_empty() {
+            let ellipsis = if self.truncated & FILE_TRUNCATED != 0 {
+                "..."
+            } else {
+                ""
+            };
+            write!(
+                f,
+                " at {}{}:{}:{}",
+                ellipsis,
+                self.file(),
+                self.line,
+                self.column
+            )?;
+        }
+        write!(f, ": {}", self.message())?;
+        if self.truncated & MESSAGE_TRUNCATED != 0 {
+            f.write_str("...")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for PanicRecord {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{}", defmt::Display2Format(self))
+    }
+}
+
+// Stores as much as fits, cutting at a character boundary.
+struct TruncatingWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    truncated: bool,
+}
+
+impl Write for TruncatingWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let space = self.buf.len() - self.len;
+        let mut n = s.len().min(space);
+        while !s.is_char_boundary(n) {
+            n -= 1;
+        }
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        self.truncated |= n < s.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_location_and_message() {
+        let record = PanicRecord::new(
+            "src/projects/uart.rs",
+            120,
+            17,
+            format_args!("called `Result::unwrap()` on an `Err` value: {}", 3),
+        );
+        assert!(record.is_valid());
+        assert_eq!(record.file(), "src/projects/uart.rs");
+        assert_eq!(
+            record.to_string(),
+            "panicked at src/projects/uart.rs:120:17: \
+             called `Result::unwrap()` on an `Err` value: 3"
+        );
+    }
+
+    #[test]
+    fn truncates_long_paths_and_messages() {
+        let file = "/home/user/.cargo/registry/src/index.crates.io-1949cf8c6b5b557f/\
+                    stm32f4xx-hal-0.22.1/src/serial.rs";
+        let message = "é".repeat(MESSAGE_LEN);
+        let record = PanicRecord::new(file, 1, 2, &message);
+
+        assert!(record.is_valid());
+        assert_eq!(record.file().len(), FILE_LEN);
+        assert!(file.ends_with(record.file()));
+        assert_eq!(record.message(), "é".repeat(MESSAGE_LEN / 2));
+        assert!(record.to_string().starts_with("panicked at ..."));
+        assert!(record.to_string().ends_with("é..."));
+    }
+
+    #[test]
+    fn unknown_location() {
+        let record = PanicRecord::new("", 0, 0, "defmt panic");
+        assert_eq!(record.to_string(), "panicked: defmt panic");
+    }
+
+    #[test]
+    fn detects_corruption_and_clear() {
+        let mut record = PanicRecord::new("a.rs", 1, 1, "boom");
+        let mut corrupted = record;
+        corrupted.message[0] = b'z';
+        assert!(!corrupted.is_valid());
+
+        record.clear();
+        assert!(!record.is_valid());
+    }
+}
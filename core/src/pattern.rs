@@ -0,0 +1,271 @@
+//! Declarative LED blink patterns.
+//!
+//! A [`Pattern`] is a list of [`Step`]s, each a blink repeated some number
+//! of times, with an optional start offset and repeat count. Whether an LED
+//! is on is a pure function of the time since its pattern started, so a
+//! [`Player`] can run one pattern per LED from a single timer tick, and the
+//! tests can check exact timelines.
+
+/// `count` blinks of `on_ms` on then `off_ms` off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub on_ms: u32,
+    pub off_ms: u32,
+    pub count: u32,
+}
+
+impl Step {
+    pub const fn blink(on_ms: u32, off_ms: u32, count: u32) -> Self {
+        Self {
+            on_ms,
+            off_ms,
+            count,
+        }
+    }
+
+    pub const fn on(ms: u32) -> Self {
+        Self::blink(ms, 0, 1)
+    }
+
+    pub const fn off(ms: u32) -> Self {
+        Self::blink(0, ms, 1)
+    }
+
+    pub const fn duration_ms(&self) -> u32 {
+        (self.on_ms + self.off_ms) * self.count
+    }
+
+    // Whether the LED is on `t_ms` into this step.
+    fn is_on_at(&self, t_ms: u32) -> bool {
+        t_ms % (self.on_ms + self.off_ms) < self.on_ms
+    }
+}
+
+/// How many times a pattern plays.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Repeat {
+    Forever,
+    Times(u32),
+}
+
+/// A sequence of steps for one LED, e.g. two quick flashes every second:
+///
+/// ```ignore
+/// const HEARTBEAT: &[Step] = &[Step::blink(100, 100, 2), Step::off(600)];
+/// let pattern = Pattern::new(HEARTBEAT).offset(250);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pattern<'a> {
+    steps: &'a [Step],
+    repeat: Repeat,
+    offset_ms: u32,
+}
+
+impl<'a> Pattern<'a> {
+    /// Plays `steps` forever, starting straight away.
+    pub const fn new(steps: &'a [Step]) -> Self {
+        Self {
+            steps,
+            repeat: Repeat::Forever,
+            offset_ms: 0,
+        }
+    }
+
+    pub const fn repeat(self, repeat: Repeat) -> Self {
+        Self { repeat, ..self }
+    }
+
+    /// Stays off for `offset_ms` before the first step, e.g. to run the same
+    /// steps out of phase on another LED.
+    pub const fn offset(self, offset_ms: u32) -> Self {
+        Self { offset_ms, ..self }
+    }
+
+    /// Length of one pass through the steps.
+    pub fn period_ms(&self) -> u32 {
+        self.steps.iter().map(Step::duration_ms).sum()
+    }
+
+    /// Time until the pattern finishes, or `None` if it repeats forever.
+    pub fn duration_ms(&self) -> Option<u32> {
+        match self.repeat {
+            Repeat::Forever => None,
+            Repeat::Times(n) => Some(self.offset_ms + n * self.period_ms()),
+        }
+    }
+
+    /// Whether the LED is on `t_ms` after the pattern started. It's off
+    /// before the offset and after the last repeat.
+    pub fn is_on_at(&self, t_ms: u32) -> bool {
+        let period_ms = self.period_ms();
+        let Some(t_ms) = t_ms.checked_sub(self.offset_ms) else {
+            return false;
+        };
+        if period_ms == 0 {
+            return false;
+        }
+        if let Repeat::Times(n) = self.repeat
+            && t_ms / period_ms >= n
+        {
+            return false;
+        }
+
+        let mut t_ms = t_ms % period_ms;
+        for step in self.steps {
+            let duration_ms = step.duration_ms();
+            if t_ms < duration_ms {
+                return step.is_on_at(t_ms);
+            }
+            t_ms -= duration_ms;
+        }
+        false
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Channel<'a> {
+    pattern: Option<Pattern<'a>>,
+    start_ms: u32,
+}
+
+/// Plays a pattern on each of `N` LEDs from a shared clock.
+pub struct Player<'a, const N: usize> {
+    channels: [Channel<'a>; N],
+    now_ms: u32,
+}
+
+impl<'a, const N: usize> Player<'a, N> {
+    /// All LEDs off.
+    pub const fn new() -> Self {
+        Self {
+            channels: [Channel {
+                pattern: None,
+                start_ms: 0,
+            }; N],
+            now_ms: 0,
+        }
+    }
+
+    /// Starts `pattern` on LED `led` from the current time.
+    pub fn play(&mut self, led: usize, pattern: Pattern<'a>) {
+        self.channels[led] = Channel {
+            pattern: Some(pattern),
+            start_ms: self.now_ms,
+        };
+    }
+
+    /// Turns LED `led` off.
+    pub fn stop(&mut self, led: usize) {
+        self.channels[led].pattern = None;
+    }
+
+    /// Advances the clock by `elapsed_ms` and returns which LEDs are on.
+    pub fn advance(&mut self, elapsed_ms: u32) -> [bool; N] {
+        self.now_ms = self.now_ms.wrapping_add(elapsed_ms);
+        self.levels()
+    }
+
+    /// Which LEDs are on now.
+    pub fn levels(&self) -> [bool; N] {
+        self.channels.map(|channel| {
+            channel
+                .pattern
+                .is_some_and(|pattern| pattern.is_on_at(self.now_ms.wrapping_sub(channel.start_ms)))
+        })
+    }
+
+    /// Whether LED `led`'s pattern has played all its repeats, or it has
+    /// none.
+    pub fn is_finished(&self, led: usize) -> bool {
+        let channel = &self.channels[led];
+        channel.pattern.is_none_or(|pattern| {
+            pattern
+                .duration_ms()
+                .is_some_and(|duration| self.now_ms.wrapping_sub(channel.start_ms) >= duration)
+        })
+    }
+}
+
+impl<const N: usize> Default for Player<'_, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Samples a pattern every `step_ms` as a string of '#' (on) and '.'.
+    fn timeline(pattern: &Pattern, step_ms: u32, len: usize) -> String {
+        (0..len as u32)
+            .map(|i| {
+                if pattern.is_on_at(i * step_ms) {
+                    '#'
+                } else {
+                    '.'
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plays_steps_in_order() {
+        let steps = [Step::blink(10, 10, 3), Step::on(30), Step::off(20)];
+        let pattern = Pattern::new(&steps);
+        assert_eq!(pattern.period_ms(), 110);
+        assert_eq!(timeline(&pattern, 10, 22), "#.#.#.###..#.#.#.###..");
+    }
+
+    #[test]
+    fn offset_and_repeat_count() {
+        let steps = [Step::blink(10, 20, 1)];
+        let pattern = Pattern::new(&steps).offset(20).repeat(Repeat::Times(2));
+        assert_eq!(timeline(&pattern, 10, 12), "..#..#......");
+        assert_eq!(pattern.duration_ms(), Some(80));
+    }
+
+    #[test]
+    fn empty_pattern_stays_off() {
+        let pattern = Pattern::new(&[]);
+        assert_eq!(timeline(&pattern, 10, 4), "....");
+    }
+
+    #[test]
+    fn player_runs_leds_independently() {
+        const FAST: &[Step] = &[Step::blink(10, 10, 1)];
+        const SLOW: &[Step] = &[Step::blink(20, 20, 1)];
+        let mut player = Player::<3>::new();
+        player.play(0, Pattern::new(FAST));
+        player.play(1, Pattern::new(SLOW).offset(10));
+
+        let mut rows = [String::new(), String::new(), String::new()];
+        for _ in 0..8 {
+            for (row, on) in rows.iter_mut().zip(player.levels()) {
+                row.push(if on { '#' } else { '.' });
+            }
+            player.advance(10);
+        }
+        assert_eq!(rows, ["#.#.#.#.", ".##..##.", "........"]);
+    }
+
+    #[test]
+    fn player_restarts_and_finishes_patterns() {
+        const ONCE: &[Step] = &[Step::on(20)];
+        let mut player = Player::<1>::new();
+        assert!(player.is_finished(0));
+
+        player.advance(15);
+        player.play(0, Pattern::new(ONCE).repeat(Repeat::Times(1)));
+        assert_eq!(player.levels(), [true]);
+        assert!(!player.is_finished(0));
+        assert_eq!(player.advance(15), [true]);
+        assert_eq!(player.advance(5), [false]);
+        assert!(player.is_finished(0));
+
+        player.play(0, Pattern::new(ONCE));
+        assert_eq!(player.advance(5), [true]);
+        player.stop(0);
+        assert_eq!(player.levels(), [false]);
+    }
+}
@@ -0,0 +1,197 @@
+//! Low-power mode selection and accounting.
+//!
+//! The firmware's power manager asks an [`IdlePolicy`] how deeply to sleep
+//! and adds up the time spent in each mode in [`PowerStats`]. Times come
+//! from the RTC as milliseconds since midnight, since it's the one clock
+//! that keeps running in Stop mode.
+
+use core::fmt;
+
+/// Milliseconds in a day, where RTC time of day wraps.
+pub const DAY_MS: u32 = 24 * 60 * 60 * 1000;
+
+/// Time from `start_ms` to `end_ms`, both times of day, allowing for one
+/// wrap past midnight.
+pub const fn day_elapsed_ms(start_ms: u32, end_ms: u32) -> u32 {
+    (end_ms + DAY_MS - start_ms) % DAY_MS
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerMode {
+    /// Awake and running code.
+    Run,
+    /// Core clock stopped; peripherals, timers and DMA keep running, and any
+    /// interrupt wakes the core.
+    Sleep,
+    /// All clocks in the 1.2 V domain stopped. Only EXTI lines, e.g. a pin or
+    /// the RTC wakeup timer, wake the chip, and the clocks then need setting
+    /// up again.
+    Stop,
+}
+
+/// Chooses the power mode for an idle period.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IdlePolicy {
+    pub allow_stop: bool,
+    /// Shorter idle periods use Sleep, since waking from Stop means
+    /// restarting the HSE and PLL.
+    pub min_stop_ms: u32,
+}
+
+impl IdlePolicy {
+    pub const DEFAULT: Self = Self {
+        allow_stop: true,
+        min_stop_ms: 10,
+    };
+
+    /// The deepest mode to idle in. `busy` means something still needs the
+    /// clocks, like a timer, DMA, UART output or button sampling.
+    /// `idle_ms` is how long until the next job, or `None` to wait for an
+    /// interrupt.
+    pub fn choose(&self, busy: bool, idle_ms: Option<u32>) -> PowerMode {
+        match idle_ms {
+            Some(0) => PowerMode::Run,
+            _ if busy || !self.allow_stop => PowerMode::Sleep,
+            Some(ms) if ms < self.min_stop_ms => PowerMode::Sleep,
+            _ => PowerMode::Stop,
+        }
+    }
+}
+
+impl Default for IdlePolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Time spent in each power mode, and how often Sleep and Stop were entered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PowerStats {
+    run_ms: u32,
+    sleep_ms: u32,
+    stop_ms: u32,
+    sleeps: u32,
+    stops: u32,
+}
+
+impl PowerStats {
+    pub const fn new() -> Self {
+        Self {
+            run_ms: 0,
+            sleep_ms: 0,
+            stop_ms: 0,
+            sleeps: 0,
+            stops: 0,
+        }
+    }
+
+    /// Adds `ms` spent in `mode`. Sleep and Stop count as one entry.
+    pub fn record(&mut self, mode: PowerMode, ms: u32) {
+        let (time, entries) = match mode {
+            PowerMode::Run => {
+                self.run_ms = self.run_ms.saturating_add(ms);
+                return;
+            }
+            PowerMode::Sleep => (&mut self.sleep_ms, &mut self.sleeps),
+            PowerMode::Stop => (&mut self.stop_ms, &mut self.stops),
+        };
+        *time = time.saturating_add(ms);
+        *entries = entries.saturating_add(1);
+    }
+
+    pub fn time_ms(&self, mode: PowerMode) -> u32 {
+        match mode {
+            PowerMode::Run => self.run_ms,
+            PowerMode::Sleep => self.sleep_ms,
+            PowerMode::Stop => self.stop_ms,
+        }
+    }
+
+    /// How many times Sleep or Stop was entered. Zero for Run.
+    pub fn entries(&self, mode: PowerMode) -> u32 {
+        match mode {
+            PowerMode::Run => 0,
+            PowerMode::Sleep => self.sleeps,
+            PowerMode::Stop => self.stops,
+        }
+    }
+
+    pub fn total_ms(&self) -> u32 {
+        self.run_ms
+            .saturating_add(self.sleep_ms)
+            .saturating_add(self.stop_ms)
+    }
+
+    /// Share of the total time spent in `mode`, in whole percent.
+    pub fn percent(&self, mode: PowerMode) -> u32 {
+        match self.total_ms() {
+            0 => 0,
+            total => (u64::from(self.time_ms(mode)) * 100 / u64::from(total)) as u32,
+        }
+    }
+}
+
+impl fmt::Display for PowerStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "run {} ms ({}%), sleep {} ms ({}%) x{}, stop {} ms ({}%) x{}",
+            self.run_ms,
+            self.percent(PowerMode::Run),
+            self.sleep_ms,
+            self.percent(PowerMode::Sleep),
+            self.sleeps,
+            self.stop_ms,
+            self.percent(PowerMode::Stop),
+            self.stops,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elapsed_wraps_at_midnight() {
+        assert_eq!(day_elapsed_ms(1000, 1500), 500);
+        assert_eq!(day_elapsed_ms(DAY_MS - 200, 300), 500);
+        assert_eq!(day_elapsed_ms(42, 42), 0);
+    }
+
+    #[test]
+    fn policy_picks_deepest_mode() {
+        let policy = IdlePolicy::DEFAULT;
+        assert_eq!(policy.choose(false, None), PowerMode::Stop);
+        assert_eq!(policy.choose(false, Some(500)), PowerMode::Stop);
+        assert_eq!(policy.choose(false, Some(5)), PowerMode::Sleep);
+        assert_eq!(policy.choose(true, Some(500)), PowerMode::Sleep);
+        assert_eq!(policy.choose(true, Some(0)), PowerMode::Run);
+
+        let no_stop = IdlePolicy {
+            allow_stop: false,
+            ..policy
+        };
+        assert_eq!(no_stop.choose(false, None), PowerMode::Sleep);
+    }
+
+    #[test]
+    fn stats_add_up() {
+        let mut stats = PowerStats::new();
+        assert_eq!(stats.percent(PowerMode::Stop), 0);
+
+        stats.record(PowerMode::Run, 100);
+        stats.record(PowerMode::Sleep, 300);
+        stats.record(PowerMode::Stop, 400);
+        stats.record(PowerMode::Stop, 200);
+        assert_eq!(stats.total_ms(), 1000);
+        assert_eq!(stats.entries(PowerMode::Stop), 2);
+        assert_eq!(stats.percent(PowerMode::Stop), 60);
+        assert_eq!(
+            stats.to_string(),
+            "run 100 ms (10%), sleep 300 ms (30%) x1, stop 600 ms (60%) x2"
+        );
+    }
+}
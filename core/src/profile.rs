@@ -0,0 +1,276 @@
+//! Execution time statistics for profiled code sections.
+//!
+//! The firmware's profiler times sections with the DWT cycle counter and
+//! records each duration in [`CycleStats`]: count, min, max, mean and a
+//! histogram with power-of-two bins. Cycle counts are passed in, so this runs
+//! on the host.
+
+use core::fmt;
+
+/// Histogram bins per section.
+pub const HISTOGRAM_BINS: usize = 16;
+
+// Bin 0 holds everything below 2^BIN_SHIFT cycles.
+const BIN_SHIFT: u32 = 6;
+
+/// The histogram bin for a duration. Bin 0 is under 64 cycles, each bin
+/// after that is twice as wide as the one before, and the last one holds
+/// everything from 2^20 cycles up.
+pub const fn bin_for(cycles: u32) -> usize {
+    let bits = u32::BITS - cycles.leading_zeros();
+    let bin = bits.saturating_sub(BIN_SHIFT) as usize;
+    if bin < HISTOGRAM_BINS {
+        bin
+    } else {
+        HISTOGRAM_BINS - 1
+    }
+}
+
+/// The shortest duration counted in `bin`.
+pub const fn bin_start(bin: usize) -> u32 {
+    match bin {
+        0 => 0,
+        _ => 1 << (bin as u32 + BIN_SHIFT - 1),
+    }
+}
+
+/// Durations recorded for one section, in CPU cycles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CycleStats {
+    count: u32,
+    min: u32,
+    max: u32,
+    total: u64,
+    histogram: [u32; HISTOGRAM_BINS],
+}
+
+impl CycleStats {
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            min: u32::MAX,
+            max: 0,
+            total: 0,
+            histogram: [0; HISTOGRAM_BINS],
+        }
+    }
+
+    pub fn record(&mut self, cycles: u32) {
+        self.count = self.count.saturating_add(1);
+        self.min = self.min.min(cycles);
+        self.max = self.max.max(cycles);
+        self.total += u64::from(cycles);
+        let bin = &mut self.histogram[bin_for(cycles)];
+        *bin = bin.saturating_add(1);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Shortest duration, or 0 if nothing was recorded.
+    pub fn min(&self) -> u32 {
+        if self.count == 0 { 0 } else { self.min }
+    }
+
+    pub fn max(&self) -> u32 {
+        self.max
+    }
+
+    /// Mean duration, rounded down, or 0 if nothing was recorded.
+    pub fn mean(&self) -> u32 {
+        match self.count {
+            0 => 0,
+            count => (self.total / u64::from(count)) as u32,
+        }
+    }
+
+    /// Counts per bin; see [`bin_for`].
+    pub fn histogram(&self) -> &[u32; HISTOGRAM_BINS] {
+        &self.histogram
+    }
+
+    /// The longest duration as a share of `budget_cycles`, in whole
+    /// percent, e.g. of the cycles between two timer interrupts.
+    pub fn max_percent_of(&self, budget_cycles: u32) -> u32 {
+        match budget_cycles {
+            0 => 0,
+            budget => (u64::from(self.max) * 100 / u64::from(budget)) as u32,
+        }
+    }
+}
+
+impl Default for CycleStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for CycleStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "n {}, min {}, mean {}, max {} cycles",
+            self.count,
+            self.min(),
+            self.mean(),
+            self.max
+        )
+    }
+}
+
+/// Handle returned by [`Profile::register`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SectionId(u8);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProfileError {
+    /// All section slots are taken.
+    Full,
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::Full => f.write_str("too many profiled sections"),
+        }
+    }
+}
+
+/// Statistics for up to `N` named sections.
+#[derive(Clone, Copy)]
+pub struct Profile<const N: usize> {
+    names: [&'static str; N],
+    stats: [CycleStats; N],
+    len: usize,
+}
+
+impl<const N: usize> Profile<N> {
+    pub const fn new() -> Self {
+        Self {
+            names: [""; N],
+            stats: [CycleStats::new(); N],
+            len: 0,
+        }
+    }
+
+    pub fn register(&mut self, name: &'static str) -> Result<SectionId, ProfileError> {
+        if self.len == N {
+            return Err(ProfileError::Full);
+        }
+        self.names[self.len] = name;
+        self.len += 1;
+        Ok(SectionId(self.len as u8 - 1))
+    }
+
+    pub fn record(&mut self, section: SectionId, cycles: u32) {
+        self.stats[section.0 as usize].record(cycles);
+    }
+
+    /// Clears the statistics, keeping the sections.
+    pub fn reset(&mut self) {
+        self.stats = [CycleStats::new(); N];
+    }
+
+    /// Each registered section's name and statistics.
+    pub fn sections(&self) -> impl Iterator<Item = (&'static str, &CycleStats)> {
+        self.names[..self.len]
+            .iter()
+            .copied()
+            .zip(&self.stats[..self.len])
+    }
+
+    /// Writes two lines per section: the statistics, with the longest run
+    /// as a share of `budget_cycles`, and the non-empty histogram bins.
+    pub fn write_report<W: fmt::Write>(&self, out: &mut W, budget_cycles: u32) -> fmt::Result {
+        for (name, stats) in self.sections() {
+            write!(
+                out,
+                "{}: {} ({}% of {})\r\n  ",
+                name,
+                stats,
+                stats.max_percent_of(budget_cycles),
+                budget_cycles
+            )?;
+            let mut sep = "";
+            for (bin, &count) in stats.histogram().iter().enumerate() {
+                if count > 0 {
+                    write!(out, "{}>={}: {}", sep, bin_start(bin), count)?;
+                    sep = ", ";
+                }
+            }
+            out.write_str("\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl<const N: usize> Default for Profile<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bins_double_in_width() {
+        assert_eq!(bin_for(0), 0);
+        assert_eq!(bin_for(63), 0);
+        assert_eq!(bin_for(64), 1);
+        assert_eq!(bin_for(127), 1);
+        assert_eq!(bin_for(128), 2);
+        assert_eq!(bin_for(1 << 20), HISTOGRAM_BINS - 1);
+        assert_eq!(bin_for(u32::MAX), HISTOGRAM_BINS - 1);
+        for bin in 0..HISTOGRAM_BINS {
+            assert_eq!(bin_for(bin_start(bin)), bin);
+        }
+    }
+
+    #[test]
+    fn stats_track_min_max_and_mean() {
+        let mut stats = CycleStats::new();
+        assert_eq!((stats.min(), stats.mean(), stats.max()), (0, 0, 0));
+
+        for cycles in [300, 100, 260, 1000] {
+            stats.record(cycles);
+        }
+        assert_eq!(stats.count(), 4);
+        assert_eq!((stats.min(), stats.mean(), stats.max()), (100, 415, 1000));
+        assert_eq!(stats.histogram()[bin_for(100)], 1);
+        assert_eq!(stats.histogram()[bin_for(300)], 2);
+        assert_eq!(stats.max_percent_of(84_000), 1);
+        assert_eq!(stats.to_string(), "n 4, min 100, mean 415, max 1000 cycles");
+    }
+
+    #[test]
+    fn profile_reports_each_section() {
+        let mut profile = Profile::<2>::new();
+        let dma = profile.register("dma").unwrap();
+        let process = profile.register("process").unwrap();
+        assert_eq!(profile.register("more"), Err(ProfileError::Full));
+
+        profile.record(dma, 500);
+        profile.record(dma, 70);
+        profile.record(process, 42_000);
+
+        let mut out = String::new();
+        profile.write_report(&mut out, 84_000).unwrap();
+        assert_eq!(
+            out,
+            "dma: n 2, min 70, mean 285, max 500 cycles (0% of 84000)\r\n  \
+             >=64: 1, >=256: 1\r\n\
+             process: n 1, min 42000, mean 42000, max 42000 cycles (50% of 84000)\r\n  \
+             >=32768: 1\r\n"
+        );
+
+        profile.reset();
+        assert!(profile.sections().all(|(_, stats)| stats.count() == 0));
+        assert_eq!(profile.sections().count(), 2);
+    }
+}
@@ -0,0 +1,127 @@
+//! Decoding why the chip last reset.
+//!
+//! The RCC clock control & status register (RCC_CSR) has a sticky flag for
+//! each reset source. Flags stay set until software clears them, and some
+//! resets set more than one: a power-on reset also sets the brown-out and
+//! pin flags, and every internal reset drives the NRST pin low, which sets
+//! the pin flag too. [`ResetCause::from_csr`] picks the most specific one.
+
+use core::fmt;
+
+const BORRSTF: u32 = 1 << 25;
+const PINRSTF: u32 = 1 << 26;
+const PORRSTF: u32 = 1 << 27;
+const SFTRSTF: u32 = 1 << 28;
+const IWDGRSTF: u32 = 1 << 29;
+const WWDGRSTF: u32 = 1 << 30;
+const LPWRRSTF: u32 = 1 << 31;
+
+/// Why the chip reset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResetCause {
+    PowerOn,
+    BrownOut,
+    IndependentWatchdog,
+    WindowWatchdog,
+    /// Entering Standby or Stop mode while the option bytes forbid it.
+    LowPower,
+    /// `SCB::sys_reset`, e.g. after a HardFault or panic.
+    Software,
+    /// The NRST pin, e.g. the black reset button.
+    Pin,
+    /// No flags set, e.g. because they were already cleared.
+    Unknown,
+}
+
+impl ResetCause {
+    /// Decodes the RCC_CSR register value.
+    pub fn from_csr(csr: u32) -> Self {
+        // Most specific first, since the pin flag accompanies all of these
+        // and power-on also sets the brown-out flag.
+        const ORDER: [(u32, ResetCause); 7] = [
+            (PORRSTF, ResetCause::PowerOn),
+            (BORRSTF, ResetCause::BrownOut),
+            (IWDGRSTF, ResetCause::IndependentWatchdog),
+            (WWDGRSTF, ResetCause::WindowWatchdog),
+            (LPWRRSTF, ResetCause::LowPower),
+            (SFTRSTF, ResetCause::Software),
+            (PINRSTF, ResetCause::Pin),
+        ];
+        ORDER
+            .iter()
+            .find(|(flag, _)| csr & flag != 0)
+            .map_or(ResetCause::Unknown, |&(_, cause)| cause)
+    }
+
+    pub fn is_watchdog(self) -> bool {
+        matches!(
+            self,
+            ResetCause::IndependentWatchdog | ResetCause::WindowWatchdog
+        )
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            ResetCause::PowerOn => "power-on reset",
+            ResetCause::BrownOut => "brown-out reset",
+            ResetCause::IndependentWatchdog => "independent watchdog reset",
+            ResetCause::WindowWatchdog => "window watchdog reset",
+            ResetCause::LowPower => "low-power management reset",
+            ResetCause::Software => "software reset",
+            ResetCause::Pin => "external reset (NRST pin)",
+            ResetCause::Unknown => "unknown reset",
+        }
+    }
+}
+
+impl fmt::Display for ResetCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn power_on_sets_several_flags() {
+        assert_eq!(
+            ResetCause::from_csr(PORRSTF | BORRSTF | PINRSTF),
+            ResetCause::PowerOn
+        );
+        assert_eq!(
+            ResetCause::from_csr(BORRSTF | PINRSTF),
+            ResetCause::BrownOut
+        );
+    }
+
+    #[test]
+    fn internal_resets_win_over_pin() {
+        let cases = [
+            (IWDGRSTF, ResetCause::IndependentWatchdog),
+            (WWDGRSTF, ResetCause::WindowWatchdog),
+            (LPWRRSTF, ResetCause::LowPower),
+            (SFTRSTF, ResetCause::Software),
+        ];
+        for (flag, cause) in cases {
+            assert_eq!(ResetCause::from_csr(flag | PINRSTF), cause);
+        }
+        assert_eq!(ResetCause::from_csr(PINRSTF), ResetCause::Pin);
+    }
+
+    #[test]
+    fn ignores_clock_bits() {
+        // LSION and LSIRDY set, flags already cleared.
+        assert_eq!(ResetCause::from_csr(0b11), ResetCause::Unknown);
+        assert_eq!(ResetCause::from_csr(SFTRSTF | 0b11), ResetCause::Software);
+    }
+
+    #[test]
+    fn watchdog_causes() {
+        assert!(ResetCause::IndependentWatchdog.is_watchdog());
+        assert!(ResetCause::WindowWatchdog.is_watchdog());
+        assert!(!ResetCause::Software.is_watchdog());
+    }
+}
@@ -0,0 +1,170 @@
+//! Fixed-capacity byte ring buffer for queued UART output.
+
+use core::fmt;
+
+/// Counters describing how a [`ByteRing`] has been used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RingStats {
+    /// Writes rejected because they didn't fit.
+    pub dropped_writes: u32,
+    /// Bytes in those rejected writes.
+    pub dropped_bytes: u32,
+    /// Most bytes ever queued at once.
+    pub high_water: usize,
+}
+
+/// A FIFO of up to `N` bytes.
+///
+/// Writes are all-or-nothing: if a write doesn't fit it is dropped and
+/// counted, rather than blocking or sending a partial message. This type
+/// does no locking; share it between contexts inside a critical section.
+pub struct ByteRing<const N: usize> {
+    buf: [u8; N],
+    head: usize,
+    len: usize,
+    stats: RingStats,
+}
+
+impl<const N: usize> ByteRing<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            head: 0,
+            len: 0,
+            stats: RingStats {
+                dropped_writes: 0,
+                dropped_bytes: 0,
+                high_water: 0,
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Space left for writes.
+    pub fn free(&self) -> usize {
+        N - self.len
+    }
+
+    pub fn stats(&self) -> RingStats {
+        self.stats
+    }
+
+    /// Queues all of `data`, or none of it if there isn't room.
+    pub fn push(&mut self, data: &[u8]) -> bool {
+        if data.len() > self.free() {
+            self.count_dropped(data.len());
+            return false;
+        }
+
+        let tail = (self.head + self.len) % N;
+        let first = data.len().min(N - tail);
+        self.buf[tail..tail + first].copy_from_slice(&data[..first]);
+        self.buf[..data.len() - first].copy_from_slice(&data[first..]);
+
+        self.len += data.len();
+        self.stats.high_water = self.stats.high_water.max(self.len);
+        true
+    }
+
+    /// Counts a write of `len` bytes that was dropped before it got here,
+    /// e.g. a line too long to format.
+    pub fn count_dropped(&mut self, len: usize) {
+        self.stats.dropped_writes = self.stats.dropped_writes.saturating_add(1);
+        self.stats.dropped_bytes = self.stats.dropped_bytes.saturating_add(len as u32);
+    }
+
+    /// Removes the oldest byte.
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.buf[self.head];
+        self.consume(1);
+        Some(byte)
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.head = (self.head + n) % N;
+        self.len -= n;
+        // Keep writes contiguous for as long as possible.
+        if self.len == 0 {
+            self.head = 0;
+        }
+    }
+}
+
+impl<const N: usize> Default for ByteRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Formatted writes that don't fit are dropped and return an error.
+impl<const N: usize> fmt::Write for ByteRing<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.push(s.as_bytes()) {
+            Ok(())
+        } else {
+            Err(fmt::Error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+
+    use super::*;
+
+    fn drain<const N: usize>(ring: &mut ByteRing<N>) -> Vec<u8> {
+        std::iter::from_fn(|| ring.pop()).collect()
+    }
+
+    #[test]
+    fn queues_in_order_across_wraparound() {
+        let mut ring = ByteRing::<8>::new();
+        assert!(ring.push(b"abcde"));
+        assert_eq!(ring.pop(), Some(b'a'));
+        assert_eq!(ring.pop(), Some(b'b'));
+
+        // Wraps past the end of the buffer.
+        assert!(ring.push(b"fghij"));
+        assert_eq!(ring.free(), 0);
+        assert_eq!(drain(&mut ring), b"cdefghij");
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn drops_whole_writes_that_dont_fit() {
+        let mut ring = ByteRing::<8>::new();
+        assert!(ring.push(b"12345"));
+        assert!(!ring.push(b"6789"));
+        assert!(ring.push(b"678"));
+
+        let stats = ring.stats();
+        assert_eq!(stats.dropped_writes, 1);
+        assert_eq!(stats.dropped_bytes, 4);
+        assert_eq!(stats.high_water, 8);
+        assert_eq!(drain(&mut ring), b"12345678");
+
+        ring.count_dropped(300);
+        assert_eq!(ring.stats().dropped_writes, 2);
+        assert_eq!(ring.stats().dropped_bytes, 304);
+    }
+
+    #[test]
+    fn formatted_writes() {
+        let mut ring = ByteRing::<16>::new();
+        write!(ring, "Press {:02}", 3).unwrap();
+        assert!(write!(ring, " and a long tail").is_err());
+        assert_eq!(drain(&mut ring), b"Press 03");
+    }
+}
@@ -0,0 +1,24 @@
+//! Text formatting of ADC samples sent to the PC.
+
+use core::fmt::{self, Write};
+
+/// Length in bytes of one line written by [`write_sample_line`].
+pub const SAMPLE_LINE_LEN: usize = 16;
+
+/// Writes a pair of samples as a line like `"00512 -- 01023\r\n"`.
+pub fn write_sample_line<W: Write>(w: &mut W, mic1: u16, mic2: u16) -> fmt::Result {
+    writeln!(w, "{:05} -- {:05}\r", mic1, mic2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_zero_padded_pair() {
+        let mut line = String::new();
+        write_sample_line(&mut line, 512, 1023).unwrap();
+        assert_eq!(line, "00512 -- 01023\r\n");
+        assert_eq!(line.len(), SAMPLE_LINE_LEN);
+    }
+}
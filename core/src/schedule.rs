@@ -0,0 +1,264 @@
+//! Timing for a cooperative scheduler.
+//!
+//! Jobs are periodic or one-shot, timed in milliseconds. A timer interrupt
+//! advances the schedule's clock with [`Schedule::tick`], and the main loop
+//! takes each job as it falls due with [`Schedule::next_due`] and runs it.
+//! Nothing here runs the jobs or reads a timer, so this runs on the host.
+
+use core::fmt;
+
+/// Handle returned by [`Schedule::every`] and [`Schedule::once`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct JobId(u8);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ScheduleError {
+    /// All job slots are taken.
+    Full,
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::Full => f.write_str("too many scheduled jobs"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Job {
+    // Zero for a one-shot job.
+    period_ms: u32,
+    due_ms: u32,
+    running: bool,
+}
+
+/// Due times for up to `N` jobs.
+pub struct Schedule<const N: usize> {
+    jobs: [Job; N],
+    len: usize,
+    now_ms: u32,
+}
+
+impl<const N: usize> Schedule<N> {
+    pub const fn new() -> Self {
+        Self {
+            jobs: [Job {
+                period_ms: 0,
+                due_ms: 0,
+                running: false,
+            }; N],
+            len: 0,
+            now_ms: 0,
+        }
+    }
+
+    /// Adds a job that runs every `period_ms`, at least 1, starting one
+    /// period from now.
+    pub fn every(&mut self, period_ms: u32) -> Result<JobId, ScheduleError> {
+        let period_ms = period_ms.max(1);
+        self.add(Job {
+            period_ms,
+            due_ms: self.now_ms.wrapping_add(period_ms),
+            running: true,
+        })
+    }
+
+    /// Adds a one-shot job, which doesn't run until [`start`](Self::start)ed.
+    pub fn once(&mut self) -> Result<JobId, ScheduleError> {
+        self.add(Job {
+            period_ms: 0,
+            due_ms: 0,
+            running: false,
+        })
+    }
+
+    fn add(&mut self, job: Job) -> Result<JobId, ScheduleError> {
+        if self.len == N {
+            return Err(ScheduleError::Full);
+        }
+        self.jobs[self.len] = job;
+        self.len += 1;
+        Ok(JobId(self.len as u8 - 1))
+    }
+
+    /// Runs `job` after `delay_ms`, replacing any earlier due time. A
+    /// periodic job carries on from there every period.
+    pub fn start(&mut self, job: JobId, delay_ms: u32) {
+        let job = &mut self.jobs[job.0 as usize];
+        job.due_ms = self.now_ms.wrapping_add(delay_ms);
+        job.running = true;
+    }
+
+    /// Keeps `job` from running until it's started again.
+    pub fn stop(&mut self, job: JobId) {
+        self.jobs[job.0 as usize].running = false;
+    }
+
+    pub fn is_running(&self, job: JobId) -> bool {
+        self.jobs[job.0 as usize].running
+    }
+
+    /// Changes a periodic job's period, at least 1, from its next run on.
+    /// Start it again to apply the change straight away.
+    pub fn set_period(&mut self, job: JobId, period_ms: u32) {
+        let job = &mut self.jobs[job.0 as usize];
+        if job.period_ms != 0 {
+            job.period_ms = period_ms.max(1);
+        }
+    }
+
+    /// Advances the clock by `elapsed_ms`.
+    pub fn tick(&mut self, elapsed_ms: u32) {
+        self.now_ms = self.now_ms.wrapping_add(elapsed_ms);
+    }
+
+    /// Milliseconds since the schedule was created, wrapping after about 49
+    /// days.
+    pub fn now_ms(&self) -> u32 {
+        self.now_ms
+    }
+
+    /// Takes the job that has been due the longest, if any, and works out
+    /// when it's due again. Periodic jobs keep their phase, so a late run
+    /// doesn't delay the next one, but runs missed by a whole period are
+    /// skipped rather than made up. One-shot jobs stop.
+    pub fn next_due(&mut self) -> Option<JobId> {
+        let now_ms = self.now_ms;
+        let (index, job) = self.jobs[..self.len]
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, job)| job.running && late_ms(now_ms, job.due_ms).is_some())
+            .max_by_key(|(index, job)| (late_ms(now_ms, job.due_ms), core::cmp::Reverse(*index)))?;
+
+        if job.period_ms == 0 {
+            job.running = false;
+        } else {
+            job.due_ms = job.due_ms.wrapping_add(job.period_ms);
+            if late_ms(now_ms, job.due_ms).is_some() {
+                job.due_ms = now_ms.wrapping_add(job.period_ms);
+            }
+        }
+        Some(JobId(index as u8))
+    }
+
+    /// Time until the next job is due, 0 if one is already, or `None` if no
+    /// job is running.
+    pub fn idle_ms(&self) -> Option<u32> {
+        self.jobs[..self.len]
+            .iter()
+            .filter(|job| job.running)
+            .map(|job| match late_ms(self.now_ms, job.due_ms) {
+                Some(_) => 0,
+                None => job.due_ms.wrapping_sub(self.now_ms),
+            })
+            .min()
+    }
+}
+
+impl<const N: usize> Default for Schedule<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// How long ago `due_ms` was, or `None` if it's still to come. Times within
+// half the `u32` range of each other compare correctly across a wrap.
+fn late_ms(now_ms: u32, due_ms: u32) -> Option<u32> {
+    let late_ms = now_ms.wrapping_sub(due_ms);
+    (late_ms <= u32::MAX / 2).then_some(late_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Advances `schedule` by `ms`, 1 ms at a time, collecting the time and
+    // job of each run.
+    fn run<const N: usize>(schedule: &mut Schedule<N>, ms: u32) -> Vec<(u32, JobId)> {
+        let mut runs = Vec::new();
+        for _ in 0..ms {
+            schedule.tick(1);
+            while let Some(job) = schedule.next_due() {
+                runs.push((schedule.now_ms(), job));
+            }
+        }
+        runs
+    }
+
+    #[test]
+    fn periodic_and_one_shot_jobs() {
+        let mut schedule = Schedule::<3>::new();
+        let blink = schedule.every(10).unwrap();
+        let timeout = schedule.once().unwrap();
+        assert!(!schedule.is_running(timeout));
+        assert_eq!(schedule.idle_ms(), Some(10));
+
+        schedule.start(timeout, 15);
+        assert_eq!(
+            run(&mut schedule, 30),
+            [(10, blink), (15, timeout), (20, blink), (30, blink)]
+        );
+        assert!(!schedule.is_running(timeout));
+
+        schedule.stop(blink);
+        assert_eq!(schedule.idle_ms(), None);
+        assert_eq!(run(&mut schedule, 30), []);
+    }
+
+    #[test]
+    fn late_runs_keep_the_phase() {
+        let mut schedule = Schedule::<1>::new();
+        let job = schedule.every(10).unwrap();
+        assert_eq!(schedule.every(5), Err(ScheduleError::Full));
+
+        // 3 ms late: the next run is still on the 10 ms grid.
+        schedule.tick(13);
+        assert_eq!(schedule.next_due(), Some(job));
+        assert_eq!(schedule.next_due(), None);
+        assert_eq!(schedule.idle_ms(), Some(7));
+
+        // Two whole periods late: it runs once, and then on the next period
+        // from now.
+        schedule.tick(32);
+        assert_eq!(schedule.next_due(), Some(job));
+        assert_eq!(schedule.next_due(), None);
+        assert_eq!(schedule.idle_ms(), Some(10));
+    }
+
+    #[test]
+    fn restarting_changes_the_period_straight_away() {
+        let mut schedule = Schedule::<2>::new();
+        let blink = schedule.every(100).unwrap();
+        let button = schedule.every(5).unwrap();
+        schedule.tick(60);
+        while schedule.next_due().is_some() {}
+
+        schedule.set_period(blink, 40);
+        assert_eq!(schedule.idle_ms(), Some(5));
+        schedule.stop(button);
+        assert_eq!(schedule.idle_ms(), Some(40));
+        schedule.start(blink, 0);
+        assert_eq!(schedule.idle_ms(), Some(0));
+        assert_eq!(schedule.next_due(), Some(blink));
+        assert_eq!(run(&mut schedule, 80), [(100, blink), (140, blink)]);
+    }
+
+    #[test]
+    fn most_overdue_job_runs_first_across_a_wrap() {
+        let mut schedule = Schedule::<2>::new();
+        schedule.tick(u32::MAX - 5);
+        let slow = schedule.every(10).unwrap();
+        let fast = schedule.every(3).unwrap();
+
+        // `fast` was due 8 ms ago and `slow` 2 ms ago. `fast` is then more
+        // than a period late, so it moves on rather than running again.
+        schedule.tick(12);
+        assert_eq!(schedule.next_due(), Some(fast));
+        assert_eq!(schedule.next_due(), Some(slow));
+        assert_eq!(schedule.next_due(), None);
+        assert_eq!(schedule.idle_ms(), Some(3));
+    }
+}
@@ -0,0 +1,200 @@
+//! Check-in bookkeeping for a watchdog supervisor.
+//!
+//! Each supervised task registers with a deadline and then checks in as it
+//! runs. A periodic check advances the supervisor's clock; the watchdog
+//! should only be fed while every task has checked in within its deadline.
+//! Time is passed in rather than read from a timer, so this runs on the host.
+
+use core::fmt;
+
+/// Handle returned by [`Supervisor::register`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TaskId(u8);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SupervisorError {
+    /// All task slots are taken.
+    Full,
+}
+
+impl fmt::Display for SupervisorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SupervisorError::Full => f.write_str("too many supervised tasks"),
+        }
+    }
+}
+
+/// A task that hasn't checked in within its deadline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Overdue {
+    pub task: TaskId,
+    pub name: &'static str,
+    /// Time since its last check-in.
+    pub silent_ms: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Task {
+    name: &'static str,
+    deadline_ms: u32,
+    last_check_in_ms: u32,
+}
+
+/// Tracks check-ins from up to `N` tasks.
+pub struct Supervisor<const N: usize> {
+    tasks: [Task; N],
+    len: usize,
+    now_ms: u32,
+}
+
+impl<const N: usize> Supervisor<N> {
+    pub const fn new() -> Self {
+        Self {
+            tasks: [Task {
+                name: "",
+                deadline_ms: 0,
+                last_check_in_ms: 0,
+            }; N],
+            len: 0,
+            now_ms: 0,
+        }
+    }
+
+    /// Adds a task that must check in at least every `deadline_ms`. It counts
+    /// as checked in now.
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        deadline_ms: u32,
+    ) -> Result<TaskId, SupervisorError> {
+        if self.len == N {
+            return Err(SupervisorError::Full);
+        }
+        self.tasks[self.len] = Task {
+            name,
+            deadline_ms,
+            last_check_in_ms: self.now_ms,
+        };
+        self.len += 1;
+        Ok(TaskId((self.len - 1) as u8))
+    }
+
+    pub fn check_in(&mut self, task: TaskId) {
+        if let Some(task) = self.tasks[..self.len].get_mut(task.0 as usize) {
+            task.last_check_in_ms = self.now_ms;
+        }
+    }
+
+    /// Advances the clock by `elapsed_ms` and returns the first overdue task,
+    /// if any. Only feed the watchdog when this returns `Ok`.
+    pub fn advance(&mut self, elapsed_ms: u32) -> Result<(), Overdue> {
+        self.now_ms = self.now_ms.wrapping_add(elapsed_ms);
+        self.tasks[..self.len]
+            .iter()
+            .enumerate()
+            .map(|(i, task)| (i, task, self.now_ms.wrapping_sub(task.last_check_in_ms)))
+            .find(|(_, task, silent_ms)| *silent_ms > task.deadline_ms)
+            .map_or(Ok(()), |(i, task, silent_ms)| {
+                Err(Overdue {
+                    task: TaskId(i as u8),
+                    name: task.name,
+                    silent_ms,
+                })
+            })
+    }
+
+    /// Time since the supervisor was created, as passed to [`advance`](Self::advance).
+    pub fn now_ms(&self) -> u32 {
+        self.now_ms
+    }
+}
+
+impl<const N: usize> Default for Supervisor<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn healthy_while_tasks_check_in() {
+        let mut supervisor = Supervisor::<2>::new();
+        let fast = supervisor.register("fast", 100).unwrap();
+        let slow = supervisor.register("slow", 1000).unwrap();
+
+        for step in 1..=20 {
+            supervisor.check_in(fast);
+            if step % 5 == 0 {
+                supervisor.check_in(slow);
+            }
+            assert_eq!(supervisor.advance(100), Ok(()));
+        }
+        assert_eq!(supervisor.now_ms(), 2000);
+    }
+
+    #[test]
+    fn reports_stalled_task() {
+        let mut supervisor = Supervisor::<2>::new();
+        let fast = supervisor.register("fast", 100).unwrap();
+        let slow = supervisor.register("slow", 250).unwrap();
+
+        supervisor.check_in(fast);
+        assert_eq!(supervisor.advance(100), Ok(()));
+        supervisor.check_in(fast);
+        assert_eq!(supervisor.advance(100), Ok(()));
+        supervisor.check_in(fast);
+        assert_eq!(
+            supervisor.advance(100),
+            Err(Overdue {
+                task: slow,
+                name: "slow",
+                silent_ms: 300,
+            })
+        );
+
+        // Recovers once it checks in again.
+        supervisor.check_in(fast);
+        supervisor.check_in(slow);
+        assert_eq!(supervisor.advance(50), Ok(()));
+    }
+
+    #[test]
+    fn deadline_is_inclusive() {
+        let mut supervisor = Supervisor::<1>::new();
+        supervisor.register("task", 100).unwrap();
+        assert_eq!(supervisor.advance(100), Ok(()));
+        assert!(supervisor.advance(1).is_err());
+    }
+
+    #[test]
+    fn late_registration_starts_fresh() {
+        let mut supervisor = Supervisor::<1>::new();
+        supervisor.advance(5000).unwrap();
+        supervisor.register("late", 100).unwrap();
+        assert_eq!(supervisor.advance(100), Ok(()));
+    }
+
+    #[test]
+    fn handles_clock_wrap() {
+        let mut supervisor = Supervisor::<1>::new();
+        let task = supervisor.register("task", 100).unwrap();
+        supervisor.advance(u32::MAX - 10).unwrap_err();
+        supervisor.check_in(task);
+        assert_eq!(supervisor.advance(50), Ok(()));
+        assert!(supervisor.now_ms() < 50);
+    }
+
+    #[test]
+    fn rejects_too_many_tasks() {
+        let mut supervisor = Supervisor::<1>::new();
+        supervisor.register("one", 100).unwrap();
+        assert_eq!(supervisor.register("two", 100), Err(SupervisorError::Full));
+    }
+}
@@ -0,0 +1,65 @@
+//! Host-side companion to the firmware binaries.
+//!
+//! Reads the byte streams the board sends over the USB-UART adapter, turns
+//! them into timestamped [`Record`]s, and writes them to CSV, JSON or WAV
+//! files. Everything here works on any `Read`, so it can be tested against a
+//! pseudo-terminal or a file instead of a real board.
+
+pub mod parse;
+pub mod reader;
+pub mod serial;
+pub mod sink;
+
+use std::{io, time::Instant};
+
+pub use parse::{BoardTime, Event};
+use reader::EventReader;
+use sink::Sink;
+
+/// An event stamped with the time it was received.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    /// Seconds since the capture started.
+    pub time_s: f64,
+    /// When the board says it happened, if it sent a timestamp.
+    pub board_time: Option<BoardTime>,
+    pub event: Event,
+}
+
+/// Reads events until the stream ends or `limit` records have been captured,
+/// passing each one to every sink. Returns the number of records captured.
+pub fn capture<R: io::Read>(
+    reader: &mut EventReader<R>,
+    sinks: &mut [Box<dyn Sink>],
+    limit: Option<usize>,
+) -> io::Result<usize> {
+    let start = Instant::now();
+    let mut count = 0;
+
+    while limit.is_none_or(|limit| count < limit) {
+        let Some((board_time, event)) = reader.next_timed()? else {
+            break;
+        };
+        let record = Record {
+            time_s: start.elapsed().as_secs_f64(),
+            board_time,
+            event,
+        };
+        for sink in sinks.iter_mut() {
+            sink.write(&record)?;
+        }
+        count += 1;
+
+        // Keep files usable if the capture is interrupted.
+        if !reader.has_buffered() {
+            for sink in sinks.iter_mut() {
+                sink.flush()?;
+            }
+        }
+    }
+
+    for sink in sinks.iter_mut() {
+        sink.finish()?;
+    }
+    Ok(count)
+}
@@ -0,0 +1,153 @@
+//! Captures and decodes what the board sends over the USB-UART adapter.
+//!
+//! For example, to record ADC samples from `rtic-adc-dma`:
+//!
+//! ```shell
+//! cargo run -- /dev/ttyUSB0 --format frames --baud 230400 --csv samples.csv --wav samples.wav
+//! ```
+
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use clap::Parser;
+use stm32f4d_core::datetime::DateTime;
+use stm32f4d_host::{
+    capture,
+    reader::{EventReader, Format},
+    serial,
+    sink::{CsvSink, JsonSink, Sink, WavSink},
+};
+
+// Long enough for the board to restart its clocks after waking from Stop.
+const WAKE_DELAY: Duration = Duration::from_millis(50);
+
+#[derive(Parser)]
+#[command(about = "Capture and decode serial output from the STM32F4DISCOVERY board")]
+struct Args {
+    /// Serial device, pseudo-terminal or file to read from.
+    device: PathBuf,
+
+    /// How the board encodes its output.
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Baud rate, when the device is a terminal.
+    #[arg(long, default_value_t = 115200)]
+    baud: u32,
+
+    /// Write records to a CSV file.
+    #[arg(long)]
+    csv: Option<PathBuf>,
+
+    /// Write records to a file with one JSON object per line.
+    #[arg(long)]
+    json: Option<PathBuf>,
+
+    /// Write samples to a WAV file.
+    #[arg(long)]
+    wav: Option<PathBuf>,
+
+    /// Sample rate recorded in the WAV file; `SAMPLE_RATE_HZ` in the firmware.
+    #[arg(long, default_value_t = 1000)]
+    rate: u32,
+
+    /// ADC resolution in bits, used to scale samples for the WAV file.
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..=16))]
+    bits: u32,
+
+    /// Set the board's clock to the PC's, in UTC, before capturing. Needs a
+    /// binary with the `time` command, like `uart-example`.
+    #[arg(long)]
+    set_time: bool,
+
+    /// Stop after this many records.
+    #[arg(long)]
+    count: Option<usize>,
+
+    /// Don't echo records to stdout.
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+/// Echoes records to stdout.
+struct Echo;
+
+impl Sink for Echo {
+    fn write(&mut self, record: &stm32f4d_host::Record) -> std::io::Result<()> {
+        match record.board_time {
+            Some(board_time) => {
+                println!("{:10.3} [{}] {:?}", record.time_s, board_time, record.event)
+            }
+            None => println!("{:10.3} {:?}", record.time_s, record.event),
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn run(args: Args) -> std::io::Result<usize> {
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+    if !args.quiet {
+        sinks.push(Box::new(Echo));
+    }
+    if let Some(path) = &args.csv {
+        sinks.push(Box::new(CsvSink::new(BufWriter::new(File::create(path)?))?));
+    }
+    if let Some(path) = &args.json {
+        sinks.push(Box::new(JsonSink::new(BufWriter::new(File::create(path)?))));
+    }
+    if let Some(path) = &args.wav {
+        let out = BufWriter::new(File::create(path)?);
+        sinks.push(Box::new(WavSink::new(out, args.rate, args.bits)));
+    }
+
+    let device = serial::open(&args.device, args.baud)?;
+    if args.set_time {
+        set_board_time(&args.device)?;
+    }
+    let mut reader = EventReader::new(device, args.format);
+    capture(&mut reader, &mut sinks, args.count)
+}
+
+// Sends the `time` command on the next whole second, since the board's RTC
+// can only be set to whole seconds.
+fn set_board_time(device: &Path) -> io::Result<()> {
+    // An empty line, which the board ignores, wakes it from Stop mode first;
+    // the bytes that wake it are lost.
+    serial::send_line(device, "")?;
+    thread::sleep(WAKE_DELAY);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(io::Error::other)?;
+    let next_ms = (now.as_millis() as u64 / 1000 + 1) * 1000;
+    let time = DateTime::from_unix_ms(next_ms).map_err(|err| io::Error::other(err.to_string()))?;
+
+    thread::sleep(Duration::from_millis(next_ms) - now);
+    serial::send_line(device, &format!("time {time}"))
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let device = args.device.clone();
+
+    match run(args) {
+        Ok(count) => {
+            eprintln!("Captured {count} records.");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{}: {err}", device.display());
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Parsing of the text lines the firmware binaries print.
+
+use std::fmt;
+
+use stm32f4d_core::{
+    datetime::{DateTime, TimeOfDay},
+    frame::FrameError,
+};
+
+/// When the board says something happened, by its RTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoardTime {
+    /// From the `[YYYY-MM-DD HH:MM:SS.mmm] ` at the start of a text line.
+    DateTime(DateTime),
+    /// From a binary frame, which only carries the time of day.
+    TimeOfDay(TimeOfDay),
+}
+
+impl fmt::Display for BoardTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoardTime::DateTime(time) => time.fmt(f),
+            BoardTime::TimeOfDay(time) => time.fmt(f),
+        }
+    }
+}
+
+/// Something the board told us.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// `"Button Press 03 Woohoo!!"` from `uart-example`.
+    ButtonPress(u32),
+    /// `"State active -> inactive (...)"` from `uart-example`.
+    Deactivated,
+    /// `"State inactive -> active (...)"` from `uart-example`.
+    Reactivated,
+    /// One sample per ADC channel from `rtic-adc-dma`. Binary frames also
+    /// carry a sequence number.
+    Samples { seq: Option<u16>, values: Vec<u16> },
+    /// Frames missing from the binary stream before the next good one.
+    Dropped(u16),
+    /// A binary frame that failed to decode.
+    FrameError(FrameError),
+    /// Any other line of text.
+    Text(String),
+}
+
+impl Event {
+    /// Short name used in the output files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::ButtonPress(_) => "button_press",
+            Event::Deactivated => "deactivated",
+            Event::Reactivated => "reactivated",
+            Event::Samples { .. } => "samples",
+            Event::Dropped(_) => "dropped",
+            Event::FrameError(_) => "frame_error",
+            Event::Text(_) => "text",
+        }
+    }
+}
+
+/// Parses one line of text, without its line ending, that may start with a
+/// timestamp.
+pub fn parse_timestamped(line: &str) -> (Option<BoardTime>, Event) {
+    if let Some((time, rest)) = line
+        .strip_prefix('[')
+        .and_then(|line| line.split_once("] "))
+        && let Ok(time) = DateTime::parse(time)
+    {
+        return (Some(BoardTime::DateTime(time)), parse_line(rest));
+    }
+    (None, parse_line(line))
+}
+
+/// Parses one line of text, without its line ending.
+pub fn parse_line(line: &str) -> Event {
+    let line = line.trim_end_matches(['\r', '\n']);
+
+    if let Some(count) = line
+        .strip_prefix("Button Press ")
+        .and_then(|rest| rest.strip_suffix(" Woohoo!!"))
+        .and_then(|count| count.parse().ok())
+    {
+        return Event::ButtonPress(count);
+    }
+
+    if line.starts_with("State active -> inactive") {
+        return Event::Deactivated;
+    }
+    if line.starts_with("State inactive -> active") {
+        return Event::Reactivated;
+    }
+
+    if let Some((mic1, mic2)) = line.split_once(" -- ")
+        && let (Some(mic1), Some(mic2)) = (parse_sample(mic1), parse_sample(mic2))
+    {
+        return Event::Samples {
+            seq: None,
+            values: vec![mic1, mic2],
+        };
+    }
+
+    Event::Text(line.to_string())
+}
+
+// Samples are printed as exactly five digits.
+fn parse_sample(s: &str) -> Option<u16> {
+    if s.len() != 5 || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_button_lines() {
+        assert_eq!(
+            parse_line("Button Press 03 Woohoo!!\r"),
+            Event::ButtonPress(3)
+        );
+        assert_eq!(
+            parse_line("Button Press 255 Woohoo!!"),
+            Event::ButtonPress(255)
+        );
+        assert_eq!(
+            parse_line("State active -> inactive (press limit reached); back in 10000 ms\r"),
+            Event::Deactivated
+        );
+        assert_eq!(
+            parse_line("State inactive -> active (inactivity period over)\r"),
+            Event::Reactivated
+        );
+    }
+
+    #[test]
+    fn parses_sample_lines() {
+        assert_eq!(
+            parse_line("00512 -- 01023\r"),
+            Event::Samples {
+                seq: None,
+                values: vec![512, 1023]
+            }
+        );
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        let (time, event) = parse_timestamped("[2026-10-18 09:05:03.250] Button Press 01 Woohoo!!");
+        assert_eq!(event, Event::ButtonPress(1));
+        assert_eq!(time.unwrap().to_string(), "2026-10-18 09:05:03.250");
+
+        assert_eq!(parse_timestamped("00512 -- 01023").0, None);
+        let line = "[not a time] hello";
+        assert_eq!(
+            parse_timestamped(line),
+            (None, Event::Text(line.to_string()))
+        );
+    }
+
+    #[test]
+    fn keeps_unrecognized_lines() {
+        for line in ["512 -- 1023", "Button Press xx Woohoo!!", "hello"] {
+            assert_eq!(parse_line(line), Event::Text(line.to_string()));
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Splitting a byte stream from the board into events.
+
+use std::{collections::VecDeque, io};
+
+use stm32f4d_core::{datetime::TimeOfDay, frame::FrameDecoder};
+
+use crate::parse::{BoardTime, Event, parse_timestamped};
+
+/// How the board encodes what it sends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Lines of text, as printed by `uart-example` or by `rtic-adc-dma` in
+    /// text mode.
+    Text,
+    /// COBS framed binary samples, as sent by `rtic-adc-dma` by default.
+    Frames,
+}
+
+/// Reads [`Event`]s from a serial device, pseudo-terminal or file.
+pub struct EventReader<R> {
+    inner: R,
+    format: Format,
+    line: Vec<u8>,
+    decoder: FrameDecoder,
+    pending: VecDeque<(Option<BoardTime>, Event)>,
+}
+
+impl<R: io::Read> EventReader<R> {
+    pub fn new(inner: R, format: Format) -> Self {
+        Self {
+            inner,
+            format,
+            line: Vec::new(),
+            decoder: FrameDecoder::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Whether events are waiting that don't need another read.
+    pub fn has_buffered(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Returns the next event, blocking until one arrives, or `None` at the
+    /// end of the stream.
+    pub fn next_event(&mut self) -> io::Result<Option<Event>> {
+        Ok(self.next_timed()?.map(|(_, event)| event))
+    }
+
+    /// Like [`next_event`](Self::next_event), also returning the board's
+    /// timestamp if the line or frame had one.
+    pub fn next_timed(&mut self) -> io::Result<Option<(Option<BoardTime>, Event)>> {
+        let mut buf = [0u8; 256];
+
+        while self.pending.is_empty() {
+            let n = match self.inner.read(&mut buf) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                // A pseudo-terminal reports EIO once the other side closes.
+                Err(e) if e.raw_os_error() == Some(nix::libc::EIO) => 0,
+                Err(e) => return Err(e),
+            };
+            if n == 0 {
+                return Ok(self.finish_line());
+            }
+            for &byte in &buf[..n] {
+                self.push(byte);
+            }
+        }
+
+        Ok(self.pending.pop_front())
+    }
+
+    fn push(&mut self, byte: u8) {
+        match self.format {
+            Format::Text => {
+                if byte == b'\n' {
+                    if let Some(timed) = self.finish_line() {
+                        self.pending.push_back(timed);
+                    }
+                } else {
+                    self.line.push(byte);
+                }
+            }
+            Format::Frames => match self.decoder.push(byte) {
+                Some(Ok(received)) => {
+                    let time = received
+                        .frame
+                        .time_ms
+                        .map(|ms| BoardTime::TimeOfDay(TimeOfDay(ms)));
+                    if received.dropped > 0 {
+                        self.pending
+                            .push_back((time, Event::Dropped(received.dropped)));
+                    }
+                    self.pending.push_back((
+                        time,
+                        Event::Samples {
+                            seq: Some(received.frame.seq),
+                            values: received.frame.samples().to_vec(),
+                        },
+                    ));
+                }
+                Some(Err(err)) => self.pending.push_back((None, Event::FrameError(err))),
+                None => {}
+            },
+        }
+    }
+
+    // Parses the text collected since the last line ending, if any.
+    fn finish_line(&mut self) -> Option<(Option<BoardTime>, Event)> {
+        let line = std::mem::take(&mut self.line);
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end_matches('\r');
+        (!line.is_empty()).then(|| parse_timestamped(line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use stm32f4d_core::frame::{MAX_FRAME_LEN, encode_frame};
+
+    use super::*;
+
+    fn read_all(bytes: &[u8], format: Format) -> Vec<Event> {
+        let mut reader = EventReader::new(bytes, format);
+        std::iter::from_fn(|| reader.next_event().unwrap()).collect()
+    }
+
+    #[test]
+    fn reads_text_lines() {
+        let events = read_all(
+            b"Button Press 01 Woohoo!!\r\n\r\n00001 -- 00002\r\npartial",
+            Format::Text,
+        );
+        assert_eq!(
+            events,
+            [
+                Event::ButtonPress(1),
+                Event::Samples {
+                    seq: None,
+                    values: vec![1, 2]
+                },
+                Event::Text("partial".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_frames_and_gaps() {
+        let mut stream = Vec::new();
+        let mut out = [0; MAX_FRAME_LEN];
+        for seq in [0, 1, 4] {
+            let len = encode_frame(seq, None, &[seq, 100], &mut out).unwrap();
+            stream.extend_from_slice(&out[..len]);
+        }
+
+        let events = read_all(&stream, Format::Frames);
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[2], Event::Dropped(2));
+        assert_eq!(
+            events[3],
+            Event::Samples {
+                seq: Some(4),
+                values: vec![4, 100]
+            }
+        );
+    }
+
+    #[test]
+    fn reads_board_timestamps() {
+        let mut stream = b"[2026-10-18 09:05:03.250] Button Press 01 Woohoo!!\r\n".to_vec();
+        let mut reader = EventReader::new(&stream[..], Format::Text);
+        let (time, event) = reader.next_timed().unwrap().unwrap();
+        assert_eq!(event, Event::ButtonPress(1));
+        assert_eq!(time.unwrap().to_string(), "2026-10-18 09:05:03.250");
+
+        stream.clear();
+        let mut out = [0; MAX_FRAME_LEN];
+        let len = encode_frame(0, Some(32_703_250), &[1, 2], &mut out).unwrap();
+        stream.extend_from_slice(&out[..len]);
+        let mut reader = EventReader::new(&stream[..], Format::Frames);
+        let (time, _) = reader.next_timed().unwrap().unwrap();
+        assert_eq!(time, Some(BoardTime::TimeOfDay(TimeOfDay(32_703_250))));
+    }
+}
@@ -0,0 +1,51 @@
+//! Opening the serial device.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use nix::sys::termios::{self, BaudRate, SetArg};
+
+/// Opens `path` for reading. If it's a terminal, such as `/dev/ttyUSB0` or a
+/// pseudo-terminal, it's put in raw mode at the given baud rate; anything else
+/// is read as-is.
+pub fn open(path: &Path, baud: u32) -> io::Result<File> {
+    let file = File::open(path)?;
+
+    if nix::unistd::isatty(&file).unwrap_or(false) {
+        let mut attrs = termios::tcgetattr(&file)?;
+        termios::cfmakeraw(&mut attrs);
+        termios::cfsetspeed(&mut attrs, baud_rate(baud)?)?;
+        termios::tcsetattr(&file, SetArg::TCSANOW, &attrs)?;
+    }
+
+    Ok(file)
+}
+
+/// Sends one line to the board, e.g. a console command. Open the device
+/// with [`open`] first so that it's set up.
+pub fn send_line(path: &Path, line: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    write!(file, "{line}\r\n")
+}
+
+fn baud_rate(baud: u32) -> io::Result<BaudRate> {
+    Ok(match baud {
+        9600 => BaudRate::B9600,
+        19200 => BaudRate::B19200,
+        38400 => BaudRate::B38400,
+        57600 => BaudRate::B57600,
+        115200 => BaudRate::B115200,
+        230400 => BaudRate::B230400,
+        460800 => BaudRate::B460800,
+        921600 => BaudRate::B921600,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported baud rate {baud}"),
+            ));
+        }
+    })
+}
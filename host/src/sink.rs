@@ -0,0 +1,301 @@
+//! Output files for captured records.
+
+use std::io::{self, Seek, Write};
+
+use crate::{Event, Record};
+
+/// Somewhere captured records are written.
+pub trait Sink {
+    fn write(&mut self, record: &Record) -> io::Result<()>;
+
+    /// Makes everything written so far visible in the output.
+    fn flush(&mut self) -> io::Result<()>;
+
+    /// Called once at the end of the capture.
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+// Extra CSV columns / JSON fields for each kind of event.
+fn event_data(event: &Event) -> Vec<String> {
+    match event {
+        Event::ButtonPress(count) => vec![count.to_string()],
+        Event::Samples { values, .. } => values.iter().map(u16::to_string).collect(),
+        Event::Dropped(count) => vec![count.to_string()],
+        Event::FrameError(err) => vec![format!("{err:?}")],
+        Event::Text(text) => vec![text.clone()],
+        Event::Deactivated | Event::Reactivated => Vec::new(),
+    }
+}
+
+fn event_seq(event: &Event) -> Option<u16> {
+    match event {
+        Event::Samples { seq, .. } => *seq,
+        _ => None,
+    }
+}
+
+/// Writes one row per record: `time_s,board_time,event,seq,data...`, where
+/// `board_time` is empty if the board didn't send one, and `data` is one
+/// column per channel for samples, or the count or text for other events.
+pub struct CsvSink<W: Write> {
+    out: W,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        writeln!(out, "time_s,board_time,event,seq,data")?;
+        Ok(Self { out })
+    }
+}
+
+impl<W: Write> Sink for CsvSink<W> {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        let event = &record.event;
+        write!(self.out, "{:.6},", record.time_s)?;
+        if let Some(board_time) = record.board_time {
+            write!(self.out, "{board_time}")?;
+        }
+        write!(self.out, ",{},", event.name())?;
+        if let Some(seq) = event_seq(event) {
+            write!(self.out, "{seq}")?;
+        }
+        for field in event_data(event) {
+            write!(self.out, ",{}", csv_escape(&field))?;
+        }
+        writeln!(self.out)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes one JSON object per line.
+pub struct JsonSink<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonSink<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+impl<W: Write> Sink for JsonSink<W> {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        let event = &record.event;
+        write!(self.out, "{{\"time_s\":{:.6}", record.time_s)?;
+        if let Some(board_time) = record.board_time {
+            write!(self.out, ",\"board_time\":\"{board_time}\"")?;
+        }
+        write!(self.out, ",\"event\":\"{}\"", event.name())?;
+        if let Some(seq) = event_seq(event) {
+            write!(self.out, ",\"seq\":{seq}")?;
+        }
+        match event {
+            Event::ButtonPress(count) => write!(self.out, ",\"count\":{count}")?,
+            Event::Dropped(count) => write!(self.out, ",\"count\":{count}")?,
+            Event::Samples { values, .. } => {
+                let values: Vec<_> = values.iter().map(u16::to_string).collect();
+                write!(self.out, ",\"values\":[{}]", values.join(","))?;
+            }
+            Event::FrameError(_) | Event::Text(_) => {
+                let text = event_data(event).remove(0);
+                write!(self.out, ",\"text\":\"{}\"", json_escape(&text))?;
+            }
+            Event::Deactivated | Event::Reactivated => {}
+        }
+        writeln!(self.out, "}}")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes samples to a 16-bit PCM WAV file, one WAV channel per ADC channel.
+/// Other events are ignored.
+///
+/// Unsigned ADC counts of the given resolution are centered on zero and
+/// scaled up to the full 16-bit range.
+pub struct WavSink<W: Write + Seek> {
+    out: Option<W>,
+    writer: Option<hound::WavWriter<W>>,
+    sample_rate: u32,
+    bits: u32,
+}
+
+impl<W: Write + Seek> WavSink<W> {
+    /// The channel count is taken from the first samples received.
+    pub fn new(out: W, sample_rate: u32, bits: u32) -> Self {
+        assert!((1..=16).contains(&bits));
+        Self {
+            out: Some(out),
+            writer: None,
+            sample_rate,
+            bits,
+        }
+    }
+
+    fn to_pcm(&self, count: u16) -> i16 {
+        let centered = count as i32 - (1 << (self.bits - 1));
+        (centered << (16 - self.bits)) as i16
+    }
+}
+
+impl<W: Write + Seek> Sink for WavSink<W> {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        let Event::Samples { values, .. } = &record.event else {
+            return Ok(());
+        };
+
+        if self.writer.is_none() {
+            let spec = hound::WavSpec {
+                channels: values.len() as u16,
+                sample_rate: self.sample_rate,
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            };
+            let out = self.out.take().expect("WAV sink already finished");
+            self.writer = Some(hound::WavWriter::new(out, spec).map_err(hound_err)?);
+        }
+
+        let pcm: Vec<i16> = values.iter().map(|&v| self.to_pcm(v)).collect();
+        let writer = self.writer.as_mut().unwrap();
+        // Pad or truncate so the channels stay interleaved correctly.
+        for ch in 0..writer.spec().channels as usize {
+            writer
+                .write_sample(pcm.get(ch).copied().unwrap_or(0))
+                .map_err(hound_err)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.writer.as_mut() {
+            Some(writer) => writer.flush().map_err(hound_err),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.writer.take() {
+            Some(writer) => writer.finalize().map_err(hound_err),
+            None => Ok(()),
+        }
+    }
+}
+
+fn hound_err(err: hound::Error) -> io::Error {
+    match err {
+        hound::Error::IoError(err) => err,
+        err => io::Error::other(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use stm32f4d_core::datetime::{DateTime, TimeOfDay};
+
+    use super::*;
+    use crate::BoardTime;
+
+    fn records() -> Vec<Record> {
+        let events = [
+            Event::ButtonPress(2),
+            Event::Samples {
+                seq: Some(7),
+                values: vec![0, 1023],
+            },
+            Event::Text("a \"quoted\", line".into()),
+            Event::Deactivated,
+        ];
+        events
+            .into_iter()
+            .enumerate()
+            .map(|(i, event)| Record {
+                time_s: i as f64 * 0.5,
+                board_time: match i {
+                    0 => DateTime::parse("2026-10-18 09:05:03.250")
+                        .ok()
+                        .map(BoardTime::DateTime),
+                    1 => Some(BoardTime::TimeOfDay(TimeOfDay(32_703_500))),
+                    _ => None,
+                },
+                event,
+            })
+            .collect()
+    }
+
+    fn write_all(sink: &mut dyn Sink) {
+        for record in records() {
+            sink.write(&record).unwrap();
+        }
+        sink.finish().unwrap();
+    }
+
+    #[test]
+    fn writes_csv_rows() {
+        let mut out = Vec::new();
+        write_all(&mut CsvSink::new(&mut out).unwrap());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "time_s,board_time,event,seq,data\n\
+             0.000000,2026-10-18 09:05:03.250,button_press,,2\n\
+             0.500000,09:05:03.500,samples,7,0,1023\n\
+             1.000000,,text,,\"a \"\"quoted\"\", line\"\n\
+             1.500000,,deactivated,\n"
+        );
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        let mut out = Vec::new();
+        write_all(&mut JsonSink::new(&mut out));
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"time_s\":0.000000,\"board_time\":\"2026-10-18 09:05:03.250\",\"event\":\"button_press\",\"count\":2}\n\
+             {\"time_s\":0.500000,\"board_time\":\"09:05:03.500\",\"event\":\"samples\",\"seq\":7,\"values\":[0,1023]}\n\
+             {\"time_s\":1.000000,\"event\":\"text\",\"text\":\"a \\\"quoted\\\", line\"}\n\
+             {\"time_s\":1.500000,\"event\":\"deactivated\"}\n"
+        );
+    }
+
+    #[test]
+    fn writes_wav_samples() {
+        let mut out = Cursor::new(Vec::new());
+        write_all(&mut WavSink::new(&mut out, 1000, 10));
+
+        out.set_position(0);
+        let mut reader = hound::WavReader::new(out).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().sample_rate, 1000);
+        let samples: Vec<i16> = reader.samples().map(Result::unwrap).collect();
+        assert_eq!(samples, [-32768, 32704]);
+    }
+}
@@ -0,0 +1,165 @@
+//! Runs captures against a pseudo-terminal standing in for the board, fed
+//! with output recorded from the firmware binaries.
+
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    os::fd::OwnedFd,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use nix::{
+    pty::openpty,
+    sys::termios::{self, SetArg},
+};
+use stm32f4d_host::{
+    Event, capture,
+    reader::{EventReader, Format},
+    serial,
+    sink::{CsvSink, JsonSink, Sink, WavSink},
+};
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    fs::read(path).unwrap()
+}
+
+/// A pseudo-terminal whose slave side plays the role of `/dev/ttyUSB0`.
+struct FakeBoard {
+    master: File,
+    // Held open so the terminal stays up until the test is done.
+    _slave: OwnedFd,
+    path: PathBuf,
+}
+
+impl FakeBoard {
+    fn new() -> Self {
+        let pty = openpty(None, None).unwrap();
+        let path = nix::unistd::ttyname(&pty.slave).unwrap();
+
+        // Set raw mode up front so nothing written before the capture opens
+        // the device gets translated by the line discipline.
+        let mut attrs = termios::tcgetattr(&pty.slave).unwrap();
+        termios::cfmakeraw(&mut attrs);
+        termios::tcsetattr(&pty.slave, SetArg::TCSANOW, &attrs).unwrap();
+
+        Self {
+            master: File::from(pty.master),
+            _slave: pty.slave,
+            path,
+        }
+    }
+
+    /// Captures `count` records in the background while sending `bytes`.
+    fn capture(
+        mut self,
+        bytes: &[u8],
+        format: Format,
+        count: usize,
+        sinks: Vec<Box<dyn Sink + Send>>,
+    ) -> usize {
+        let device = serial::open(&self.path, 115200).unwrap();
+        let reader = thread::spawn(move || {
+            let mut sinks: Vec<Box<dyn Sink>> =
+                sinks.into_iter().map(|s| s as Box<dyn Sink>).collect();
+            let mut reader = EventReader::new(device, format);
+            capture(&mut reader, &mut sinks, Some(count)).unwrap()
+        });
+
+        self.master.write_all(bytes).unwrap();
+        reader.join().unwrap()
+    }
+}
+
+#[test]
+fn captures_button_presses_to_csv() {
+    let dir = tempfile::tempdir().unwrap();
+    let csv = dir.path().join("buttons.csv");
+
+    let sinks: Vec<Box<dyn Sink + Send>> = vec![Box::new(
+        CsvSink::new(BufWriter::new(File::create(&csv).unwrap())).unwrap(),
+    )];
+    let count = FakeBoard::new().capture(&fixture("uart-example.txt"), Format::Text, 6, sinks);
+    assert_eq!(count, 6);
+
+    let csv = fs::read_to_string(csv).unwrap();
+    let rows: Vec<Vec<&str>> = csv.lines().map(|l| l.split(',').collect()).collect();
+    assert_eq!(rows[0], ["time_s", "board_time", "event", "seq", "data"]);
+    for (i, row) in rows[1..6].iter().enumerate() {
+        assert!(row[0].parse::<f64>().unwrap() >= 0.0);
+        assert_eq!(row[2..], ["button_press", "", &(i + 1).to_string()]);
+    }
+    assert_eq!(rows[1][1], "2026-10-18 09:05:01.125");
+    assert_eq!(rows[6][2], "deactivated");
+}
+
+#[test]
+fn captures_text_samples_to_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let json = dir.path().join("samples.json");
+
+    let sinks: Vec<Box<dyn Sink + Send>> = vec![Box::new(JsonSink::new(BufWriter::new(
+        File::create(&json).unwrap(),
+    )))];
+    FakeBoard::new().capture(&fixture("rtic-adc-dma.txt"), Format::Text, 5, sinks);
+
+    let json = fs::read_to_string(json).unwrap();
+    let lines: Vec<&str> = json.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert!(lines[0].starts_with("{\"time_s\":"));
+    assert!(lines[0].ends_with(",\"event\":\"samples\",\"values\":[512,498]}"));
+    assert!(lines[4].ends_with(",\"values\":[600,420]}"));
+}
+
+#[test]
+fn captures_frames_to_wav() {
+    let dir = tempfile::tempdir().unwrap();
+    let wav = dir.path().join("samples.wav");
+    let csv = dir.path().join("samples.csv");
+
+    let sinks: Vec<Box<dyn Sink + Send>> = vec![
+        Box::new(WavSink::new(
+            BufWriter::new(File::create(&wav).unwrap()),
+            1000,
+            10,
+        )),
+        Box::new(CsvSink::new(BufWriter::new(File::create(&csv).unwrap())).unwrap()),
+    ];
+    // Five frames plus a report of the one missing from the recording.
+    FakeBoard::new().capture(&fixture("rtic-adc-dma.bin"), Format::Frames, 6, sinks);
+
+    let mut reader = hound::WavReader::open(wav).unwrap();
+    assert_eq!(reader.spec().channels, 2);
+    let samples: Vec<i16> = reader.samples().map(Result::unwrap).collect();
+    assert_eq!(samples.len(), 10);
+    assert_eq!(samples[..2], [0, -14 * 64]);
+
+    let csv = fs::read_to_string(csv).unwrap();
+    let events: Vec<&str> = csv
+        .lines()
+        .skip(1)
+        .map(|l| l.split(',').nth(2).unwrap())
+        .collect();
+    assert_eq!(
+        events,
+        [
+            "samples", "samples", "samples", "dropped", "samples", "samples"
+        ]
+    );
+}
+
+#[test]
+fn reads_recordings_from_files() {
+    let recording = fixture("rtic-adc-dma.bin");
+    let mut reader = EventReader::new(&recording[..], Format::Frames);
+    let mut seqs = Vec::new();
+    while let Some(event) = reader.next_event().unwrap() {
+        if let Event::Samples { seq, .. } = event {
+            seqs.push(seq.unwrap());
+        }
+    }
+    assert_eq!(seqs, [0, 1, 2, 4, 5]);
+}
@@ -0,0 +1,90 @@
+//! Setting up ADC1 from an [`AcquisitionConfig`].
+//!
+//! The config names its inputs, resolution and so on with the core crate's
+//! types, which know nothing of the HAL; [`AnalogInputs`] owns the pins and
+//! maps those onto ADC1's channel sequence and settings.
+
+use stm32f4d_core::acquisition::{
+    AcquisitionConfig, AnalogInput, ClockDivider, INTERNAL_SAMPLE_TIME, Resolution, SampleTime,
+};
+use stm32f4xx_hal::{
+    adc::{
+        Adc, Temperature, Vref,
+        config::{self, Sequence},
+    },
+    gpio::{Analog, PA1, PA2, PA3},
+    pac::ADC1,
+};
+
+/// The pins that can be chosen as inputs, in analog mode.
+pub struct AnalogInputs {
+    pa1: PA1<Analog>,
+    pa2: PA2<Analog>,
+    pa3: PA3<Analog>,
+}
+
+impl AnalogInputs {
+    pub fn new(pa1: PA1, pa2: PA2, pa3: PA3) -> Self {
+        Self {
+            pa1: pa1.into_analog(),
+            pa2: pa2.into_analog(),
+            pa3: pa3.into_analog(),
+        }
+    }
+
+    /// Sets the resolution and clock, and the sequence: the inputs, then
+    /// VREFINT and the temperature sensor. The ADC must be disabled.
+    pub fn configure(&self, adc: &mut Adc<ADC1>, config: &AcquisitionConfig) {
+        adc.set_resolution(resolution(config.resolution));
+        adc.set_clock(clock(config.clock_divider));
+        adc.enable_temperature_and_vref();
+
+        adc.reset_sequence();
+        let sample_time = sample_time(config.sample_time);
+        for (input, sequence) in config
+            .inputs
+            .into_iter()
+            .zip([Sequence::One, Sequence::Two])
+        {
+            match input {
+                AnalogInput::Pa1 => adc.configure_channel(&self.pa1, sequence, sample_time),
+                AnalogInput::Pa2 => adc.configure_channel(&self.pa2, sequence, sample_time),
+                AnalogInput::Pa3 => adc.configure_channel(&self.pa3, sequence, sample_time),
+            }
+        }
+        let internal = self::sample_time(INTERNAL_SAMPLE_TIME);
+        adc.configure_channel(&Vref, Sequence::Three, internal);
+        adc.configure_channel(&Temperature, Sequence::Four, internal);
+    }
+}
+
+fn resolution(resolution: Resolution) -> config::Resolution {
+    match resolution {
+        Resolution::Six => config::Resolution::Six,
+        Resolution::Eight => config::Resolution::Eight,
+        Resolution::Ten => config::Resolution::Ten,
+        Resolution::Twelve => config::Resolution::Twelve,
+    }
+}
+
+fn sample_time(sample_time: SampleTime) -> config::SampleTime {
+    match sample_time {
+        SampleTime::Cycles3 => config::SampleTime::Cycles_3,
+        SampleTime::Cycles15 => config::SampleTime::Cycles_15,
+        SampleTime::Cycles28 => config::SampleTime::Cycles_28,
+        SampleTime::Cycles56 => config::SampleTime::Cycles_56,
+        SampleTime::Cycles84 => config::SampleTime::Cycles_84,
+        SampleTime::Cycles112 => config::SampleTime::Cycles_112,
+        SampleTime::Cycles144 => config::SampleTime::Cycles_144,
+        SampleTime::Cycles480 => config::SampleTime::Cycles_480,
+    }
+}
+
+fn clock(divider: ClockDivider) -> config::Clock {
+    match divider {
+        ClockDivider::Two => config::Clock::Pclk2_div_2,
+        ClockDivider::Four => config::Clock::Pclk2_div_4,
+        ClockDivider::Six => config::Clock::Pclk2_div_6,
+        ClockDivider::Eight => config::Clock::Pclk2_div_8,
+    }
+}
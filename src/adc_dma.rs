@@ -0,0 +1,122 @@
+//! Circular DMA from ADC1 in blocks.
+//!
+//! DMA2 stream 0 copies each conversion into a [`DmaBuffer`] of two blocks,
+//! wrapping round without stopping. The stream interrupts when each half is
+//! full, and [`CircularAdc::on_interrupt`] copies out the half that's ready
+//! while the DMA fills the other one. The ADC must be set up to scan
+//! `CHANNELS` channels with continuous DMA requests, e.g. triggered by a
+//! timer. [`CircularAdc::restart`] reconfigures the ADC between runs.
+
+use stm32f4d_core::block::{Block, DmaBuffer, HalfTracker};
+use stm32f4xx_hal::{
+    adc::{Adc, config::Dma},
+    dma::{
+        PeripheralToMemory, Stream0, Transfer,
+        config::DmaConfig,
+        traits::{Stream, StreamISR},
+    },
+    pac::{ADC1, DMA2},
+};
+
+type AdcTransfer<const FRAMES: usize, const CHANNELS: usize> = Transfer<
+    Stream0<DMA2>,
+    0,
+    Adc<ADC1>,
+    PeripheralToMemory,
+    &'static mut DmaBuffer<FRAMES, CHANNELS>,
+>;
+
+/// ADC1 samples in blocks of `FRAMES` frames of `CHANNELS` channels.
+///
+/// ```ignore
+/// let adc = CircularAdc::start(dma.0, adc, ctx.local.buffer);
+///
+/// #[task(binds = DMA2_STREAM0, local = [adc])]
+/// fn dma(ctx: dma::Context) {
+///     if let Some(block) = ctx.local.adc.on_interrupt() { ... }
+/// }
+/// ```
+pub struct CircularAdc<const FRAMES: usize, const CHANNELS: usize> {
+    transfer: AdcTransfer<FRAMES, CHANNELS>,
+    // The buffer the transfer owns, for reading the half it isn't writing.
+    buffer: *const DmaBuffer<FRAMES, CHANNELS>,
+    halves: HalfTracker,
+}
+
+// SAFETY: The pointer is only read through `&mut self`, and the buffer is
+// `'static`.
+unsafe impl<const FRAMES: usize, const CHANNELS: usize> Send for CircularAdc<FRAMES, CHANNELS> {}
+
+impl<const FRAMES: usize, const CHANNELS: usize> CircularAdc<FRAMES, CHANNELS> {
+    /// Starts the stream in circular mode, interrupting as each half of
+    /// `buffer` fills. The ADC must be enabled; its triggers start the
+    /// conversions.
+    pub fn start(
+        stream: Stream0<DMA2>,
+        adc: Adc<ADC1>,
+        buffer: &'static mut DmaBuffer<FRAMES, CHANNELS>,
+    ) -> Self {
+        let pointer = &*buffer as *const _;
+        let config = DmaConfig::default()
+            .memory_increment(true)
+            .half_transfer_interrupt(true)
+            .transfer_complete_interrupt(true);
+        let mut transfer = Transfer::init_peripheral_to_memory(stream, adc, buffer, None, config);
+        // SAFETY: The HAL leaves circular mode alone outside double
+        // buffering, and the stream isn't enabled yet.
+        unsafe { transfer.stream().set_circular_mode(true) };
+        transfer.start(|_| {});
+
+        Self {
+            transfer,
+            buffer: pointer,
+            halves: HalfTracker::new(),
+        }
+    }
+
+    /// Clears the stream's flags and returns a copy of the block that was
+    /// just filled, if any. Call this from the `DMA2_STREAM0` interrupt
+    /// handler, which must run within one block's time of the interrupt.
+    pub fn on_interrupt(&mut self) -> Option<Block<FRAMES, CHANNELS>> {
+        let half_transfer = self.transfer.is_half_transfer();
+        let transfer_complete = self.transfer.is_transfer_complete();
+        self.transfer.clear_half_transfer();
+        self.transfer.clear_transfer_complete();
+
+        let half = self.halves.on_interrupt(half_transfer, transfer_complete)?;
+        // SAFETY: The DMA is filling the other half, and won't come back to
+        // this one until that's done.
+        let block = unsafe { core::ptr::read_volatile(&(*self.buffer)[half.index()]) };
+        Some(block)
+    }
+
+    /// Stops the ADC and the stream, lets `reconfigure` change the ADC, and
+    /// starts both again from the start of the buffer. Stop the trigger
+    /// first, so that no conversion is under way, and start it again after.
+    /// The ADC must convert `CHANNELS` channels per trigger.
+    pub fn restart(&mut self, reconfigure: impl FnOnce(&mut Adc<ADC1>)) {
+        // Runs before the stream is disabled.
+        self.transfer.pause(|adc| {
+            adc.disable();
+            reconfigure(adc);
+            // Turning DMA off and on again clears the ADC's DMA state, which
+            // stops after an overrun.
+            adc.set_dma(Dma::Disabled);
+            adc.set_dma(Dma::Continuous);
+        });
+        // SAFETY: The ADC is off, so nothing else is writing its status.
+        unsafe { (*ADC1::ptr()).sr().modify(|_, w| w.ovr().clear_bit()) };
+
+        // The stream would carry on from where it was paused.
+        let transfers = (2 * FRAMES * CHANNELS) as u16;
+        // SAFETY: The stream is disabled, so its count can be written.
+        unsafe { self.transfer.stream().set_number_of_transfers(transfers) };
+        self.halves.restart();
+        self.transfer.start(|adc| adc.enable());
+    }
+
+    /// Blocks the DMA overwrote before the interrupt handler read them.
+    pub fn lost_blocks(&self) -> u32 {
+        self.halves.lost()
+    }
+}
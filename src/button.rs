@@ -0,0 +1,138 @@
+//! Interrupt-driven B1 user button driver.
+//!
+//! The EXTI0 interrupt on PA0 wakes the driver when the pin changes, and a
+//! periodic tick, e.g. SysTick, then samples the pin until the button has
+//! settled. The samples go through [`Gestures`], which debounces them and
+//! turns them into press, release, long-press, double-click and repeat
+//! events. While the button is idle the tick only keeps time.
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::Mutex;
+use stm32f4d_core::button::{ButtonEvent, GestureConfig, Gestures};
+use stm32f4xx_hal::{
+    gpio::{Edge, ExtiPin, Input, PA0},
+    pac::EXTI,
+    syscfg::SysCfg,
+};
+
+struct Inner {
+    pin: PA0<Input>,
+    gestures: Gestures,
+    // Whether the tick should sample the pin.
+    sampling: bool,
+    now_ms: u32,
+}
+
+/// The B1 button, meant to live in a `static`.
+///
+/// ```ignore
+/// static BUTTON: Button = Button::new();
+///
+/// BUTTON.init(gpioa.pa0, &mut syscfg, &mut dp.EXTI, GestureConfig::DEFAULT);
+/// unsafe { pac::NVIC::unmask(pac::Interrupt::EXTI0) };
+///
+/// #[interrupt]
+/// fn EXTI0() {
+///     BUTTON.on_edge();
+/// }
+///
+/// #[exception]
+/// fn SysTick() {
+///     BUTTON.on_tick(TICK_MS);
+/// }
+///
+/// while let Some(event) = BUTTON.next_event() { ... }
+/// ```
+pub struct Button {
+    inner: Mutex<RefCell<Option<Inner>>>,
+}
+
+impl Button {
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(None)),
+        }
+    }
+
+    /// Takes the PA0 pin and sets up EXTI0 to fire on both edges. The EXTI0
+    /// interrupt must be unmasked in the NVIC separately.
+    pub fn init(
+        &self,
+        mut pin: PA0<Input>,
+        syscfg: &mut SysCfg,
+        exti: &mut EXTI,
+        config: GestureConfig,
+    ) {
+        pin.make_interrupt_source(syscfg);
+        pin.trigger_on_edge(exti, Edge::RisingFalling);
+        pin.enable_interrupt(exti);
+
+        cortex_m::interrupt::free(|cs| {
+            self.inner.borrow(cs).replace(Some(Inner {
+                pin,
+                gestures: Gestures::new(config),
+                sampling: true,
+                now_ms: 0,
+            }));
+        });
+    }
+
+    /// Starts sampling after the pin changed. Call this from the EXTI0
+    /// interrupt handler.
+    pub fn on_edge(&self) {
+        cortex_m::interrupt::free(|cs| {
+            if let Some(inner) = self.inner.borrow(cs).borrow_mut().as_mut() {
+                inner.pin.clear_interrupt_pending_bit();
+                inner.sampling = true;
+            }
+        });
+    }
+
+    /// Advances time by `elapsed_ms` and samples the pin if the button is
+    /// active. Call this every few milliseconds; the debouncer needs three
+    /// equal samples, so a 5 ms tick gives 15 ms of debouncing.
+    pub fn on_tick(&self, elapsed_ms: u32) {
+        cortex_m::interrupt::free(|cs| {
+            let mut inner = self.inner.borrow(cs).borrow_mut();
+            let Some(inner) = inner.as_mut() else {
+                return;
+            };
+            inner.now_ms = inner.now_ms.wrapping_add(elapsed_ms);
+            if inner.sampling {
+                // B1 pulls PA0 high when pressed.
+                inner.gestures.update(inner.pin.is_high(), inner.now_ms);
+                inner.sampling = !inner.gestures.is_idle();
+            }
+        });
+    }
+
+    /// Whether the tick is still sampling the pin, so it must keep running,
+    /// e.g. SysTick shouldn't be stopped by entering Stop mode.
+    pub fn is_sampling(&self) -> bool {
+        cortex_m::interrupt::free(|cs| {
+            self.inner
+                .borrow(cs)
+                .borrow()
+                .as_ref()
+                .is_some_and(|inner| inner.sampling)
+        })
+    }
+
+    /// Takes the oldest button event, if any.
+    pub fn next_event(&self) -> Option<ButtonEvent> {
+        cortex_m::interrupt::free(|cs| {
+            self.inner
+                .borrow(cs)
+                .borrow_mut()
+                .as_mut()
+                .and_then(|inner| inner.gestures.next_event())
+        })
+    }
+}
+
+impl Default for Button {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,122 @@
+//! HardFault crash capture.
+//!
+//! The HardFault handler decodes why the core faulted, logs it through defmt
+//! and saves a [`CrashRecord`] in the `.uninit` RAM section, which isn't
+//! zeroed on reset. Without a debugger attached it then resets the board, and
+//! [`report_previous_crash`] picks the record up on the next boot.
+
+use core::mem::MaybeUninit;
+
+use cortex_m::peripheral::{DCB, SCB};
+use cortex_m_rt::ExceptionFrame;
+use stm32f4d_core::fault::{CrashRecord, FaultStatus, StackedRegisters};
+
+#[unsafe(link_section = ".uninit.CRASH_RECORD")]
+static mut CRASH_RECORD: MaybeUninit<CrashRecord> = MaybeUninit::uninit();
+
+/// Records and logs a HardFault, then exits to the debugger or resets.
+///
+/// The HardFault a panic raises to stop under a debugger is passed straight
+/// through, so the next boot only reports the panic.
+pub(crate) fn handle_hard_fault(frame: &ExceptionFrame) -> ! {
+    // A panic handing over to the debugger, which already saved a record.
+    if crate::panic::is_halting() {
+        semihosting::process::exit(1);
+    }
+
+    let registers = StackedRegisters {
+        r0: frame.r0(),
+        r1: frame.r1(),
+        r2: frame.r2(),
+        r3: frame.r3(),
+        r12: frame.r12(),
+        lr: frame.lr(),
+        pc: frame.pc(),
+        xpsr: frame.xpsr(),
+    };
+
+    // SAFETY: Reading the fault status registers has no side effects.
+    let scb = unsafe { &*SCB::PTR };
+    let status = FaultStatus {
+        cfsr: scb.cfsr.read(),
+        hfsr: scb.hfsr.read(),
+        mmfar: scb.mmfar.read(),
+        bfar: scb.bfar.read(),
+    };
+
+    let record = CrashRecord::new(registers, status);
+    // SAFETY: Nothing else runs once we're in the HardFault handler.
+    unsafe { (&raw mut CRASH_RECORD).write_volatile(MaybeUninit::new(record)) };
+
+    defmt::error!("HardFault!");
+    log_crash(&record);
+
+    if DCB::is_debugger_attached() {
+        // Make a semihosting-capable debug tool exit with an error.
+        semihosting::process::exit(1);
+    } else {
+        // Reset so the crash can be reported on the next boot.
+        SCB::sys_reset();
+    }
+}
+
+/// Returns the crash saved before the last reset, if any, and clears it so
+/// it's only reported once.
+pub fn take_crash_record() -> Option<CrashRecord> {
+    // SAFETY: Called from thread mode at startup; the handler that writes the
+    // record never returns. Any bit pattern is a valid `CrashRecord`, and
+    // garbage left in RAM at power-on fails the validity check.
+    unsafe {
+        let ptr = &raw mut CRASH_RECORD;
+        let record = ptr.read_volatile().assume_init();
+        if !record.is_valid() {
+            return None;
+        }
+        let mut cleared = record;
+        cleared.clear();
+        ptr.write_volatile(MaybeUninit::new(cleared));
+        Some(record)
+    }
+}
+
+/// Logs the crash saved before the last reset, if any, and returns it.
+pub fn report_previous_crash() -> Option<CrashRecord> {
+    let record = take_crash_record()?;
+    defmt::warn!("Recovered from a HardFault before the last reset:");
+    log_crash(&record);
+    Some(record)
+}
+
+fn log_crash(record: &CrashRecord) {
+    let regs = &record.registers;
+    let status = &record.status;
+
+    defmt::error!(
+        "  PC {=u32:#010x}  LR {=u32:#010x}  xPSR {=u32:#010x}",
+        regs.pc,
+        regs.lr,
+        regs.xpsr
+    );
+    defmt::error!(
+        "  R0 {=u32:#010x}  R1 {=u32:#010x}  R2 {=u32:#010x}  R3 {=u32:#010x}  R12 {=u32:#010x}",
+        regs.r0,
+        regs.r1,
+        regs.r2,
+        regs.r3,
+        regs.r12
+    );
+    defmt::error!(
+        "  CFSR {=u32:#010x}  HFSR {=u32:#010x}",
+        status.cfsr,
+        status.hfsr
+    );
+    for reason in status.reasons() {
+        defmt::error!("  - {=str}", reason.description());
+    }
+    if let Some(addr) = status.mem_manage_address() {
+        defmt::error!("  MemManage fault address {=u32:#010x}", addr);
+    }
+    if let Some(addr) = status.bus_fault_address() {
+        defmt::error!("  Bus fault address {=u32:#010x}", addr);
+    }
+}
@@ -0,0 +1,50 @@
+//! The four user LEDs on PD12–PD15.
+//!
+//! Indexes match the channels of a [`Player`](stm32f4d_core::pattern::Player),
+//! so its levels can be passed straight to [`Leds::set`].
+
+use stm32f4xx_hal::gpio::{ErasedPin, Output, PD12, PD13, PD14, PD15};
+
+pub const GREEN: usize = 0;
+pub const ORANGE: usize = 1;
+pub const RED: usize = 2;
+pub const BLUE: usize = 3;
+
+/// Number of user LEDs.
+pub const COUNT: usize = 4;
+
+pub struct Leds {
+    pins: [ErasedPin<Output>; COUNT],
+}
+
+impl Leds {
+    /// Takes the LED pins as push-pull outputs, all off.
+    pub fn new(pd12: PD12, pd13: PD13, pd14: PD14, pd15: PD15) -> Self {
+        let mut leds = Self {
+            pins: [
+                pd12.into_push_pull_output().erase(),
+                pd13.into_push_pull_output().erase(),
+                pd14.into_push_pull_output().erase(),
+                pd15.into_push_pull_output().erase(),
+            ],
+        };
+        leds.set([false; COUNT]);
+        leds
+    }
+
+    /// Turns each LED on or off.
+    pub fn set(&mut self, levels: [bool; COUNT]) {
+        for (led, on) in levels.into_iter().enumerate() {
+            self.set_one(led, on);
+        }
+    }
+
+    /// Turns LED `led` on or off.
+    pub fn set_one(&mut self, led: usize, on: bool) {
+        self.pins[led].set_state(on.into());
+    }
+
+    pub fn toggle(&mut self, led: usize) {
+        self.pins[led].toggle();
+    }
+}
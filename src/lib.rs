@@ -1,20 +1,48 @@
 //! Adapted from code generated by Knurling app template.
+//!
+//! The runtime pieces here (logger, panic and fault handlers) only make sense
+//! on the board, so they're gated behind the embedded target. Logic that can
+//! be tested on the host lives in the `stm32f4d-core` crate.
 
 #![no_main]
 #![no_std]
 
+#[cfg(target_os = "none")]
 use defmt_rtt as _; // global logger
 
+#[cfg(target_os = "none")]
 use stm32f4xx_hal as _; // memory layout
 
-use panic_probe as _;
+pub mod acquisition;
+pub mod adc_dma;
+pub mod button;
+pub mod fault;
+pub mod leds;
+pub mod panic;
+pub mod power;
+pub mod profile;
+pub mod pwm_leds;
+pub mod reset;
+pub mod rtc;
+pub mod scheduler;
+pub mod uart_tx;
+pub mod watchdog;
 
+#[cfg(target_os = "none")]
 mod tools {
-    // same panicking *behavior* as `panic-probe` but doesn't print a panic message
-    // this prevents the panic message being printed *twice* when `defmt::panic` is invoked
+    // same panicking *behavior* as our `#[panic_handler]` but doesn't print a panic message
+    // this prevents the panic message being printed *twice* when `defmt::panic` is invoked.
+    // defmt only sends the message to the host, so all we can save is that it happened
     #[defmt::panic_handler]
     fn panic() -> ! {
-        cortex_m::asm::udf()
+        cortex_m::interrupt::disable();
+        crate::panic::save(stm32f4d_core::panic_record::PanicRecord::new(
+            "",
+            0,
+            0,
+            "defmt panic (message only in the defmt log)",
+        ));
+        crate::panic::halt()
     }
 
     /// Terminates the application and makes a semihosting-capable debug tool exit
@@ -25,16 +53,18 @@ mod tools {
 
     /// Hardfault handler.
     ///
-    /// Terminates the application and makes a semihosting-capable debug tool exit
-    /// with an error. This seems better than the default, which is to spin in a
-    /// loop.
+    /// Logs and saves the cause of the fault, then makes a semihosting-capable
+    /// debug tool exit with an error, or resets the board if no debugger is
+    /// attached. See `fault.rs`. This seems better than the default, which is
+    /// to spin in a loop.
     #[cortex_m_rt::exception]
-    unsafe fn HardFault(_frame: &cortex_m_rt::ExceptionFrame) -> ! {
-        semihosting::process::exit(1);
+    unsafe fn HardFault(frame: &cortex_m_rt::ExceptionFrame) -> ! {
+        crate::fault::handle_hard_fault(frame)
     }
 }
 
 // Re-export for ease of use.
+#[cfg(target_os = "none")]
 pub use tools::*;
 
 // defmt-test 0.3.0 has the limitation that this `#[tests]` attribute can only be used
@@ -0,0 +1,106 @@
+//! Panic capture.
+//!
+//! This replaces `panic-probe`'s handler: it still logs the panic through
+//! defmt, but also saves the location and (truncated) message in a
+//! [`PanicRecord`] in the `.uninit` RAM section, which isn't zeroed on reset.
+//! Without a debugger attached it then resets the board, and
+//! [`report_previous_panic`] picks the record up on the next boot.
+
+use core::{
+    mem::MaybeUninit,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use cortex_m::peripheral::{DCB, SCB};
+use stm32f4d_core::panic_record::PanicRecord;
+
+#[unsafe(link_section = ".uninit.PANIC_RECORD")]
+static mut PANIC_RECORD: MaybeUninit<PanicRecord> = MaybeUninit::uninit();
+
+// Set when `halt` raises a HardFault on purpose, so the HardFault handler
+// doesn't record it as a crash on top of the panic.
+static HALTING: AtomicBool = AtomicBool::new(false);
+
+#[cfg(target_os = "none")]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    static PANICKED: AtomicBool = AtomicBool::new(false);
+
+    cortex_m::interrupt::disable();
+
+    // Don't record or log a panic that happens while handling another one.
+    if !PANICKED.swap(true, Ordering::Relaxed) {
+        defmt::error!("{}", defmt::Display2Format(info));
+        let record = match info.location() {
+            Some(loc) => PanicRecord::new(loc.file(), loc.line(), loc.column(), info.message()),
+            None => PanicRecord::new("", 0, 0, info.message()),
+        };
+        save(record);
+    }
+
+    halt()
+}
+
+/// Saves `record` to be reported after the next reset.
+pub(crate) fn save(record: PanicRecord) {
+    // SAFETY: Only called with interrupts disabled from handlers that never
+    // return.
+    unsafe { (&raw mut PANIC_RECORD).write_volatile(MaybeUninit::new(record)) };
+}
+
+/// Hands over to the debugger if there is one, otherwise resets.
+pub(crate) fn halt() -> ! {
+    if DCB::is_debugger_attached() {
+        // Like `panic-probe`, trigger a HardFault so probe-rs prints a
+        // backtrace and exits.
+        HALTING.store(true, Ordering::Relaxed);
+        hard_fault()
+    } else {
+        // Reset so the panic can be reported on the next boot.
+        SCB::sys_reset()
+    }
+}
+
+/// Whether a HardFault was raised by [`halt`] rather than a real fault.
+pub(crate) fn is_halting() -> bool {
+    HALTING.load(Ordering::Relaxed)
+}
+
+// From `panic-probe`: an undefined instruction raises a HardFault, as long
+// as the UsageFault exception is disabled.
+fn hard_fault() -> ! {
+    // SAFETY: Only clears USGFAULTENA in SHCSR.
+    unsafe {
+        let scb = &*SCB::PTR;
+        scb.shcsr.modify(|shcsr| shcsr & !(1 << 18));
+    }
+    cortex_m::asm::udf()
+}
+
+/// Returns the panic saved before the last reset, if any, and clears it so
+/// it's only reported once.
+pub fn take_panic_record() -> Option<PanicRecord> {
+    // SAFETY: Called from thread mode at startup; the handler that writes the
+    // record never returns. Any bit pattern is a valid `PanicRecord`, and
+    // garbage left in RAM at power-on fails the validity check.
+    unsafe {
+        let ptr = &raw mut PANIC_RECORD;
+        let record = ptr.read_volatile().assume_init();
+        if !record.is_valid() {
+            return None;
+        }
+        let mut cleared = record;
+        cleared.clear();
+        ptr.write_volatile(MaybeUninit::new(cleared));
+        Some(record)
+    }
+}
+
+/// Logs the panic saved before the last reset, if any, and returns it so it
+/// can also be sent over the UART.
+pub fn report_previous_panic() -> Option<PanicRecord> {
+    let record = take_panic_record()?;
+    defmt::warn!("Recovered from a panic before the last reset:");
+    defmt::error!("  {}", record);
+    Some(record)
+}
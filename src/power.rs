@@ -0,0 +1,227 @@
+//! Sleep and Stop mode power management.
+//!
+//! [`PowerManager::idle`] puts the chip in Sleep or Stop mode until an
+//! interrupt, optionally arming the RTC wakeup timer first, and adds the time
+//! spent to [`PowerStats`]. The RTC also timestamps each mode change, since
+//! SysTick and the other timers stop in Stop mode.
+//!
+//! In Stop mode only EXTI lines wake the chip: a button on PA0, the RTC
+//! wakeup timer on line 22, or a falling edge on a UART RX pin set up with
+//! [`PowerManager::wake_on_uart_rx`]. The USART itself is stopped, so the
+//! byte that wakes the chip is lost. The chip wakes running from the 16 MHz
+//! HSI, so the HSE and PLL are restarted before any interrupt handler runs.
+
+use cortex_m::peripheral::{DCB, NVIC, SCB};
+use stm32f4d_core::power::{PowerMode, PowerStats, day_elapsed_ms};
+use stm32f4xx_hal::{
+    gpio::{Edge, ExtiPin, PinExt},
+    pac::{self, DBGMCU, EXTI, PWR, RCC, RTC},
+    prelude::*,
+    rtc::Event,
+    syscfg::SysCfg,
+};
+
+use crate::rtc::RtcClock;
+
+/// Enters low-power modes from the idle loop, timed by the RTC.
+///
+/// ```ignore
+/// static CLOCK: RtcClock = RtcClock::new();
+///
+/// CLOCK.init(dp.RTC, &mut dp.PWR, &clocks, LSE_TIMEOUT_MS);
+/// let mut power = PowerManager::new(&CLOCK, cp.SCB, &dp.PWR, &dp.DBGMCU, &mut dp.EXTI);
+///
+/// loop {
+///     let mode = policy.choose(BUTTON.is_sampling(), Some(next_ms));
+///     power.idle(mode, Some(next_ms));
+/// }
+///
+/// #[interrupt]
+/// fn RTC_WKUP() {
+///     stm32f4d::power::on_rtc_wakeup();
+/// }
+/// ```
+pub struct PowerManager {
+    clock: &'static RtcClock,
+    scb: SCB,
+    stats: PowerStats,
+    // RTC time of day when we last woke.
+    last_ms: u32,
+    // EXTI line of the UART RX pin, enabled only during Stop.
+    uart_line: Option<u8>,
+}
+
+impl PowerManager {
+    /// Sets up Stop mode to use the low-power regulator, and the RTC wakeup
+    /// interrupt. Panics if `clock` hasn't been initialised.
+    ///
+    /// With a debugger attached the debug clocks are kept on in Sleep and
+    /// Stop, so RTT logging and the probe keep working; this uses more
+    /// power than a free-running board.
+    pub fn new(
+        clock: &'static RtcClock,
+        scb: SCB,
+        pwr: &PWR,
+        dbgmcu: &DBGMCU,
+        exti: &mut EXTI,
+    ) -> Self {
+        // Stop rather than Standby, with the regulator in low-power mode.
+        pwr.cr()
+            .modify(|_, w| w.pdds().clear_bit().lpds().set_bit());
+        if DCB::is_debugger_attached() {
+            dbgmcu
+                .cr()
+                .modify(|_, w| w.dbg_sleep().set_bit().dbg_stop().set_bit());
+        }
+        clock
+            .with(|rtc| rtc.listen(exti, Event::Wakeup))
+            .expect("RTC clock not initialised");
+
+        Self {
+            clock,
+            scb,
+            stats: PowerStats::new(),
+            last_ms: clock.time_of_day_ms().unwrap_or_default(),
+            uart_line: None,
+        }
+    }
+
+    /// Lets a falling edge on a UART RX pin, e.g. the start bit of a
+    /// received byte, wake the chip from Stop. Call this before handing the
+    /// pin to the serial driver. The EXTI interrupt for the pin's line must
+    /// have a handler that calls [`clear_wakeup_line`].
+    pub fn wake_on_uart_rx<P: ExtiPin + PinExt>(
+        &mut self,
+        pin: &mut P,
+        syscfg: &mut SysCfg,
+        exti: &mut EXTI,
+    ) {
+        pin.make_interrupt_source(syscfg);
+        pin.trigger_on_edge(exti, Edge::Falling);
+        // Masked until we enter Stop, so received bytes don't interrupt.
+        pin.disable_interrupt(exti);
+        self.uart_line = Some(pin.pin_id());
+        // SAFETY: The handler only clears the pending bit.
+        unsafe { NVIC::unmask(exti_interrupt(pin.pin_id())) };
+    }
+
+    /// Idles in `mode` until an interrupt, or for at most `wakeup_ms` if
+    /// given, up to about 71 minutes. [`PowerMode::Run`] returns straight
+    /// away.
+    ///
+    /// Returns the time spent idle, for catching up clocks that stop in Stop
+    /// mode, like SysTick.
+    pub fn idle(&mut self, mode: PowerMode, wakeup_ms: Option<u32>) -> u32 {
+        let start_ms = self.now_ms();
+        self.stats
+            .record(PowerMode::Run, day_elapsed_ms(self.last_ms, start_ms));
+        if mode == PowerMode::Run {
+            self.last_ms = start_ms;
+            return 0;
+        }
+
+        if let Some(ms) = wakeup_ms {
+            self.clock
+                .with(|rtc| rtc.enable_wakeup(ms.saturating_mul(1000).micros().into()));
+            // SAFETY: The binary's RTC_WKUP handler clears the flags.
+            unsafe { NVIC::unmask(pac::Interrupt::RTC_WKUP) };
+        }
+        match mode {
+            PowerMode::Sleep => cortex_m::asm::wfi(),
+            _ => {
+                self.stop();
+                self.clock.resync();
+            }
+        }
+        if wakeup_ms.is_some() {
+            self.clock.with(|rtc| rtc.disable_wakeup());
+        }
+
+        let end_ms = self.now_ms();
+        let idle_ms = day_elapsed_ms(start_ms, end_ms);
+        self.stats.record(mode, idle_ms);
+        self.last_ms = end_ms;
+        idle_ms
+    }
+
+    pub fn stats(&self) -> &PowerStats {
+        &self.stats
+    }
+
+    /// Milliseconds since midnight on the RTC.
+    pub fn now_ms(&self) -> u32 {
+        self.clock.time_of_day_ms().unwrap_or_default()
+    }
+
+    fn stop(&mut self) {
+        // SAFETY: Only touches RCC_CR, RCC_CFGR and EXTI_IMR, with
+        // interrupts disabled, and puts them back as they were.
+        let rcc = unsafe { &*RCC::ptr() };
+        let exti = unsafe { &*EXTI::ptr() };
+
+        // With interrupts disabled, the wakeup interrupt stays pending until
+        // the clocks are back, so no handler runs on the HSI.
+        cortex_m::interrupt::free(|_| {
+            let cr = rcc.cr().read();
+            let cfgr = rcc.cfgr().read().bits();
+            if let Some(line) = self.uart_line {
+                exti.imr()
+                    .modify(|r, w| unsafe { w.bits(r.bits() | 1 << line) });
+            }
+
+            self.scb.set_sleepdeep();
+            cortex_m::asm::dsb();
+            cortex_m::asm::wfi();
+            self.scb.clear_sleepdeep();
+
+            if let Some(line) = self.uart_line {
+                exti.imr()
+                    .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << line)) });
+            }
+            // The PLL configuration and prescalers survive Stop, but the
+            // oscillators are off and the system clock is back on the HSI.
+            if cr.hseon().bit_is_set() {
+                rcc.cr().modify(|_, w| w.hseon().set_bit());
+                while rcc.cr().read().hserdy().bit_is_clear() {}
+            }
+            if cr.pllon().bit_is_set() {
+                rcc.cr().modify(|_, w| w.pllon().set_bit());
+                while rcc.cr().read().pllrdy().bit_is_clear() {}
+            }
+            rcc.cfgr().write(|w| unsafe { w.bits(cfgr) });
+            while rcc.cfgr().read().sws().bits() != rcc.cfgr().read().sw().bits() {}
+        });
+    }
+}
+
+/// Clears the RTC wakeup timer flags. Call this from the `RTC_WKUP`
+/// interrupt handler.
+pub fn on_rtc_wakeup() {
+    // SAFETY: Clearing WUTF and EXTI line 22 doesn't touch anything the
+    // power manager's RTC driver relies on.
+    let rtc = unsafe { &*RTC::ptr() };
+    rtc.isr().modify(|_, w| w.wutf().clear_bit());
+    clear_wakeup_line(22);
+}
+
+/// Clears the pending bit of EXTI `line`, e.g. from the handler for a UART RX
+/// wakeup pin.
+pub fn clear_wakeup_line(line: u8) {
+    // SAFETY: EXTI_PR bits are cleared by writing one, so this doesn't
+    // affect other lines.
+    let exti = unsafe { &*EXTI::ptr() };
+    exti.pr().write(|w| unsafe { w.bits(1 << line) });
+}
+
+// The NVIC interrupt for a GPIO EXTI line.
+fn exti_interrupt(line: u8) -> pac::Interrupt {
+    match line {
+        0 => pac::Interrupt::EXTI0,
+        1 => pac::Interrupt::EXTI1,
+        2 => pac::Interrupt::EXTI2,
+        3 => pac::Interrupt::EXTI3,
+        4 => pac::Interrupt::EXTI4,
+        5..=9 => pac::Interrupt::EXTI9_5,
+        _ => pac::Interrupt::EXTI15_10,
+    }
+}
@@ -0,0 +1,132 @@
+//! Cycle-accurate profiling with the DWT cycle counter.
+//!
+//! Code sections register by name and are timed in CPU cycles, from
+//! `CYCCNT`, which counts at the system clock rate and wraps every 51 seconds
+//! at 84 MHz. The statistics are [`Profile`] in the core crate, and can be
+//! logged over defmt or written out over the UART on demand.
+//!
+//! A section's time includes any higher-priority interrupts that preempt it.
+
+use core::{cell::RefCell, fmt};
+
+use cortex_m::{
+    interrupt::Mutex,
+    peripheral::{DCB, DWT},
+};
+use stm32f4d_core::profile::{Profile, SectionId};
+
+/// Timing statistics for up to `N` sections, meant to live in a `static`.
+///
+/// ```ignore
+/// static PROFILER: Profiler<2> = Profiler::new();
+///
+/// PROFILER.enable(&mut cp.DCB, &mut cp.DWT);
+/// let section = PROFILER.register("dma");
+///
+/// // In the profiled handler:
+/// let _timing = PROFILER.start(section);
+///
+/// // On demand, with the cycles available per run:
+/// PROFILER.log(84_000);
+/// ```
+pub struct Profiler<const N: usize> {
+    profile: Mutex<RefCell<Profile<N>>>,
+}
+
+impl<const N: usize> Profiler<N> {
+    pub const fn new() -> Self {
+        Self {
+            profile: Mutex::new(RefCell::new(Profile::new())),
+        }
+    }
+
+    /// Starts the cycle counter.
+    pub fn enable(&self, dcb: &mut DCB, dwt: &mut DWT) {
+        dcb.enable_trace();
+        DWT::unlock();
+        dwt.enable_cycle_counter();
+    }
+
+    /// Adds a section to time.
+    ///
+    /// Panics if more than `N` sections register.
+    pub fn register(&self, name: &'static str) -> SectionId {
+        cortex_m::interrupt::free(|cs| self.profile.borrow(cs).borrow_mut().register(name).unwrap())
+    }
+
+    /// Times `section` until the returned guard is dropped.
+    pub fn start(&self, section: SectionId) -> Timing<'_, N> {
+        Timing {
+            profiler: self,
+            section,
+            start: DWT::cycle_count(),
+        }
+    }
+
+    /// Runs `f` and records how long it took as `section`.
+    pub fn measure<R>(&self, section: SectionId, f: impl FnOnce() -> R) -> R {
+        let _timing = self.start(section);
+        f()
+    }
+
+    /// Clears the statistics, e.g. to measure a new configuration.
+    pub fn reset(&self) {
+        cortex_m::interrupt::free(|cs| self.profile.borrow(cs).borrow_mut().reset());
+    }
+
+    /// Logs each section over defmt, with the longest run as a share of
+    /// `budget_cycles`.
+    pub fn log(&self, budget_cycles: u32) {
+        let profile = self.snapshot();
+        for (name, stats) in profile.sections() {
+            defmt::info!(
+                "{=str}: n {=u32}, min {=u32}, mean {=u32}, max {=u32} cycles ({=u32}% of {=u32}), histogram {}",
+                name,
+                stats.count(),
+                stats.min(),
+                stats.mean(),
+                stats.max(),
+                stats.max_percent_of(budget_cycles),
+                budget_cycles,
+                stats.histogram()
+            );
+        }
+    }
+
+    /// Writes each section as text, e.g. to a UART writer.
+    pub fn report<W: fmt::Write>(&self, out: &mut W, budget_cycles: u32) -> fmt::Result {
+        self.snapshot().write_report(out, budget_cycles)
+    }
+
+    // Copies the statistics so they can be formatted with interrupts on.
+    fn snapshot(&self) -> Profile<N> {
+        cortex_m::interrupt::free(|cs| *self.profile.borrow(cs).borrow())
+    }
+}
+
+impl<const N: usize> Default for Profiler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Guard returned by [`Profiler::start`] that records the section's time when
+/// dropped.
+pub struct Timing<'a, const N: usize> {
+    profiler: &'a Profiler<N>,
+    section: SectionId,
+    start: u32,
+}
+
+impl<const N: usize> Drop for Timing<'_, N> {
+    fn drop(&mut self) {
+        let cycles = DWT::cycle_count().wrapping_sub(self.start);
+        cortex_m::interrupt::free(|cs| {
+            self.profiler
+                .profile
+                .borrow(cs)
+                .borrow_mut()
+                .record(self.section, cycles)
+        });
+    }
+}
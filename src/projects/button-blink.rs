@@ -1,23 +1,60 @@
 //! Modified from :
 //!  `https://blog.theembeddedrustacean.com/stm32f4-embedded-rust-at-the-hal-gpio-button-controlled-blinking
+//!
+//! Instead of spinning in a delay loop, the LED toggle and the button
+//! sampling are jobs on the SysTick scheduler, and the board idles in Stop
+//! mode between toggles. The RTC wakeup timer wakes it for the next toggle
+//! and the button's EXTI0 interrupt for a press. SysTick stops in Stop, so
+//! the scheduler is caught up with the time the RTC measured. While the
+//! button is being debounced we only Sleep, so SysTick keeps running. A long
+//! press logs the time spent in each power mode.
 
 #![no_std]
 #![no_main]
 
-use cortex_m_rt::entry;
+use cortex_m_rt::{entry, exception};
 
 use stm32f4d as _; // global logger + panicking-behavior + memory layout
 
+use stm32f4d::{button::Button, power::PowerManager, rtc::RtcClock, scheduler::Scheduler};
+use stm32f4d_core::{
+    button::{ButtonEvent, GestureConfig},
+    power::{IdlePolicy, PowerMode},
+};
 use stm32f4xx_hal::{
-    gpio::Pin,
-    pac::{self},
+    pac::{self, interrupt},
     prelude::*,
 };
 
+// How often the button is sampled while it's active.
+const BUTTON_TICK_MS: u32 = 5;
+// Time between toggles at startup, how much each press takes off it, and
+// the fastest before going back to the start.
+const INITIAL_DELAY_MS: u32 = 500;
+const DELAY_STEP_MS: u32 = 125;
+const MIN_DELAY_MS: u32 = 125;
+// How long to wait for an LSE crystal to start before using the LSI.
+const LSE_TIMEOUT_MS: u32 = 2000;
+
+// Debounced button, woken by EXTI0 and sampled by a scheduler job.
+static BUTTON: Button = Button::new();
+// Times the button sampling and the LED.
+static SCHEDULER: Scheduler<2> = Scheduler::new();
+// Calendar clock, which keeps running in Stop mode.
+static CLOCK: RtcClock = RtcClock::new();
+
 #[entry]
 fn main() -> ! {
     // Setup handler for device peripherals
-    let dp = pac::Peripherals::take().unwrap();
+    let mut dp = pac::Peripherals::take().unwrap();
+    let cp = cortex_m::Peripherals::take().unwrap();
+
+    // Say why we booted.
+    stm32f4d::reset::report_reset_cause();
+
+    // Log any HardFault or panic saved before the last reset.
+    stm32f4d::fault::report_previous_crash();
+    stm32f4d::panic::report_previous_panic();
 
     // Configure the LED pin as a push pull ouput and obtain handler.
     // On the Nucleo FR401 theres an on-board LED connected to pin PA5.
@@ -28,41 +65,95 @@ fn main() -> ! {
     // On the Nucleo FR401 there is a button connected to pin PC13.
     // Pin is input by default
     let gpioa = dp.GPIOA.split();
-    let button = gpioa.pa0;
+    let mut syscfg = dp.SYSCFG.constrain();
+    BUTTON.init(gpioa.pa0, &mut syscfg, &mut dp.EXTI, GestureConfig::DEFAULT);
+    // SAFETY: The handler only touches state behind a mutex.
+    unsafe { pac::NVIC::unmask(pac::Interrupt::EXTI0) };
+
+    // Run from the 8 MHz crystal, which the power manager restarts after
+    // each Stop.
+    let clocks = dp.RCC.constrain().cfgr.use_hse(8.MHz()).freeze();
 
-    // Create and initialize a delay variable to manage delay loop
-    let mut del_var = 10_0000_u32;
+    // Millisecond clock for the scheduler.
+    SCHEDULER.start_systick(cp.SYST, &clocks);
+
+    // The RTC keeps time in Stop mode. Most boards have no LSE crystal
+    // fitted, so it usually runs from the LSI, which is only accurate to
+    // several percent.
+    let source = CLOCK.init(dp.RTC, &mut dp.PWR, &clocks, LSE_TIMEOUT_MS);
+    defmt::info!("RTC running from the {}", source);
+    let mut power = PowerManager::new(&CLOCK, cp.SCB, &dp.PWR, &dp.DBGMCU, &mut dp.EXTI);
+    let policy = IdlePolicy::DEFAULT;
+
+    let mut delay_ms = INITIAL_DELAY_MS;
+    let button_job = SCHEDULER.every(BUTTON_TICK_MS);
+    let blink_job = SCHEDULER.every(delay_ms);
 
     // Initialize LED to on or off
     led.set_low();
 
     // Application Loop
     loop {
-        // Call delay function and update delay variable once done
-        del_var = loop_delay(del_var, &button);
+        // EXTI0 woke the button, so sample it until it settles.
+        if BUTTON.is_sampling() && !SCHEDULER.is_running(button_job) {
+            SCHEDULER.start(button_job, BUTTON_TICK_MS);
+        }
 
-        // Toggle LED
-        led.toggle();
-    }
-}
+        while let Some(job) = SCHEDULER.next_due() {
+            if job == blink_job {
+                led.toggle();
+                continue;
+            }
 
-// Delay Function
-fn loop_delay<const P: char, const N: u8>(mut del: u32, but: &Pin<P, N>) -> u32 {
-    let loop_bound = del;
-    // Loop for until value of del
-    for _i in 1..loop_bound {
-        // Check if button got pressed
-        if but.is_low() {
-            // If button pressed decrease the delay value
-            del -= 2_5000_u32;
-            // If updated delay value reaches zero then reset it back to starting value
-            if del < 2_5000 {
-                del = 10_0000_u32;
+            BUTTON.on_tick(BUTTON_TICK_MS);
+            while let Some(event) = BUTTON.next_event() {
+                match event {
+                    // Blink faster, going back to the start after the
+                    // fastest, and toggle straight away.
+                    ButtonEvent::Press => {
+                        delay_ms = delay_ms.saturating_sub(DELAY_STEP_MS);
+                        if delay_ms < MIN_DELAY_MS {
+                            delay_ms = INITIAL_DELAY_MS;
+                        }
+                        SCHEDULER.set_period(blink_job, delay_ms);
+                        SCHEDULER.start(blink_job, 0);
+                    }
+                    ButtonEvent::LongPress => defmt::info!("Power: {}", power.stats()),
+                    _ => {}
+                }
+            }
+            // Let the board stop until the next edge.
+            if !BUTTON.is_sampling() {
+                SCHEDULER.stop(button_job);
             }
-            // Exit function returning updated delay value if button pressed
-            return del;
+        }
+
+        // Sleep until the next job, or until the button wakes us. Sleep
+        // doesn't need the RTC, since SysTick wakes it every millisecond.
+        let idle_ms = SCHEDULER.idle_ms();
+        let mode = policy.choose(BUTTON.is_sampling(), idle_ms);
+        let wakeup_ms = idle_ms.filter(|_| mode == PowerMode::Stop);
+        let idle_ms = power.idle(mode, wakeup_ms);
+        if mode == PowerMode::Stop {
+            SCHEDULER.on_tick(idle_ms);
         }
     }
-    // Exit function returning original delay value if button no pressed (for loop ending naturally)
-    del
+}
+
+// Wakes the button driver when PA0 changes.
+#[interrupt]
+fn EXTI0() {
+    BUTTON.on_edge();
+}
+
+// Wakes the main loop for the next toggle.
+#[interrupt]
+fn RTC_WKUP() {
+    stm32f4d::power::on_rtc_wakeup();
+}
+
+// Advances the scheduler's clock.
+#[exception]
+fn SysTick() {
+    SCHEDULER.on_tick(1);
 }
@@ -3,6 +3,33 @@
 //! We learned from the Embedded Rustacean example available here:
 //!  https://dev.to/theembeddedrustacean/stm32f4-embedded-rust-at-the-hal-the-rtic-framework-1j9i
 //!
+//! TIM2's update event, routed to its TRGO output, starts an ADC conversion
+//! of both mics, VREFINT and the temperature sensor at the sample rate, 1 kHz
+//! by default, with no interrupt. Circular DMA fills the two halves of a
+//! buffer of `BLOCK_FRAMES` frames each in turn, so the CPU only wakes when a
+//! half is full. The DMA interrupt sends that block over an `rtic-sync`
+//! channel to the async `process` task, which updates the LEDs and queues the
+//! output, while the DMA fills the other half. The interrupt handler stays
+//! short, and processing runs at a lower priority.
+//!
+//! Frames carry the RTC time of day and text lines start with the date and
+//! time, which takes the UART up to 230400 baud.
+//!
+//! The mics are a differential pair, so by default the output is their
+//! calibrated difference and common mode rather than the raw channels; see
+//! `SIGNAL`. Holding B1 calibrates the pair: keep quiet for the first second,
+//! while the offsets are measured, then play a steady tone for the next,
+//! while the gains are matched.
+//!
+//! The mic pins, resolution, sample time, ADC clock and sample rate can be
+//! changed from the terminal; type `help` for the commands. Each change is
+//! checked against the ADC's limits before sampling restarts with it, and
+//! blocks sampled before the restart are dropped. Replies always go to
+//! defmt, but only go out over the UART with text output, where they can't
+//! be mistaken for frames.
+//!
+//! The tasks are timed with the DWT cycle counter. Pressing B1 logs how long
+//! each takes, against the cycles available per block.
 
 #![no_main]
 #![no_std]
@@ -10,46 +37,243 @@
 // For panic_handler.
 use stm32f4d as _;
 
-#[rtic::app(device = stm32f4xx_hal::pac, peripherals = true)]
+use stm32f4d::{
+    acquisition::AnalogInputs, adc_dma::CircularAdc, button::Button, profile::Profiler,
+    rtc::RtcClock, uart_tx::BufferedTx, watchdog::Watchdog,
+};
+use stm32f4xx_hal::pac::USART1;
+
+// Queued UART output, drained by the USART1 interrupt so that the DMA
+// handler never waits on the UART.
+static UART_TX: BufferedTx<USART1, 512> = BufferedTx::new();
+
+// Calendar clock for timestamps.
+static CLOCK: RtcClock = RtcClock::new();
+
+// Resets the board if sampling stalls, e.g. a DMA transfer that never
+// completes.
+static WATCHDOG: Watchdog<2> = Watchdog::new();
+
+// Execution times of the sampling tasks.
+static PROFILER: Profiler<2> = Profiler::new();
+
+// B1: a press reports the timings, a long press calibrates the mics.
+static BUTTON: Button = Button::new();
+
+#[rtic::app(device = stm32f4xx_hal::pac, peripherals = true, dispatchers = [SPI1])]
 mod app {
     // Imports.
+    use super::{AnalogInputs, BUTTON, CLOCK, CircularAdc, PROFILER, UART_TX, WATCHDOG};
     use core::fmt::Write;
+    use rtic_monotonics::systick::prelude::*;
+    use rtic_sync::{
+        channel::{Receiver, Sender},
+        make_channel,
+    };
+    use stm32f4d::{leds, pwm_leds::PwmLeds};
+    use stm32f4d_core::{
+        acquisition::{
+            self, AcquisitionCommand, AcquisitionConfig, AnalogInput, Limits, Resolution,
+        },
+        adc_calibration::{AdcCalibration, FactoryCalibration},
+        block::{self, DmaBuffer},
+        button::{ButtonEvent, GestureConfig},
+        differential::{Calibration, Calibrator},
+        fade::{Curve, Fader},
+        fir::{self, FirQ15},
+        frame::{FrameEncoder, MAX_FRAME_LEN},
+        line::{Line, LineBuffer, LineError},
+        power::DAY_MS,
+        profile::SectionId,
+        sample::write_sample_line,
+        supervisor::TaskId,
+    };
     use stm32f4xx_hal::{
         adc::{
             Adc,
-            config::{AdcConfig, Clock, Dma, Resolution, SampleTime, Scan, Sequence},
+            config::{AdcConfig, Dma, ExternalTrigger, Scan, TriggerMode},
         },
-        dma::{PeripheralToMemory, Stream0, StreamsTuple, Transfer, config::DmaConfig},
-        gpio::{self, Output, PushPull},
-        pac::{ADC1, DMA2, TIM2, USART1},
+        dma::StreamsTuple,
+        pac::{TIM2, USART1, tim2::cr2::MMS},
         prelude::*,
-        serial::{Tx, config::Config},
-        timer::{CounterHz, Event, Flag},
+        serial::{Rx, config::Config},
+        signature::{VrefCal, VtempCal30, VtempCal110},
+        timer::CounterHz,
     };
 
-    // Alias to simplify type name; borrowed from Hiari.
-    type DMATransfer =
-        Transfer<Stream0<DMA2>, 0, Adc<ADC1>, PeripheralToMemory, &'static mut [u16; 2]>;
+    // The settings sampling starts with, for the mic calibration.
+    const DEFAULT_BITS: u32 = AcquisitionConfig::DEFAULT.resolution.bits();
+    const DEFAULT_MICS: ([AnalogInput; 2], Resolution) = (
+        AcquisitionConfig::DEFAULT.inputs,
+        AcquisitionConfig::DEFAULT.resolution,
+    );
+
+    // Frames per half of the DMA buffer. The CPU wakes once per block.
+    const BLOCK_FRAMES: usize = 8;
+    // Both mics, then VREFINT and the temperature sensor, in the order of
+    // the ADC sequence.
+    const CHANNELS: usize = 4;
+    const VREFINT: usize = 2;
+    const TEMPERATURE: usize = 3;
+    type Block = block::Block<BLOCK_FRAMES, CHANNELS>;
+    // A block and the `generation` of the settings it was sampled with.
+    type TaggedBlock = (u32, Block);
+    // Just the mics.
+    type Mics = block::Block<BLOCK_FRAMES, 2>;
+
+    // How samples are sent to the PC.
+    #[allow(dead_code)]
+    enum SampleOutput {
+        // ASCII lines like "00512 -- 01023", for watching in minicom.
+        Text,
+        // COBS framed binary with sequence numbers and CRC; see `frame.rs`.
+        Frames,
+    }
+
+    const SAMPLE_OUTPUT: SampleOutput = SampleOutput::Frames;
+
+    // What the two output channels carry.
+    #[allow(dead_code)]
+    enum Signal {
+        // Each mic as converted; capture with `--bits` set to the resolution.
+        Raw,
+        // The calibrated difference and common mode, in offset binary two
+        // bits wider than the resolution, for headroom; 12 bits by default.
+        Differential,
+        // Each mic in millivolts, scaled by the measured VDDA; capture with
+        // `--bits 12`.
+        Millivolts,
+    }
+
+    const SIGNAL: Signal = Signal::Differential;
+
+    // Whether the mics are filtered before anything else is done with them.
+    #[allow(dead_code)]
+    enum Filter {
+        None,
+        // `LOW_PASS`, on each mic.
+        LowPass,
+    }
+
+    const FILTER: Filter = Filter::None;
+
+    // A 7-tap low pass cut off at a fifth of the sample rate: a Hamming
+    // windowed sinc, with unity gain at DC so offsets are kept.
+    const LOW_PASS_TAPS: usize = 7;
+    const LOW_PASS: [i16; LOW_PASS_TAPS] = fir::coeffs_to_q15([
+        -0.00545737,
+        0.03172097,
+        0.25497236,
+        0.43752807,
+        0.25497236,
+        0.03172097,
+        -0.00545737,
+    ]);
+
+    // Bits the difference of two channels needs over the resolution, with
+    // headroom for gains above one.
+    const DIFF_EXTRA_BITS: u32 = 2;
+    // Millivolts up to 4095, for a VDDA up to the 3.6 V maximum.
+    const MILLIVOLT_BITS: u32 = 12;
+
+    // How often VDDA and the chip temperature are logged.
+    const SUPPLY_REPORT_MS: u32 = 1000;
+
+    // Fast enough for a 15 byte timestamped frame at the default 1 kHz;
+    // `LIMITS` keeps the sample rate within what it can carry.
+    const UART_BAUD: u32 = 230_400;
+    const FRAME_BYTES: u32 = 15;
+
+    // Longest console line we accept, and lines waiting for the console.
+    const LINE_LEN: usize = 40;
+    const LINE_QUEUE_LEN: usize = 2;
+
+    // How long to wait for an LSE crystal to start before using the LSI.
+    const LSE_TIMEOUT_MS: u32 = 2000;
+
+    const SYSCLK_HZ: u32 = 84_000_000;
+    // The ADC clock is divided from this.
+    const PCLK2_HZ: u32 = 21_000_000;
+
+    // Blocks waiting for the processing task. If it falls this far behind,
+    // new blocks are dropped.
+    const BLOCK_QUEUE_LEN: usize = 4;
+
+    // How often the watchdog task checks that sampling is still running.
+    const WATCHDOG_CHECK_MS: u32 = 100;
+    // The IWDG resets the board if it isn't fed for this long.
+    const WATCHDOG_TIMEOUT_MS: u32 = 500;
+    // The sampling tasks run every block; allow plenty of slack.
+    const SAMPLING_DEADLINE_MS: u32 = 100;
+
+    // What acquisition settings must stay within. Each block must come
+    // before the watchdog deadline, and the UART must keep up with frames;
+    // the ADC itself could go much faster with a shorter sample time.
+    const LIMITS: Limits = Limits {
+        pclk2_hz: PCLK2_HZ,
+        min_rate_hz: BLOCK_FRAMES as u32 * 1000 / SAMPLING_DEADLINE_MS,
+        // Ten bits on the wire per byte.
+        max_rate_hz: UART_BAUD / 10 / FRAME_BYTES,
+    };
+
+    // How often the button task samples B1 while it's active.
+    const BUTTON_TICK_MS: u32 = 5;
+
+    // Millisecond timer on SysTick, for the watchdog task.
+    systick_monotonic!(Mono, 1_000);
 
     // Resources shared between tasks
     #[shared]
     struct Shared {
-        transfer: DMATransfer,
+        // Set by a long press, taken by the processing task.
+        calibrate: bool,
+        // Changed by the console, which restarts `adc` with it.
+        config: AcquisitionConfig,
+        // Counts restarts, so blocks sampled with older settings can be
+        // told apart.
+        generation: u32,
+        adc: CircularAdc<BLOCK_FRAMES, CHANNELS>,
     }
 
     // Local resources to specific tasks (cannot be shared)
     #[local]
     struct Local {
-        led: gpio::PD13<Output<PushPull>>,
-        uart_tx: Tx<USART1>,
-        buffer: Option<&'static mut [u16; 2]>,
+        leds: PwmLeds,
+        fader: Fader<{ leds::COUNT }>,
+        inputs: AnalogInputs,
         timer: CounterHz<TIM2>,
+        adc_calibration: AdcCalibration,
+        filters: [FirQ15<LOW_PASS_TAPS>; 2],
+        rx: Rx<USART1>,
+        lines: Sender<'static, Result<Line<LINE_LEN>, LineError>, LINE_QUEUE_LEN>,
+        blocks: Sender<'static, TaggedBlock, BLOCK_QUEUE_LEN>,
+        dma_task: TaskId,
+        process_task: TaskId,
+        dma_section: SectionId,
+        process_section: SectionId,
     }
 
-    #[init(local = [first_buffer: [u16; 2] = [0; 2],second_buffer: [u16; 2] = [0; 2]])]
-    fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
+    #[init(local = [buffer: DmaBuffer<BLOCK_FRAMES, CHANNELS> = [[[0; CHANNELS]; BLOCK_FRAMES]; 2]])]
+    fn init(mut ctx: init::Context) -> (Shared, Local) {
         // Borrow peripherals handle.
-        let dp = ctx.device;
+        let mut dp = ctx.device;
+
+        // Say why we booted, e.g. if the watchdog reset us, over the UART
+        // too with text output.
+        let reset_cause = stm32f4d::reset::report_reset_cause();
+        if matches!(SAMPLE_OUTPUT, SampleOutput::Text) {
+            writeln!(UART_TX.writer(), "Booted after {}\r", reset_cause).ok();
+        }
+
+        // Log any HardFault or panic saved before the last reset. The panic
+        // only goes out over the UART with text output, so it can't be
+        // mistaken for a frame.
+        stm32f4d::fault::report_previous_crash();
+        if let Some(panic) = stm32f4d::panic::report_previous_panic()
+            && matches!(SAMPLE_OUTPUT, SampleOutput::Text)
+        {
+            writeln!(UART_TX.writer(), "Recovered from a panic: {}\r", panic).ok();
+        }
 
         // Get system clock peripheral.
         let rcc = dp.RCC.constrain();
@@ -60,99 +284,143 @@ mod app {
             // Use external (to chip) high speed oscillator. (Datasheet pg 24.)
             .use_hse(8.MHz())
             // HSE is input to PLL to increase frequency. (Datasheet pg 24.)
-            .sysclk(84.MHz())
+            .sysclk(SYSCLK_HZ.Hz())
             // Clock signal to AHB bus. (Internet.)
             .hclk(84.MHz())
             // Special 48Hz PLL-generated clock. (Why needed?)
             .require_pll48clk()
             // Sets PCLK2 = HCLK / 4.
-            .pclk2(21.MHz())
+            .pclk2(PCLK2_HZ.Hz())
             .freeze();
 
+        Mono::start(ctx.core.SYST, clocks.sysclk().to_Hz());
+        PROFILER.enable(&mut ctx.core.DCB, &mut ctx.core.DWT);
+
+        // Timestamp output from here on. Lines queued above go out without.
+        let source = CLOCK.init(dp.RTC, &mut dp.PWR, &clocks, LSE_TIMEOUT_MS);
+        defmt::info!("RTC running from the {}", source);
+        UART_TX.set_clock(&CLOCK);
+
         // See this page on STM32 clocks:
         //  https://www.learningaboutelectronics.com/Articles/SYSCLK-HCLK-PCLK1-PCLK2-clock-STM32F4xx.php
 
         let gpioa = dp.GPIOA.split();
-        let mic1 = gpioa.pa1.into_analog();
-        let mic2 = gpioa.pa2.into_analog();
+        let inputs = AnalogInputs::new(gpioa.pa1, gpioa.pa2, gpioa.pa3);
 
-        // Configure ADC peripheral following Hiari. He says:
-        //  Configure ADC for sequence conversion with interrtups.
+        // RTIC unmasks EXTI0 for its task.
+        let mut syscfg = dp.SYSCFG.constrain();
+        BUTTON.init(gpioa.pa0, &mut syscfg, &mut dp.EXTI, GestureConfig::DEFAULT);
+
+        // Convert all the channels on each rising edge of TIM2 TRGO. Continuous
+        // DMA requests keep going from one transfer to the next.
         let adc_config = AdcConfig::default()
-            // TODO: As we're using this, we could use single DMA mode for
-            //       timer-controlled sampling.
             .dma(Dma::Continuous)
             .scan(Scan::Enabled)
-            .resolution(Resolution::Ten)
-            .clock(Clock::Pclk2_div_8);
+            .external_trigger(TriggerMode::RisingEdge, ExternalTrigger::Tim_2_trgo);
 
+        // By default each channel takes (480 + 10) / 2.625 MHz = 187 us, so
+        // all four fit in a sample period up to about 1.3 kHz.
+        let config = AcquisitionConfig::DEFAULT;
         let mut adc = Adc::adc1(dp.ADC1, true, adc_config);
-        adc.configure_channel(&mic1, Sequence::One, SampleTime::Cycles_480);
-        adc.configure_channel(&mic2, Sequence::Two, SampleTime::Cycles_480);
-        // TODO: Our mic has differential outputs; we can see if the Rust
-        //       HAL supports differential ADC inputs for our board.
+        inputs.configure(&mut adc, &config);
+
+        // Readings of the internal channels are scaled by the factory values.
+        let adc_calibration = AdcCalibration::new(FactoryCalibration {
+            vrefint: VrefCal::get().read(),
+            ts_cal1: VtempCal30::get().read(),
+            ts_cal2: VtempCal110::get().read(),
+        });
+        defmt::info!("ADC factory calibration: {}", adc_calibration);
+        // Triggers are only seen while the ADC is on.
+        adc.enable();
 
-        // Configure orange LED for simple debugging.
+        // Dim the LEDs with TIM4 PWM. Blue breathes to show we're running,
+        // and orange and red show the level of each mic.
         let gpiod = dp.GPIOD.split();
-        let mut led = gpiod.pd13.into_push_pull_output();
-        led.set_high();
+        let leds = PwmLeds::new(
+            dp.TIM4,
+            (gpiod.pd12, gpiod.pd13, gpiod.pd14, gpiod.pd15),
+            &clocks,
+        );
+        let mut fader = Fader::new();
+        fader.set(
+            leds::BLUE,
+            Curve::Breathe {
+                min: 0,
+                max: 255,
+                period_ms: 2000,
+            },
+        );
 
-        // Setup UART transmit pin via multiplexer config.
+        // Setup UART transmit and receive pins via multiplexer config.
         let gpiob = dp.GPIOB.split();
-        // Pin configuration type is inferred from use below.
+        // Pin configuration types are inferred from use below.
         let tx_pin = gpiob.pb6.into_alternate();
+        let rx_pin = gpiob.pb7.into_alternate();
 
-        // Configure USART/UART peripheral with chosen pin.
-        let uart_tx: Tx<USART1> = dp
+        // Configure USART/UART peripheral with chosen pins.
+        let (uart_tx, mut rx) = dp
             .USART1
-            .tx(
-                tx_pin,
+            .serial(
+                (tx_pin, rx_pin),
                 Config::default()
-                    .baudrate(115200.bps())
+                    .baudrate(UART_BAUD.bps())
                     .wordlength_8()
                     .parity_none(),
                 &clocks,
             )
-            .unwrap();
+            .unwrap()
+            .split();
+        // Interrupt on each received console byte.
+        rx.listen();
+        UART_TX.init(uart_tx);
 
-        // Setup DMA following Hiari.
+        // Circular DMA, ready for the first trigger.
         let dma = StreamsTuple::new(dp.DMA2);
-        let dma_config = DmaConfig::default()
-            .transfer_complete_interrupt(true)
-            .memory_increment(true)
-            .double_buffer(false);
-
-        let transfer = Transfer::init_peripheral_to_memory(
-            dma.0,
-            adc,
-            ctx.local.first_buffer,
-            None,
-            dma_config,
-        );
-
-        // How often to trigger ADC transfer start.
-        //  Should be as fast as UART can send,
-        //  if we've calculated correctly.
-        const ADC_TIMER_RATE_HZ: u32 = 1000;
-        // TODO: For audio 48khz is recommened. But don't think
-        //  we can send data back to the board that quickly
+        let adc = CircularAdc::start(dma.0, adc, ctx.local.buffer);
 
-        // Setup timer.
+        // Start the sample clock. Its update event drives TRGO, with no
+        // interrupt. The console keeps it to change the rate.
         let mut timer = dp.TIM2.counter_hz(&clocks);
-        timer.listen(Event::Update);
-        timer.start(ADC_TIMER_RATE_HZ.Hz()).unwrap();
+        timer.set_master_mode(MMS::Update);
+        timer.start(config.sample_rate_hz.Hz()).unwrap();
+
+        let dma_task = WATCHDOG.register("dma", SAMPLING_DEADLINE_MS);
+        let process_task = WATCHDOG.register("process", SAMPLING_DEADLINE_MS);
+        WATCHDOG.start(dp.IWDG, &dp.DBGMCU, WATCHDOG_TIMEOUT_MS);
+
+        let dma_section = PROFILER.register("dma");
+        let process_section = PROFILER.register("process");
+
+        let (blocks, receiver) = make_channel!(TaggedBlock, BLOCK_QUEUE_LEN);
+        process::spawn(receiver).unwrap();
+        let (lines, receiver) = make_channel!(Result<Line<LINE_LEN>, LineError>, LINE_QUEUE_LEN);
+        console::spawn(receiver).unwrap();
+        watchdog::spawn().unwrap();
+        button::spawn().unwrap();
 
         (
-            Shared { transfer },
+            Shared {
+                calibrate: false,
+                config,
+                generation: 0,
+                adc,
+            },
             Local {
-                led,
-                uart_tx,
-                buffer: Some(ctx.local.second_buffer),
+                leds,
+                fader,
+                inputs,
                 timer,
+                adc_calibration,
+                filters: [FirQ15::new(LOW_PASS); 2],
+                rx,
+                lines,
+                blocks,
+                dma_task,
+                process_task,
+                dma_section,
+                process_section,
             },
-            // Hiari: We aren't using these explicitly,
-            //        but they still need initialized.
-            init::Monotonics(),
         )
     }
 
@@ -166,39 +434,364 @@ mod app {
         }
     }
 
-    // Based on Hiari's example.
-    #[task(binds = TIM2, shared = [transfer], local = [led, timer])]
-    fn adc_start(mut ctx: adc_start::Context) {
-        ctx.local.led.toggle();
-        ctx.shared.transfer.lock(|transfer| {
-            transfer.start(|adc| {
-                // TODO: See note above about Dma::Continuous mode.
-                adc.start_conversion();
-            });
-        });
-        ctx.local.timer.clear_flags(Flag::Update);
+    // Runs once per block; the conversions and the DMA need no CPU.
+    #[task(
+        binds = DMA2_STREAM0,
+        priority = 2,
+        shared = [adc, generation],
+        local = [blocks, dma_task, dma_section, lost: u32 = 0]
+    )]
+    fn dma(mut ctx: dma::Context) {
+        let local = ctx.local;
+        let _timing = PROFILER.start(*local.dma_section);
+        WATCHDOG.check_in(*local.dma_task);
+
+        // Nothing else can take the ADC at this priority.
+        let (block, lost) = ctx
+            .shared
+            .adc
+            .lock(|adc| (adc.on_interrupt(), adc.lost_blocks()));
+        let Some(block) = block else {
+            return;
+        };
+        if lost != *local.lost {
+            *local.lost = lost;
+            defmt::warn!(
+                "DMA overwrote {=u32} blocks before they were read",
+                *local.lost
+            );
+        }
+
+        // Hand the block to the processing task. If it's behind, drop it;
+        // frames show that as a sequence gap.
+        let generation = ctx.shared.generation.lock(|generation| *generation);
+        local.blocks.try_send((generation, block)).ok();
     }
 
-    // Based on Hiari's example.
-    #[task(binds = DMA2_STREAM0, shared = [transfer], local = [uart_tx, buffer])]
-    fn dma(ctx: dma::Context) {
-        let mut shared = ctx.shared;
+    // Calibrates the mics when asked, shows each block on the LEDs and
+    // queues its samples for the PC.
+    #[task(
+        priority = 1,
+        shared = [calibrate, config, generation],
+        local = [
+            leds,
+            fader,
+            process_task,
+            process_section,
+            adc_calibration,
+            filters,
+            since_report_ms: u32 = 0,
+            calibration: Calibration = Calibration::centred(DEFAULT_BITS),
+            calibrated_for: ([AnalogInput; 2], Resolution) = DEFAULT_MICS,
+            calibrator: Option<Calibrator> = None,
+            frame_encoder: FrameEncoder = FrameEncoder::new(),
+            frame: [u8; MAX_FRAME_LEN] = [0; MAX_FRAME_LEN],
+        ]
+    )]
+    async fn process(
+        mut ctx: process::Context,
+        mut receiver: Receiver<'static, TaggedBlock, BLOCK_QUEUE_LEN>,
+    ) {
         let local = ctx.local;
+        while let Ok((block_generation, block)) = receiver.recv().await {
+            let _timing = PROFILER.start(*local.process_section);
+            WATCHDOG.check_in(*local.process_task);
 
-        let buffer = shared.transfer.lock(|transfer| {
-            let (buffer, _) = transfer
-                .next_transfer(local.buffer.take().unwrap())
-                .unwrap();
-            buffer
-        });
+            // Blocks queued before the console restarted sampling were taken
+            // with the old settings, so their resolution and timing would be
+            // wrong.
+            let (config, generation) = (&mut ctx.shared.config, &mut ctx.shared.generation)
+                .lock(|config, generation| (*config, *generation));
+            if block_generation != generation {
+                continue;
+            }
+            let bits = config.resolution.bits();
+            let block_ms = block_ms(&config);
+
+            // Calibrations and filter inputs don't carry over to other pins
+            // or resolutions.
+            if (config.inputs, config.resolution) != *local.calibrated_for {
+                *local.calibrated_for = (config.inputs, config.resolution);
+                *local.calibration = Calibration::centred(bits);
+                *local.calibrator = None;
+                local.filters.iter_mut().for_each(FirQ15::reset);
+            }
+
+            // A second for each phase.
+            if ctx.shared.calibrate.lock(core::mem::take) {
+                *local.calibrator = Some(Calibrator::new(config.sample_rate_hz));
+                defmt::info!("Calibrating the mic offsets: keep quiet");
+                write_text(format_args!("Calibrating the mic offsets: keep quiet\r\n"));
+            }
+            // Follow VDDA and the chip temperature from the internal channels.
+            let adc_calibration = &mut *local.adc_calibration;
+            adc_calibration.update(
+                block::channel_mean(&block, VREFINT),
+                block::channel_mean(&block, TEMPERATURE),
+                bits,
+            );
+            *local.since_report_ms += block_ms;
+            if *local.since_report_ms >= SUPPLY_REPORT_MS {
+                *local.since_report_ms = 0;
+                defmt::info!(
+                    "VDDA {=u32} mV, chip {=?} mC",
+                    adc_calibration.vdda_mv(),
+                    adc_calibration.millicelsius()
+                );
+                write_text(format_args!("{}\r\n", adc_calibration));
+            }
+
+            let mut mics: Mics = block.map(|[mic1, mic2, _, _]| [mic1, mic2]);
+            if matches!(FILTER, Filter::LowPass) {
+                low_pass(local.filters, &mut mics, bits);
+            }
+            calibrate(local.calibrator, local.calibration, &mics);
+
+            // Pick the signal, and the shift from it to 8-bit brightness.
+            let (block, output_bits) = match SIGNAL {
+                Signal::Raw => (mics, bits),
+                Signal::Differential => {
+                    let diff_bits = bits + DIFF_EXTRA_BITS;
+                    let calibration = &*local.calibration;
+                    (
+                        mics.map(|frame| calibration.apply(frame).to_offset_binary(diff_bits)),
+                        diff_bits,
+                    )
+                }
+                Signal::Millivolts => (
+                    mics.map(|frame| {
+                        frame.map(|count| adc_calibration.millivolts(count, bits) as u16)
+                    }),
+                    MILLIVOLT_BITS,
+                ),
+            };
+            let shift = output_bits.saturating_sub(8);
+
+            // Show the latest samples on the LEDs.
+            let mut levels = local.fader.advance(block_ms);
+            let [first, second] = block[BLOCK_FRAMES - 1];
+            levels[leds::ORANGE] = (first >> shift) as u8;
+            levels[leds::RED] = (second >> shift) as u8;
+            local.leds.set_all(levels);
+
+            // The block ended about now, so date each frame back from here.
+            let end_ms = CLOCK.time_of_day_ms();
+
+            // Queue data for the PC. If the UART can't keep up samples are
+            // dropped and counted, which shows up as a sequence gap in frames.
+            for (i, frame) in block.iter().enumerate() {
+                let [first, second] = *frame;
+                match SAMPLE_OUTPUT {
+                    // Each line is 42 bytes with the timestamp, too many to
+                    // send at 1 kHz, so some are dropped.
+                    SampleOutput::Text => {
+                        write_sample_line(&mut UART_TX.writer(), first, second).ok();
+                    }
+                    // Each frame is 15 bytes for two channels and a timestamp.
+                    SampleOutput::Frames => {
+                        let age_ms = (BLOCK_FRAMES - 1 - i) as u32 * 1000 / config.sample_rate_hz;
+                        let time_ms = end_ms.map(|end_ms| (end_ms + DAY_MS - age_ms) % DAY_MS);
+                        let bytes = local
+                            .frame_encoder
+                            .encode(time_ms, frame, local.frame)
+                            .unwrap();
+                        UART_TX.write(bytes);
+                    }
+                }
+            }
+        }
+    }
+
+    // Time between DMA completions, rounded down.
+    fn block_ms(config: &AcquisitionConfig) -> u32 {
+        BLOCK_FRAMES as u32 * 1000 / config.sample_rate_hz
+    }
+
+    // Cycles between DMA completions, which the sampling tasks must fit in.
+    fn block_budget_cycles(config: &AcquisitionConfig) -> u32 {
+        SYSCLK_HZ / config.sample_rate_hz * BLOCK_FRAMES as u32
+    }
+
+    // Filters each mic through its own filter, as Q15 around mid-scale.
+    fn low_pass(filters: &mut [FirQ15<LOW_PASS_TAPS>; 2], mics: &mut Mics, bits: u32) {
+        for (channel, filter) in filters.iter_mut().enumerate() {
+            let input = mics.map(|frame| fir::count_to_q15(frame[channel], bits));
+            let mut output = [0; BLOCK_FRAMES];
+            filter.process_block(&input, &mut output);
+            for (frame, y) in mics.iter_mut().zip(output) {
+                frame[channel] = fir::q15_to_count(y, bits);
+            }
+        }
+    }
 
-        let mic1 = buffer[0];
-        let mic2 = buffer[1];
+    // Feeds a block to the calibrator, if one is running, and takes its
+    // result when it's done.
+    fn calibrate(calibrator: &mut Option<Calibrator>, calibration: &mut Calibration, block: &Mics) {
+        for frame in block {
+            let Some(running) = calibrator else {
+                return;
+            };
+            let phase = running.phase();
+            let result = running.push(*frame);
+            if running.phase() != phase {
+                defmt::info!("Calibrating the mic gains: play a steady tone");
+                write_text(format_args!(
+                    "Calibrating the mic gains: play a steady tone\r\n"
+                ));
+            }
 
-        // From Hiari: After this RHS buffer is dropped and returned to pool.
-        *local.buffer = Some(buffer);
+            match result {
+                None => continue,
+                Some(Ok(measured)) => {
+                    *calibration = measured;
+                    defmt::info!("Mics calibrated: {}", measured);
+                    write_text(format_args!("Mics calibrated: {}\r\n", measured));
+                }
+                // The offsets are still worth having.
+                Some(Err(error)) => {
+                    *calibration = error.offsets();
+                    defmt::warn!("Mic gains not calibrated: {}", error);
+                    write_text(format_args!("Mic gains not calibrated: {}\r\n", error));
+                }
+            }
+            *calibrator = None;
+        }
+    }
+
+    // Writes a message to the UART with text output, where it can't be
+    // mistaken for a frame.
+    fn write_text(message: core::fmt::Arguments) {
+        if matches!(SAMPLE_OUTPUT, SampleOutput::Text) {
+            UART_TX.writer().write_fmt(message).ok();
+        }
+    }
+
+    // Starts B1 sampling on each edge.
+    #[task(binds = EXTI0)]
+    fn button_edge(_: button_edge::Context) {
+        BUTTON.on_edge();
+    }
+
+    // Debounces B1. A press reports the task timings over defmt, and over
+    // the UART with text output; a long press starts a mic calibration.
+    #[task(priority = 1, shared = [calibrate, config])]
+    async fn button(mut ctx: button::Context) {
+        let mut next = Mono::now();
+        loop {
+            BUTTON.on_tick(BUTTON_TICK_MS);
+            while let Some(event) = BUTTON.next_event() {
+                match event {
+                    ButtonEvent::Press => {
+                        let budget = ctx.shared.config.lock(|config| block_budget_cycles(config));
+                        PROFILER.log(budget);
+                        if matches!(SAMPLE_OUTPUT, SampleOutput::Text) {
+                            PROFILER.report(&mut UART_TX.writer(), budget).ok();
+                        }
+                    }
+                    ButtonEvent::LongPress => {
+                        ctx.shared.calibrate.lock(|calibrate| *calibrate = true)
+                    }
+                    _ => {}
+                }
+            }
+
+            next += BUTTON_TICK_MS.millis();
+            Mono::delay_until(next).await;
+        }
+    }
+
+    // Sends queued output and collects received bytes into lines for the
+    // console.
+    #[task(binds = USART1, local = [rx, lines, line_buffer: LineBuffer<LINE_LEN> = LineBuffer::new()])]
+    fn uart(ctx: uart::Context) {
+        UART_TX.on_interrupt();
 
-        // Send data to PC; each message is 14 bytes.
-        writeln!(local.uart_tx, "{:05} -- {:05}\r", mic1, mic2).unwrap();
+        // Reading clears the interrupt. Errors such as overrun just drop the
+        // byte, and the line will fail to parse.
+        let local = ctx.local;
+        while local.rx.is_rx_not_empty() {
+            if let Ok(byte) = local.rx.read()
+                && let Some(line) = local.line_buffer.push(byte)
+            {
+                // If the console is still busy, the line is dropped.
+                local.lines.try_send(line).ok();
+            }
+        }
+    }
+
+    // Applies acquisition commands from the terminal. Each change is
+    // validated, then sampling stops, the ADC is reconfigured and sampling
+    // starts again from the start of the DMA buffer. Replies are logged, and
+    // sent over the UART with text output.
+    #[task(priority = 1, shared = [config, generation, adc], local = [inputs, timer])]
+    async fn console(
+        mut ctx: console::Context,
+        mut lines: Receiver<'static, Result<Line<LINE_LEN>, LineError>, LINE_QUEUE_LEN>,
+    ) {
+        while let Ok(line) = lines.recv().await {
+            let command = match line {
+                Ok(line) => acquisition::parse(line.as_str()),
+                Err(LineError::TooLong) => {
+                    defmt::warn!("Console line too long");
+                    write_text(format_args!("error: line too long\r\n"));
+                    continue;
+                }
+            };
+            let config = ctx.shared.config.lock(|config| *config);
+            let new_config = match command {
+                Ok(AcquisitionCommand::Help) => {
+                    defmt::info!("{=str}", acquisition::HELP);
+                    write_text(format_args!("{}\r\n", acquisition::HELP));
+                    continue;
+                }
+                Ok(AcquisitionCommand::Status) => {
+                    defmt::info!("Acquisition: {}", config);
+                    write_text(format_args!("{}\r\n", config));
+                    continue;
+                }
+                Ok(command) => config.with(command),
+                Err(err) => {
+                    defmt::warn!("Console: {}", err);
+                    write_text(format_args!("error: {}\r\n", err));
+                    continue;
+                }
+            };
+            if let Err(err) = new_config.validate(&LIMITS) {
+                defmt::warn!("Acquisition not changed: {}", err);
+                write_text(format_args!("error: {}\r\n", err));
+                continue;
+            }
+
+            // Stop the triggers first, so no conversion is cut short.
+            let timer = &mut *ctx.local.timer;
+            let inputs = &*ctx.local.inputs;
+            timer.cancel().ok();
+            // The new settings and generation take effect with the restart,
+            // so every block after it is tagged with them.
+            let shared = &mut ctx.shared;
+            (&mut shared.adc, &mut shared.config, &mut shared.generation).lock(
+                |adc, config, generation| {
+                    adc.restart(|adc| inputs.configure(adc, &new_config));
+                    *config = new_config;
+                    *generation = generation.wrapping_add(1);
+                },
+            );
+            timer.start(new_config.sample_rate_hz.Hz()).unwrap();
+
+            defmt::info!("Acquisition: {}", new_config);
+            write_text(format_args!("{}\r\n", new_config));
+        }
+    }
+
+    // Feeds the watchdog while the sampling tasks keep checking in.
+    #[task(priority = 1)]
+    async fn watchdog(_: watchdog::Context) {
+        let mut next = Mono::now();
+        loop {
+            WATCHDOG.check(WATCHDOG_CHECK_MS);
+
+            next += WATCHDOG_CHECK_MS.millis();
+            Mono::delay_until(next).await;
+        }
     }
 }
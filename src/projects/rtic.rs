@@ -3,51 +3,89 @@
 //! We learned from the Embedded Rustacean example available here:
 //!  https://dev.to/theembeddedrustacean/stm32f4-embedded-rust-at-the-hal-the-rtic-framework-1j9i
 //!
+//! Everything here runs as async software tasks on a SysTick monotonic.
+//! Orange blinks at a rate that cycles through `TIMER_DELAYS_MS`, green
+//! sends "SOS" in Morse code, and red and blue share a heartbeat half a
+//! second out of phase.
+//!
+//! Periodic tasks wait with `Mono::delay_until` for their previous deadline
+//! plus their period, rather than `Mono::delay` from whenever they happened
+//! to run, so they don't drift however long each run takes.
 
 #![no_main]
 #![no_std]
 
 // For panic_handler.
 use stm32f4d as _;
+use stm32f4d::watchdog::Watchdog;
+
+// Resets the board if an LED task stops running.
+static WATCHDOG: Watchdog<2> = Watchdog::new();
 
-#[rtic::app(device = stm32f4xx_hal::pac, peripherals = true)]
+#[rtic::app(device = stm32f4xx_hal::pac, peripherals = true, dispatchers = [SPI1])]
 mod app {
     // Imports.
-    use stm32f4xx_hal::{
-        gpio::{self, Output, PushPull},
-        pac::TIM2,
-        prelude::*,
-        timer::{self, Event, Flag},
+    use super::WATCHDOG;
+    use rtic_monotonics::systick::prelude::*;
+    use stm32f4d::leds::{self, Leds};
+    use stm32f4d_core::{
+        morse,
+        pattern::{Pattern, Player, Step},
+        supervisor::TaskId,
     };
+    use stm32f4xx_hal::prelude::*;
 
-    // List of timeout delay values to cycle through.
-    const TIMER_DELAYS_MS: &[u32] = &[50, 500];
-    // Period after which we change to the next timeout.
+    // List of blink delays to cycle through. Any values work; they don't
+    // need to divide the change interval.
+    const TIMER_DELAYS_MS: &[u32] = &[50, 120, 500];
+    // Period after which we change to the next delay.
     const TIMEOUT_CHANGE_INT_MS: u32 = 5000;
 
-    pub struct TimerInfo {
-        pub current_timeout: usize,
-        pub cumul_timeout: u32,
-    }
+    // Two quick flashes a second.
+    const HEARTBEAT: &[Step] = &[Step::blink(100, 100, 2), Step::off(600)];
+    // Morse message on the green LED.
+    const MORSE_TEXT: &str = "SOS";
+    const MORSE_UNIT_MS: u32 = 150;
+
+    // How often the pattern task updates the LEDs.
+    const LED_TICK_MS: u32 = 10;
+
+    // How often the watchdog task checks that the LED tasks are running.
+    const WATCHDOG_CHECK_MS: u32 = 100;
+    // The IWDG resets the board if it isn't fed for this long.
+    const WATCHDOG_TIMEOUT_MS: u32 = 1000;
+
+    // Millisecond timer on SysTick.
+    systick_monotonic!(Mono, 1_000);
 
     // Resources shared between tasks
     #[shared]
     struct Shared {
-        timer: timer::CounterMs<TIM2>,
-        timer_info: TimerInfo,
+        leds: Leds,
+        // Index into `TIMER_DELAYS_MS`.
+        delay_index: usize,
     }
 
     // Local resources to specific tasks (cannot be shared)
     #[local]
     struct Local {
-        led: gpio::PD13<Output<PushPull>>,
+        player: Player<'static, { leds::COUNT }>,
+        blink_task: TaskId,
+        pattern_task: TaskId,
     }
 
-    #[init]
-    fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
+    #[init(local = [morse_steps: [Step; 16] = [Step::off(0); 16]])]
+    fn init(ctx: init::Context) -> (Shared, Local) {
         // Borrow peripherals handle.
         let dp = ctx.device;
 
+        // Say why we booted, e.g. if the watchdog reset us.
+        stm32f4d::reset::report_reset_cause();
+
+        // Log any HardFault or panic saved before the last reset.
+        stm32f4d::fault::report_previous_crash();
+        stm32f4d::panic::report_previous_panic();
+
         // Get system clock peripheral.
         let rcc = dp.RCC.constrain();
         // Configure peripheral to use on-board oscillator:
@@ -55,32 +93,41 @@ mod app {
         //   and also reflected in our board's datasheet.
         let clocks = rcc.cfgr.use_hse(8.MHz()).freeze();
 
-        // Configure orange LED pin.
-        let gpiod = dp.GPIOD.split();
-        let mut led = gpiod.pd13.into_push_pull_output();
-
-        // Start with LED on.
-        led.set_high();
+        Mono::start(ctx.core.SYST, clocks.sysclk().to_Hz());
 
-        // Create timer.
-        let mut timer = dp.TIM2.counter_ms(&clocks);
-        // Start timer with initial timeout rate.
-        timer.start(TIMER_DELAYS_MS[0].millis()).unwrap();
-        // Hiari: Set up to generate interrupt when timer expires
-        timer.listen(Event::Update);
+        // Configure the four LED pins.
+        let gpiod = dp.GPIOD.split();
+        let leds = Leds::new(gpiod.pd12, gpiod.pd13, gpiod.pd14, gpiod.pd15);
+
+        // Orange is driven by the blink task; the rest play patterns.
+        let morse = morse::encode(MORSE_TEXT, MORSE_UNIT_MS, ctx.local.morse_steps).unwrap();
+        let mut player = Player::new();
+        player.play(leds::GREEN, Pattern::new(morse));
+        player.play(leds::RED, Pattern::new(HEARTBEAT));
+        player.play(leds::BLUE, Pattern::new(HEARTBEAT).offset(500));
+
+        // Allow the blink task two of the longest delays between runs, and
+        // the pattern task a few missed ticks.
+        let max_delay_ms = TIMER_DELAYS_MS.iter().copied().max().unwrap();
+        let blink_task = WATCHDOG.register("blink", 2 * max_delay_ms);
+        let pattern_task = WATCHDOG.register("pattern_tick", 10 * LED_TICK_MS);
+        WATCHDOG.start(dp.IWDG, &dp.DBGMCU, WATCHDOG_TIMEOUT_MS);
+
+        blink::spawn().unwrap();
+        change_rate::spawn().unwrap();
+        pattern_tick::spawn().unwrap();
+        watchdog::spawn().unwrap();
 
         (
             Shared {
-                timer,
-                timer_info: TimerInfo {
-                    current_timeout: 0,
-                    cumul_timeout: 0,
-                },
+                leds,
+                delay_index: 0,
+            },
+            Local {
+                player,
+                blink_task,
+                pattern_task,
             },
-            Local { led },
-            // Hiari: We aren't using these explicitly,
-            //        but they still need initialized.
-            init::Monotonics(),
         )
     }
 
@@ -94,30 +141,62 @@ mod app {
         }
     }
 
-    // Code to run on timer expired interrupt.
-    #[task(binds = TIM2, local=[led], shared=[timer, timer_info])]
-    fn timer_expired(mut ctx: timer_expired::Context) {
-        let mut current_timeout = 0;
-        ctx.shared.timer_info.lock(|ti| {
-            ti.cumul_timeout += TIMER_DELAYS_MS[ti.current_timeout];
-
-            // If timeout update interval has expired, move to next rate.
-            if ti.cumul_timeout > 0 && ti.cumul_timeout % TIMEOUT_CHANGE_INT_MS == 0 {
-                ti.current_timeout = (ti.current_timeout + 1) % TIMER_DELAYS_MS.len();
-            }
-
-            current_timeout = ti.current_timeout;
-        });
-
-        ctx.local.led.toggle();
-        ctx.shared.timer.lock(|timer| {
-            // Clear interrupt flag so it doesn't immediately fire again.
-            timer.clear_flags(Flag::Update);
-
-            // Now restart timer with current timeout value.
-            timer
-                .start(TIMER_DELAYS_MS[current_timeout].millis())
-                .unwrap();
-        });
+    // Toggles orange at the current delay.
+    #[task(priority = 1, local = [blink_task], shared = [leds, delay_index])]
+    async fn blink(mut ctx: blink::Context) {
+        let mut next = Mono::now();
+        loop {
+            WATCHDOG.check_in(*ctx.local.blink_task);
+            ctx.shared.leds.lock(|leds| leds.toggle(leds::ORANGE));
+
+            let delay_ms = ctx.shared.delay_index.lock(|index| TIMER_DELAYS_MS[*index]);
+            next += delay_ms.millis();
+            Mono::delay_until(next).await;
+        }
+    }
+
+    // Moves on to the next blink delay, on its own fixed schedule.
+    #[task(priority = 1, shared = [delay_index])]
+    async fn change_rate(mut ctx: change_rate::Context) {
+        let mut next = Mono::now();
+        loop {
+            next += TIMEOUT_CHANGE_INT_MS.millis();
+            Mono::delay_until(next).await;
+
+            ctx.shared
+                .delay_index
+                .lock(|index| *index = (*index + 1) % TIMER_DELAYS_MS.len());
+        }
+    }
+
+    // Updates the pattern LEDs.
+    #[task(priority = 1, local = [player, pattern_task], shared = [leds])]
+    async fn pattern_tick(mut ctx: pattern_tick::Context) {
+        let mut next = Mono::now();
+        loop {
+            WATCHDOG.check_in(*ctx.local.pattern_task);
+
+            let levels = ctx.local.player.advance(LED_TICK_MS);
+            ctx.shared.leds.lock(|leds| {
+                for led in [leds::GREEN, leds::RED, leds::BLUE] {
+                    leds.set_one(led, levels[led]);
+                }
+            });
+
+            next += LED_TICK_MS.millis();
+            Mono::delay_until(next).await;
+        }
+    }
+
+    // Feeds the watchdog while the LED tasks keep checking in.
+    #[task(priority = 1)]
+    async fn watchdog(_: watchdog::Context) {
+        let mut next = Mono::now();
+        loop {
+            WATCHDOG.check(WATCHDOG_CHECK_MS);
+
+            next += WATCHDOG_CHECK_MS.millis();
+            Mono::delay_until(next).await;
+        }
     }
 }
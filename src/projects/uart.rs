@@ -4,6 +4,26 @@
 //! attached to via a USB virtual com port adapter, while the user
 //! controls the rate of a blinking LED via a pushbutton.
 //!
+//! The PC can also type commands into the terminal, which are received
+//! on PB7 by the USART1 interrupt: `rate <ms>`, `reset`, `status`,
+//! `activate`, `time [YYYY-MM-DD HH:MM:SS]` and `help`. Once the RTC is
+//! running every line sent starts with its date and time; the capture tool's
+//! `--set-time` sets it from the PC.
+//!
+//! The LED toggles, the button is sampled and the inactivity period is
+//! timed by jobs on the SysTick scheduler, so the blink rate is in real
+//! milliseconds. After `ALLOWED_PRESSES` presses the program ignores the
+//! button until a one-shot job ends the inactivity period. Each state change
+//! is reported over the UART.
+//!
+//! Between jobs the board idles in Stop mode, woken by the RTC for the next
+//! job, by the button, or by a falling edge on PB7 when the PC starts sending.
+//! The USART is stopped too, so the byte that wakes the board is lost, along
+//! with any that follow while the clocks restart: type any key to wake it,
+//! then the command. After each received byte the board only Sleeps for
+//! `CONSOLE_AWAKE_MS`, so the rest of a command arrives whole. It also
+//! Sleeps while the button is being debounced or output is still being sent.
+//!
 //! The code here was adapted from several places, but mostly from
 //! [this](https://dev.to/theembeddedrustacean/stm32f4-embedded-rust-at-the-hal-uart-serial-communication-1oc8)
 //! blog post.
@@ -13,119 +33,328 @@
 #![no_std]
 #![no_main]
 
-use core::fmt::Write;
-use cortex_m_rt::entry;
-use debouncr::{Edge, debounce_3};
+use core::{
+    cell::RefCell,
+    fmt::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::{entry, exception};
 
 // From Knurling template setup:
 // global logger + panicking-behavior + memory layout
 use stm32f4d as _;
 
+use stm32f4d::{
+    button::Button, power::PowerManager, rtc::RtcClock, scheduler::Scheduler, uart_tx::BufferedTx,
+};
+use stm32f4d_core::{
+    blink::{ActivityConfig, BlinkControl, Input, PressReset, State},
+    button::{ButtonEvent, GestureConfig},
+    command::{self, Command, Reply},
+    line::{Line, LineBuffer, LineError},
+    power::{IdlePolicy, PowerMode},
+};
 use stm32f4xx_hal::{
-    pac::{self},
+    gpio::PinExt,
+    pac::{self, USART1, interrupt},
     prelude::*,
-    serial::Config,
+    serial::{Config, Rx},
 };
 
-enum State {
-    Active,
-    Inactive,
-}
+// Longest command line we accept.
+const LINE_LEN: usize = 32;
+// How often the button is sampled while it's active.
+const BUTTON_TICK_MS: u32 = 5;
+// How long to stay out of Stop after a byte is received, so the rest of a
+// command isn't lost.
+const CONSOLE_AWAKE_MS: u32 = 10_000;
+// EXTI line of the PB7 RX pin, which wakes us from Stop.
+const RX_WAKE_LINE: u8 = 7;
+// How long to wait for an LSE crystal to start before using the LSI.
+const LSE_TIMEOUT_MS: u32 = 2000;
+// When to stop responding to the button, and for how long.
+const ACTIVITY: ActivityConfig = ActivityConfig {
+    allowed_presses: BlinkControl::ALLOWED_PRESSES,
+    inactive_ms: 10_000,
+    press_reset: PressReset::OnReactivate,
+};
+
+// Queued UART output, sent from the interrupt handler. Writes that
+// don't fit are dropped rather than blocking the blink loop.
+static UART_TX: BufferedTx<USART1, 256> = BufferedTx::new();
+// Receive side of the UART, owned by the interrupt handler after setup.
+static UART_RX: Mutex<RefCell<Option<Rx<USART1>>>> = Mutex::new(RefCell::new(None));
+// Bytes received so far on the current line.
+static LINE_BUFFER: Mutex<RefCell<LineBuffer<LINE_LEN>>> =
+    Mutex::new(RefCell::new(LineBuffer::new()));
+// Set when a byte arrives or PB7 wakes us, for the main loop to stay awake.
+static UART_ACTIVITY: AtomicBool = AtomicBool::new(false);
+// Last complete line, waiting for the main loop to handle it.
+static PENDING_LINE: Mutex<RefCell<Option<Result<Line<LINE_LEN>, LineError>>>> =
+    Mutex::new(RefCell::new(None));
+// Calendar clock for timestamps and the `time` command.
+static CLOCK: RtcClock = RtcClock::new();
+// B1 user button, woken by EXTI0 and sampled by a scheduler job.
+static BUTTON: Button = Button::new();
+// Times the button sampling, the LED, the inactivity period and how long
+// to stay awake for the console.
+static SCHEDULER: Scheduler<4> = Scheduler::new();
 
 #[entry]
 fn main() -> ! {
     // Take ownership of peripheral interface.
-    let dp = pac::Peripherals::take().unwrap();
+    let mut dp = pac::Peripherals::take().unwrap();
+    let cp = cortex_m::Peripherals::take().unwrap();
+
+    // Say why we booted, over the UART too once it's set up below.
+    let reset_cause = stm32f4d::reset::report_reset_cause();
+    writeln!(UART_TX.writer(), "Booted after {}\r", reset_cause).ok();
+
+    // Log any HardFault or panic saved before the last reset. The panic also
+    // goes out over the UART, queued until it's set up below.
+    stm32f4d::fault::report_previous_crash();
+    if let Some(panic) = stm32f4d::panic::report_previous_panic() {
+        writeln!(UART_TX.writer(), "Recovered from a panic: {}\r", panic).ok();
+    }
 
     // Configure orange LED pin.
     let gpiod = dp.GPIOD.split();
     let mut led = gpiod.pd13.into_push_pull_output();
 
-    // Configure B! user pushbutton pin.
+    // Configure B! user pushbutton pin to interrupt on both edges.
     let gpioa = dp.GPIOA.split();
-    let button = gpioa.pa0;
+    let mut syscfg = dp.SYSCFG.constrain();
+    BUTTON.init(gpioa.pa0, &mut syscfg, &mut dp.EXTI, GestureConfig::DEFAULT);
 
     // Get system clock peripheral.
     let rcc = dp.RCC.constrain();
     // Configure peripheral to use on-board oscillator.
     // 8 MHz was suggested by Hiari for other board and reflected in datasheet.
+    // The power manager restarts it after each Stop.
     let clocks = rcc.cfgr.use_hse(8.MHz()).freeze();
 
-    // Setup UART transmit pin via multiplexer config.
+    // Timestamp output from here on. Lines queued above go out without.
+    let source = CLOCK.init(dp.RTC, &mut dp.PWR, &clocks, LSE_TIMEOUT_MS);
+    defmt::info!("RTC running from the {}", source);
+    UART_TX.set_clock(&CLOCK);
+    let mut power = PowerManager::new(&CLOCK, cp.SCB, &dp.PWR, &dp.DBGMCU, &mut dp.EXTI);
+    let policy = IdlePolicy::DEFAULT;
+
+    // Millisecond clock for the scheduler.
+    SCHEDULER.start_systick(cp.SYST, &clocks);
+
+    // Setup UART transmit and receive pins via multiplexer config.
     let gpiob = dp.GPIOB.split();
-    // Pin configuration type is inferred from use below.
+    // Pin configuration types are inferred from use below.
     let tx_pin = gpiob.pb6.into_alternate();
+    // The start bit of a received byte wakes us from Stop.
+    let mut rx_pin = gpiob.pb7;
+    power.wake_on_uart_rx(&mut rx_pin, &mut syscfg, &mut dp.EXTI);
+    debug_assert_eq!(rx_pin.pin_id(), RX_WAKE_LINE);
+    let rx_pin = rx_pin.into_alternate();
 
-    // Configure USART/UART peripheral with chosen pin.
-    let mut uart_tx = dp
+    // Configure USART/UART peripheral with chosen pins.
+    let (uart_tx, mut uart_rx) = dp
         .USART1
-        .tx(
-            tx_pin,
+        .serial(
+            (tx_pin, rx_pin),
             Config::default()
                 .baudrate(115200.bps())
                 .wordlength_8()
                 .parity_none(),
             &clocks,
         )
-        .unwrap();
+        .unwrap()
+        .split();
+
+    // Interrupt on each received byte and hand the receiver to the handler.
+    uart_rx.listen();
+    cortex_m::interrupt::free(|cs| UART_RX.borrow(cs).replace(Some(uart_rx)));
+    UART_TX.init(uart_tx);
+    // SAFETY: The handlers only touch state behind mutexes.
+    unsafe {
+        pac::NVIC::unmask(pac::Interrupt::USART1);
+        pac::NVIC::unmask(pac::Interrupt::EXTI0);
+    }
 
     // Start with LED off.
     led.set_low();
 
-    // Hiari: Initialize debouncer to false because button is
-    // active low. Chose 3 consecutive states based on testing.
-    let mut debouncer = debounce_3(false);
-    // Controls spin loop iterations between LED toggles.
-    let mut toggle_delay_iters = 7_0000_i32;
-    // Count button presses.
-    let mut num_button_presses: u8 = 0;
-    // Current program state.
-    let mut system_state = State::Active;
+    // Blink delay, press count and program state.
+    let mut control = BlinkControl::with_config(ACTIVITY);
 
-    // # presses before entering inactive state.
-    const ALLOWED_PRESSES: u8 = 5;
+    let button_job = SCHEDULER.every(BUTTON_TICK_MS);
+    let blink_job = SCHEDULER.every(control.toggle_delay_ms);
+    // Started on deactivation.
+    let inactivity_job = SCHEDULER.once();
+    // Started on each received byte, and keeps us out of Stop until it runs.
+    let console_job = SCHEDULER.once();
 
     // Program main loop.
     loop {
-        // Explicitly copy upper bound to immutable; makes clippy happy.
-        let loop_bound = toggle_delay_iters;
-
-        // LED flash delay and input handling loop.
-        for _i in 1..loop_bound {
-            // Check for button press.
-            if let State::Active = system_state
-                && debouncer.update(button.is_low()) == Some(Edge::Falling)
-            {
-                num_button_presses = num_button_presses.wrapping_add(1);
-                writeln!(uart_tx, "Button Press {:02} Woohoo!!\r", num_button_presses).unwrap();
-
-                // Reduce LED toggle delay on button press.
-                toggle_delay_iters -= 3_0000_i32;
-                // When delay reaches minimum reset to initial.
-                if toggle_delay_iters < 1_0000 {
-                    toggle_delay_iters = 7_0000_i32;
+        // EXTI0 woke the button, so sample it until it settles.
+        if BUTTON.is_sampling() && !SCHEDULER.is_running(button_job) {
+            SCHEDULER.start(button_job, BUTTON_TICK_MS);
+        }
+        if UART_ACTIVITY.swap(false, Ordering::Relaxed) {
+            SCHEDULER.start(console_job, CONSOLE_AWAKE_MS);
+        }
+
+        while let Some(job) = SCHEDULER.next_due() {
+            if job == button_job {
+                BUTTON.on_tick(BUTTON_TICK_MS);
+
+                // Check for button presses. Other gestures are only logged.
+                while let Some(event) = BUTTON.next_event() {
+                    defmt::debug!("Button {}", event);
+                    if event == ButtonEvent::Press
+                        && let State::Active = control.state
+                        && control.on_press()
+                    {
+                        writeln!(
+                            UART_TX.writer(),
+                            "Button Press {:02} Woohoo!!\r",
+                            control.num_button_presses
+                        )
+                        .ok();
+
+                        // Immediately trigger blink rate change.
+                        SCHEDULER.set_period(blink_job, control.toggle_delay_ms);
+                        SCHEDULER.start(blink_job, 0);
+                    }
+                }
+
+                // After the allowed presses enter inactive state (unresponsive
+                // to button presses then) until the inactivity job runs.
+                if let Some(transition) = control.check_deactivate() {
+                    led.set_high();
+                    SCHEDULER.start(inactivity_job, control.config.inactive_ms);
+                    writeln!(
+                        UART_TX.writer(),
+                        "State {}; back in {} ms\r",
+                        transition,
+                        control.config.inactive_ms
+                    )
+                    .ok();
                 }
 
-                // Immediately trigger blink rate change.
-                break;
+                // Let the board stop until the next edge.
+                if !BUTTON.is_sampling() {
+                    SCHEDULER.stop(button_job);
+                }
+            } else if job == blink_job {
+                led.toggle();
+            } else if job == inactivity_job
+                && let Some(transition) = control.handle(Input::TimerExpired)
+            {
+                writeln!(UART_TX.writer(), "State {}\r", transition).ok();
             }
         }
 
-        // Toggle LED
-        led.toggle();
-
-        // After 5 button presses enter inactive state (unresponsive to
-        // button presses then) as a demonstration of basic state handling.
-        if let State::Active = system_state
-            && num_button_presses >= ALLOWED_PRESSES
-        {
-            led.set_high();
-            writeln!(uart_tx, "Deactivating program...\r").unwrap();
-            system_state = State::Inactive;
+        // Handle any command typed since the last pass. Replies that don't
+        // fit in the output queue are dropped.
+        match cortex_m::interrupt::free(|cs| PENDING_LINE.borrow(cs).take()) {
+            Some(Ok(line)) => match command::parse(line.as_str()) {
+                Ok(Command::Time(time)) => {
+                    if let Some(time) = time
+                        && let Err(err) = CLOCK.set(&time)
+                    {
+                        writeln!(UART_TX.writer(), "error: {}\r", err).ok();
+                    } else if let Some(now) = CLOCK.now() {
+                        writeln!(UART_TX.writer(), "time {}\r", now).ok();
+                    }
+                }
+                Ok(cmd) => {
+                    let reply = command::execute(cmd, &mut control);
+                    // Reactivated early, so the inactivity job isn't needed.
+                    if let Reply::Transition(_) = reply {
+                        SCHEDULER.stop(inactivity_job);
+                    }
+                    // `rate` and `reset` change the blink rate.
+                    SCHEDULER.set_period(blink_job, control.toggle_delay_ms);
+                    writeln!(UART_TX.writer(), "{}\r", reply).ok();
+                    // `status` also reports where the time has gone.
+                    if let Reply::Status { .. } = reply {
+                        let stats = power.stats();
+                        defmt::info!("Power: {}", stats);
+                        writeln!(UART_TX.writer(), "power: {}\r", stats).ok();
+                    }
+                }
+                Err(err) => {
+                    writeln!(UART_TX.writer(), "error: {}\r", err).ok();
+                }
+            },
+            Some(Err(LineError::TooLong)) => {
+                writeln!(UART_TX.writer(), "error: line too long\r").ok();
+            }
+            None => {}
+        }
 
-            // TODO: Start timer that triggers switch back to active.
+        // Idle until the next job, a button press or a received byte. Stop
+        // would halt SysTick and the USART, so only Sleep while they're
+        // needed.
+        let busy = BUTTON.is_sampling() || SCHEDULER.is_running(console_job) || !UART_TX.is_idle();
+        let idle_ms = SCHEDULER.idle_ms();
+        let mode = policy.choose(busy, idle_ms);
+        let wakeup_ms = idle_ms.filter(|_| mode == PowerMode::Stop);
+        let idle_ms = power.idle(mode, wakeup_ms);
+        if mode == PowerMode::Stop {
+            SCHEDULER.on_tick(idle_ms);
         }
     }
 }
 
+// Wakes the button driver when PA0 changes.
+#[interrupt]
+fn EXTI0() {
+    BUTTON.on_edge();
+}
+
+// PB7 fell while we were in Stop: the PC is sending. The byte itself is
+// lost, since the USART was stopped.
+#[interrupt]
+fn EXTI9_5() {
+    stm32f4d::power::clear_wakeup_line(RX_WAKE_LINE);
+    UART_ACTIVITY.store(true, Ordering::Relaxed);
+}
+
+// Wakes the main loop for the next job.
+#[interrupt]
+fn RTC_WKUP() {
+    stm32f4d::power::on_rtc_wakeup();
+}
+
+// Advances the scheduler's clock.
+#[exception]
+fn SysTick() {
+    SCHEDULER.on_tick(1);
+}
+
+// Sends queued output and collects received bytes into lines for
+// the main loop.
+#[interrupt]
+fn USART1() {
+    UART_TX.on_interrupt();
+
+    cortex_m::interrupt::free(|cs| {
+        let mut rx = UART_RX.borrow(cs).borrow_mut();
+        let Some(rx) = rx.as_mut() else {
+            return;
+        };
+
+        // Reading clears the interrupt. Errors such as overrun just
+        // drop the byte, and the line will fail to parse.
+        while rx.is_rx_not_empty() {
+            UART_ACTIVITY.store(true, Ordering::Relaxed);
+            if let Ok(byte) = rx.read()
+                && let Some(line) = LINE_BUFFER.borrow(cs).borrow_mut().push(byte)
+            {
+                // If the main loop hasn't handled the last line yet it's replaced.
+                PENDING_LINE.borrow(cs).replace(Some(line));
+            }
+        }
+    });
+}
+
 // On our machine can receive UART data with: minicom -D /dev/ttyUSB0
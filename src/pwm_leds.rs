@@ -0,0 +1,61 @@
+//! Dimmable user LEDs on TIM4 PWM.
+//!
+//! PD12–PD15 are TIM4 channels 1–4 in alternate function 2, so instead of
+//! switching them as GPIO outputs we can set each LED's brightness. Levels
+//! are perceived brightness, gamma corrected with the table from the core
+//! crate's build script. Indexes are the same as in [`crate::leds`].
+
+use stm32f4d_core::gamma;
+use stm32f4xx_hal::{
+    gpio::{PD12, PD13, PD14, PD15},
+    pac::TIM4,
+    prelude::*,
+    rcc::Clocks,
+    timer::{ErasedChannel, PwmExt},
+};
+
+use crate::leds::COUNT;
+
+// Fast enough not to flicker, slow enough for fine duty steps.
+const PWM_FREQ_HZ: u32 = 1000;
+
+pub struct PwmLeds {
+    channels: [ErasedChannel<TIM4>; COUNT],
+    max_duty: u16,
+}
+
+impl PwmLeds {
+    /// Takes TIM4 and the LED pins, all off.
+    pub fn new(tim4: TIM4, pins: (PD12, PD13, PD14, PD15), clocks: &Clocks) -> Self {
+        let (_, (ch1, ch2, ch3, ch4)) = tim4.pwm_hz(PWM_FREQ_HZ.Hz(), clocks);
+        let mut channels = [
+            ch1.with(pins.0).erase(),
+            ch2.with(pins.1).erase(),
+            ch3.with(pins.2).erase(),
+            ch4.with(pins.3).erase(),
+        ];
+        for channel in &mut channels {
+            channel.set_duty(0);
+            channel.enable();
+        }
+        // Zero means the full 16-bit range.
+        let max_duty = match channels[0].get_max_duty() {
+            0 => u16::MAX,
+            max_duty => max_duty,
+        };
+        Self { channels, max_duty }
+    }
+
+    /// Sets LED `led` to brightness `level`.
+    pub fn set(&mut self, led: usize, level: u8) {
+        self.channels[led].set_duty(gamma::duty(level, self.max_duty));
+    }
+
+    /// Sets every LED's brightness, e.g. from a
+    /// [`Fader`](stm32f4d_core::fade::Fader).
+    pub fn set_all(&mut self, levels: [u8; COUNT]) {
+        for (led, level) in levels.into_iter().enumerate() {
+            self.set(led, level);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Reset cause detection.
+//!
+//! The reset flags in RCC_CSR stay set across resets until cleared, so
+//! [`take_reset_cause`] clears them after reading; otherwise a later software
+//! reset would still show the power-on flag.
+
+use stm32f4d_core::reset::ResetCause;
+use stm32f4xx_hal::pac::RCC;
+
+/// Reads why the chip last reset and clears the flags for next time.
+///
+/// Doesn't need the `RCC` peripheral, so it can be called before
+/// `dp.RCC.constrain()`.
+pub fn take_reset_cause() -> ResetCause {
+    // SAFETY: Only touches RCC_CSR, which the HAL's clock setup doesn't use.
+    // Called once at startup before any other code could race on it.
+    let rcc = unsafe { &*RCC::ptr() };
+    let cause = ResetCause::from_csr(rcc.csr().read().bits());
+    rcc.csr().modify(|_, w| w.rmvf().clear());
+    cause
+}
+
+/// Logs why the chip last reset and returns it. Watchdog resets are logged
+/// as warnings. Only the independent watchdog is fed by the supervisor, so
+/// only its resets mean a supervised task stalled.
+pub fn report_reset_cause() -> ResetCause {
+    let cause = take_reset_cause();
+    match cause {
+        ResetCause::IndependentWatchdog => {
+            defmt::warn!("Booted after {}; a supervised task stalled", cause)
+        }
+        _ if cause.is_watchdog() => defmt::warn!("Booted after {}", cause),
+        _ => defmt::info!("Booted after {}", cause),
+    }
+    cause
+}
@@ -0,0 +1,172 @@
+//! Calendar clock on the RTC.
+//!
+//! [`RtcClock::init`] runs the RTC from the 32.768 kHz LSE crystal if one
+//! starts within a timeout, and otherwise from the LSI. The RTC sits in the
+//! backup domain, so on the LSE the time survives resets, though not power
+//! cycles since the board has no backup battery. The LSI stops on reset, and
+//! it's only accurate to several percent.
+//!
+//! The HAL's calendar functions count years from 1970, which puts the RTC's
+//! leap years in the wrong place, so the registers are read and written here
+//! with [`DateTime`], counting from 2000.
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::Mutex;
+use stm32f4d_core::datetime::{DateTime, DateTimeError};
+use stm32f4xx_hal::{
+    pac::{PWR, RCC, RTC},
+    rcc::Clocks,
+    rtc::Rtc,
+};
+
+/// Which oscillator clocks the RTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum ClockSource {
+    Lse,
+    Lsi,
+}
+
+/// The RTC, meant to live in a `static` so that interrupt handlers and UART
+/// writers can read the time.
+///
+/// ```ignore
+/// static CLOCK: RtcClock = RtcClock::new();
+///
+/// let source = CLOCK.init(dp.RTC, &mut dp.PWR, &clocks, LSE_TIMEOUT_MS);
+/// defmt::info!("RTC running from the {}", source);
+/// CLOCK.set(&DateTime::parse("2026-10-18 09:05:03").unwrap()).unwrap();
+/// ```
+pub struct RtcClock {
+    rtc: Mutex<RefCell<Option<Rtc>>>,
+}
+
+impl RtcClock {
+    pub const fn new() -> Self {
+        Self {
+            rtc: Mutex::new(RefCell::new(None)),
+        }
+    }
+
+    /// Starts the RTC, waiting up to `lse_timeout_ms` for the LSE crystal
+    /// unless it's already running from before a reset. Crystals can take a
+    /// couple of seconds to start.
+    pub fn init(
+        &self,
+        regs: RTC,
+        pwr: &mut PWR,
+        clocks: &Clocks,
+        lse_timeout_ms: u32,
+    ) -> ClockSource {
+        // SAFETY: Only RCC_APB1ENR, RCC_BDCR and PWR_CR, which the HAL's RTC
+        // driver sets up the same way below.
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.apb1enr().modify(|_, w| w.pwren().set_bit());
+        pwr.cr().modify(|_, w| w.dbp().set_bit());
+
+        let lse_ready =
+            rcc.bdcr().read().lserdy().bit_is_set() || start_lse(clocks, lse_timeout_ms);
+        let source = if lse_ready {
+            ClockSource::Lse
+        } else {
+            ClockSource::Lsi
+        };
+        // The HAL only resets the backup domain if the oscillator isn't
+        // ready, so a running calendar is kept.
+        let rtc = match source {
+            ClockSource::Lse => Rtc::new(regs, pwr),
+            ClockSource::Lsi => Rtc::new_lsi(regs, pwr),
+        };
+        cortex_m::interrupt::free(|cs| self.rtc.borrow(cs).replace(Some(rtc)));
+        source
+    }
+
+    /// The current time, or `None` before [`init`](Self::init). Reads
+    /// 2000-01-01 until the time is set.
+    pub fn now(&self) -> Option<DateTime> {
+        self.with(|rtc| {
+            let regs = &rtc.regs;
+            // Wait for the shadow registers to catch up with the calendar.
+            while regs.isr().read().rsf().bit_is_clear() {}
+            // Reading SSR then TR locks DR until it's read, so all three
+            // are from the same moment.
+            let ssr = regs.ssr().read().bits();
+            let tr = regs.tr().read().bits();
+            let dr = regs.dr().read().bits();
+            regs.isr().modify(|_, w| w.rsf().clear_bit());
+
+            let prediv_s = u32::from(regs.prer().read().prediv_s().bits());
+            DateTime::from_rtc(tr, dr, ssr, prediv_s)
+        })
+    }
+
+    /// Makes the next read wait for the calendar to be copied to the shadow
+    /// registers again. Call this after waking from Stop, where they aren't
+    /// updated.
+    pub fn resync(&self) {
+        self.with(|rtc| rtc.regs.isr().modify(|_, w| w.rsf().clear_bit()));
+    }
+
+    /// Milliseconds since midnight, or `None` before [`init`](Self::init).
+    pub fn time_of_day_ms(&self) -> Option<u32> {
+        self.now().map(|now| now.time_of_day().0)
+    }
+
+    /// Sets the calendar. Milliseconds are dropped since the subsecond
+    /// counter restarts on the whole second. Does nothing before
+    /// [`init`](Self::init).
+    pub fn set(&self, time: &DateTime) -> Result<(), DateTimeError> {
+        let (tr, dr) = time.to_rtc()?;
+        self.with(|rtc| {
+            let regs = &rtc.regs;
+            // Unlock the registers and stop the calendar to load it.
+            regs.wpr().write(|w| unsafe { w.bits(0xca) });
+            regs.wpr().write(|w| unsafe { w.bits(0x53) });
+            regs.isr().modify(|_, w| w.init().set_bit());
+            while regs.isr().read().initf().bit_is_clear() {}
+
+            // SAFETY: `to_rtc` only sets bits in the register fields.
+            regs.tr().write(|w| unsafe { w.bits(tr) });
+            regs.dr().write(|w| unsafe { w.bits(dr) });
+
+            regs.isr().modify(|_, w| w.init().clear_bit());
+            regs.isr().modify(|_, w| w.rsf().clear_bit());
+            regs.wpr().write(|w| unsafe { w.bits(0xff) });
+        });
+        Ok(())
+    }
+
+    /// Runs `f` with the HAL's RTC driver, e.g. for the wakeup timer, or
+    /// returns `None` before [`init`](Self::init). Don't use its calendar
+    /// functions; see the module documentation.
+    pub fn with<R>(&self, f: impl FnOnce(&mut Rtc) -> R) -> Option<R> {
+        cortex_m::interrupt::free(|cs| self.rtc.borrow(cs).borrow_mut().as_mut().map(f))
+    }
+}
+
+impl Default for RtcClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Resets the backup domain and starts the LSE, giving up and turning it off
+// again after `timeout_ms`.
+fn start_lse(clocks: &Clocks, timeout_ms: u32) -> bool {
+    // SAFETY: The backup domain only holds the RTC, which isn't running yet.
+    let rcc = unsafe { &*RCC::ptr() };
+    rcc.bdcr().modify(|_, w| w.bdrst().set_bit());
+    rcc.bdcr().modify(|_, w| w.bdrst().clear_bit());
+    rcc.bdcr()
+        .modify(|_, w| w.lsebyp().clear_bit().lseon().set_bit());
+
+    let cycles_per_ms = clocks.sysclk().to_Hz() / 1000;
+    for _ in 0..timeout_ms {
+        if rcc.bdcr().read().lserdy().bit_is_set() {
+            return true;
+        }
+        cortex_m::asm::delay(cycles_per_ms);
+    }
+    rcc.bdcr().modify(|_, w| w.lseon().clear_bit());
+    false
+}
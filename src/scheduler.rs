@@ -0,0 +1,121 @@
+//! Cooperative scheduler for the non-RTIC binaries.
+//!
+//! SysTick advances the clock every millisecond, and the main loop runs the
+//! jobs as they fall due, so a job never preempts another and can use
+//! anything the loop owns. A job that runs long only delays the others. The
+//! due times are kept by [`Schedule`] in the core crate.
+//!
+//! SysTick stops in Stop mode, so a binary that stops passes the time spent
+//! there, measured by the RTC, to [`Scheduler::on_tick`] when it wakes.
+
+use core::cell::RefCell;
+
+use cortex_m::{interrupt::Mutex, peripheral::SYST};
+use stm32f4d_core::schedule::{JobId, Schedule};
+use stm32f4xx_hal::{prelude::*, rcc::Clocks, timer::SysEvent};
+
+/// Up to `N` jobs timed by SysTick, meant to live in a `static`.
+///
+/// ```ignore
+/// static SCHEDULER: Scheduler<2> = Scheduler::new();
+///
+/// SCHEDULER.start_systick(cp.SYST, &clocks);
+/// let blink = SCHEDULER.every(500);
+///
+/// loop {
+///     while let Some(job) = SCHEDULER.next_due() {
+///         if job == blink {
+///             led.toggle();
+///         }
+///     }
+///     cortex_m::asm::wfi();
+/// }
+///
+/// #[exception]
+/// fn SysTick() {
+///     SCHEDULER.on_tick(1);
+/// }
+/// ```
+pub struct Scheduler<const N: usize> {
+    schedule: Mutex<RefCell<Schedule<N>>>,
+}
+
+impl<const N: usize> Scheduler<N> {
+    pub const fn new() -> Self {
+        Self {
+            schedule: Mutex::new(RefCell::new(Schedule::new())),
+        }
+    }
+
+    /// Starts SysTick interrupting every millisecond. Its handler must call
+    /// [`on_tick`](Self::on_tick)`(1)`.
+    pub fn start_systick(&self, syst: SYST, clocks: &Clocks) {
+        let mut systick = syst.counter_hz(clocks);
+        systick.start(1.kHz()).unwrap();
+        // Dropping the driver leaves the counter running.
+        systick.listen(SysEvent::Update);
+    }
+
+    /// Adds a job that runs every `period_ms`, starting one period from now.
+    ///
+    /// Panics if more than `N` jobs are added.
+    pub fn every(&self, period_ms: u32) -> JobId {
+        self.with(|schedule| schedule.every(period_ms).unwrap())
+    }
+
+    /// Adds a one-shot job, which doesn't run until [`start`](Self::start)ed.
+    ///
+    /// Panics if more than `N` jobs are added.
+    pub fn once(&self) -> JobId {
+        self.with(|schedule| schedule.once().unwrap())
+    }
+
+    /// Runs `job` after `delay_ms`, replacing any earlier due time.
+    pub fn start(&self, job: JobId, delay_ms: u32) {
+        self.with(|schedule| schedule.start(job, delay_ms));
+    }
+
+    pub fn stop(&self, job: JobId) {
+        self.with(|schedule| schedule.stop(job));
+    }
+
+    pub fn is_running(&self, job: JobId) -> bool {
+        self.with(|schedule| schedule.is_running(job))
+    }
+
+    /// Changes a periodic job's period from its next run on.
+    pub fn set_period(&self, job: JobId, period_ms: u32) {
+        self.with(|schedule| schedule.set_period(job, period_ms));
+    }
+
+    /// Advances the clock. Call this from the SysTick handler, and after
+    /// waking from Stop mode with the time spent there.
+    pub fn on_tick(&self, elapsed_ms: u32) {
+        self.with(|schedule| schedule.tick(elapsed_ms));
+    }
+
+    /// Milliseconds since SysTick started, wrapping after about 49 days.
+    pub fn now_ms(&self) -> u32 {
+        self.with(|schedule| schedule.now_ms())
+    }
+
+    /// Takes the next job to run, if one is due.
+    pub fn next_due(&self) -> Option<JobId> {
+        self.with(|schedule| schedule.next_due())
+    }
+
+    /// Time until the next job is due, or `None` if no job is running.
+    pub fn idle_ms(&self) -> Option<u32> {
+        self.with(|schedule| schedule.idle_ms())
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut Schedule<N>) -> R) -> R {
+        cortex_m::interrupt::free(|cs| f(&mut self.schedule.borrow(cs).borrow_mut()))
+    }
+}
+
+impl<const N: usize> Default for Scheduler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,231 @@
+//! Buffered, interrupt-driven UART transmitter.
+//!
+//! Writes from any context are copied into a [`ByteRing`] and return
+//! immediately; the USART's TXE interrupt then moves the bytes out one at a
+//! time. If the ring is full a write is dropped and counted instead of
+//! blocking, which keeps interrupt handlers that print from stalling.
+//!
+//! Formatted output is put together a line at a time on the stack and queued
+//! whole, so a line is either sent complete or dropped, never cut up. Given
+//! an [`RtcClock`], each line starts with the time, like
+//! `[2026-10-18 09:05:03.250] `.
+
+use core::{
+    cell::{Cell, RefCell},
+    fmt::{self, Write},
+};
+
+use cortex_m::interrupt::Mutex;
+use stm32f4d_core::ring::{ByteRing, RingStats};
+use stm32f4xx_hal::{
+    prelude::*,
+    serial::{Instance, Tx},
+};
+
+use crate::rtc::RtcClock;
+
+/// The longest line a [`Writer`] sends, timestamp and line ending included.
+/// Longer lines are dropped.
+pub const LINE_LEN: usize = 192;
+
+/// A UART transmitter with an `N` byte queue, meant to live in a `static`.
+///
+/// ```ignore
+/// static UART_TX: BufferedTx<USART1, 256> = BufferedTx::new();
+///
+/// UART_TX.init(uart_tx);
+/// writeln!(UART_TX.writer(), "Hello\r").ok();
+///
+/// #[interrupt]
+/// fn USART1() {
+///     UART_TX.on_interrupt();
+/// }
+/// ```
+///
+/// The USART interrupt must be unmasked in the NVIC for the queue to drain.
+pub struct BufferedTx<U: Instance, const N: usize> {
+    ring: Mutex<RefCell<ByteRing<N>>>,
+    tx: Mutex<RefCell<Option<Tx<U>>>>,
+    clock: Mutex<Cell<Option<&'static RtcClock>>>,
+}
+
+impl<U: Instance, const N: usize> BufferedTx<U, N> {
+    pub const fn new() -> Self {
+        Self {
+            ring: Mutex::new(RefCell::new(ByteRing::new())),
+            tx: Mutex::new(RefCell::new(None)),
+            clock: Mutex::new(Cell::new(None)),
+        }
+    }
+
+    /// Starts each line written through [`writer`](Self::writer) with the
+    /// time on `clock`, once it's initialised.
+    pub fn set_clock(&self, clock: &'static RtcClock) {
+        cortex_m::interrupt::free(|cs| self.clock.borrow(cs).set(Some(clock)));
+    }
+
+    /// Hands over the transmitter. Writes made before this are queued.
+    pub fn init(&self, tx: Tx<U>) {
+        cortex_m::interrupt::free(|cs| {
+            self.tx.borrow(cs).replace(Some(tx));
+            self.start(cs);
+        });
+    }
+
+    /// Queues all of `bytes`, or drops them and returns `false` if they don't
+    /// fit. Binary data like frames goes through here without timestamps.
+    pub fn write(&self, bytes: &[u8]) -> bool {
+        cortex_m::interrupt::free(|cs| {
+            let queued = self.ring.borrow(cs).borrow_mut().push(bytes);
+            if queued {
+                self.start(cs);
+            }
+            queued
+        })
+    }
+
+    /// A handle for formatted writes, e.g. with `writeln!`.
+    ///
+    /// Each line is queued once it's complete, and any unfinished line when
+    /// the handle is dropped. A line that doesn't fit is dropped, counted and
+    /// makes the write return an error; it never blocks. Lines are
+    /// timestamped if there's a clock.
+    pub fn writer(&self) -> Writer<'_, U, N> {
+        let clock = cortex_m::interrupt::free(|cs| self.clock.borrow(cs).get());
+        Writer {
+            tx: self,
+            clock,
+            line: [0; LINE_LEN],
+            len: 0,
+        }
+    }
+
+    /// Whether everything queued has been sent, including the last byte's
+    /// stop bit. The USART stops in Stop mode, so wait for this first.
+    pub fn is_idle(&self) -> bool {
+        cortex_m::interrupt::free(|cs| {
+            let sent = match self.tx.borrow(cs).borrow_mut().as_mut() {
+                Some(tx) => tx.flush().is_ok(),
+                None => true,
+            };
+            sent && self.ring.borrow(cs).borrow().is_empty()
+        })
+    }
+
+    /// Overflow and usage counters.
+    pub fn stats(&self) -> RingStats {
+        cortex_m::interrupt::free(|cs| self.ring.borrow(cs).borrow().stats())
+    }
+
+    /// Sends queued bytes while the data register is free. Call this from the
+    /// USART interrupt handler.
+    pub fn on_interrupt(&self) {
+        cortex_m::interrupt::free(|cs| {
+            let mut tx = self.tx.borrow(cs).borrow_mut();
+            let Some(tx) = tx.as_mut() else {
+                return;
+            };
+            let mut ring = self.ring.borrow(cs).borrow_mut();
+
+            while tx.is_tx_empty() {
+                let Some(byte) = ring.pop() else {
+                    // Nothing left; stop the TXE interrupt until the next write.
+                    tx.unlisten();
+                    return;
+                };
+                // Can't block since the data register is empty.
+                tx.write(byte).ok();
+            }
+        });
+    }
+
+    // Enables the TXE interrupt, which fires straight away if the data
+    // register is empty.
+    fn start(&self, cs: &cortex_m::interrupt::CriticalSection) {
+        if let Some(tx) = self.tx.borrow(cs).borrow_mut().as_mut() {
+            tx.listen();
+        }
+    }
+}
+
+impl<U: Instance, const N: usize> Default for BufferedTx<U, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Formatted-write handle returned by [`BufferedTx::writer`].
+pub struct Writer<'a, U: Instance, const N: usize> {
+    tx: &'a BufferedTx<U, N>,
+    clock: Option<&'static RtcClock>,
+    // The line so far. `len` counts everything written to it, so it's more
+    // than `LINE_LEN` if the line has outgrown the buffer.
+    line: [u8; LINE_LEN],
+    len: usize,
+}
+
+impl<U: Instance, const N: usize> Writer<'_, U, N> {
+    fn append(&mut self, bytes: &[u8]) {
+        if let Some(free) = self.line.get_mut(self.len..) {
+            let fits = bytes.len().min(free.len());
+            free[..fits].copy_from_slice(&bytes[..fits]);
+        }
+        self.len += bytes.len();
+    }
+
+    // Writes "[time] " to start a line if there's a clock.
+    fn timestamp(&mut self) {
+        if let Some(now) = self.clock.and_then(RtcClock::now) {
+            write!(LineWriter(self), "[{}] ", now).ok();
+        }
+    }
+
+    // Queues the line so far, or counts it as dropped if it's too long.
+    fn send_line(&mut self) -> fmt::Result {
+        let len = core::mem::take(&mut self.len);
+        let queued = match self.line.get(..len) {
+            Some(line) => self.tx.write(line),
+            None => {
+                cortex_m::interrupt::free(|cs| {
+                    self.tx.ring.borrow(cs).borrow_mut().count_dropped(len)
+                });
+                false
+            }
+        };
+        if queued { Ok(()) } else { Err(fmt::Error) }
+    }
+}
+
+impl<U: Instance, const N: usize> fmt::Write for Writer<'_, U, N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut result = Ok(());
+        for piece in s.split_inclusive('\n') {
+            if self.len == 0 {
+                self.timestamp();
+            }
+            self.append(piece.as_bytes());
+            if piece.ends_with('\n') {
+                result = result.and(self.send_line());
+            }
+        }
+        result
+    }
+}
+
+impl<U: Instance, const N: usize> Drop for Writer<'_, U, N> {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            self.send_line().ok();
+        }
+    }
+}
+
+// Appends to the line without starting a new one, for the timestamp itself.
+struct LineWriter<'a, 'b, U: Instance, const N: usize>(&'a mut Writer<'b, U, N>);
+
+impl<U: Instance, const N: usize> fmt::Write for LineWriter<'_, '_, U, N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.append(s.as_bytes());
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+//! Independent watchdog (IWDG) supervision.
+//!
+//! Tasks register with a deadline and check in as they run. A periodic check,
+//! e.g. from a timer interrupt, feeds the IWDG only while every task has
+//! checked in on time, so one stalled task resets the board even if the
+//! others keep running. The bookkeeping is [`Supervisor`] in the core crate.
+//!
+//! After such a reset [`crate::reset::take_reset_cause`] returns
+//! `ResetCause::IndependentWatchdog`.
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::Mutex;
+use stm32f4d_core::supervisor::{Supervisor, TaskId};
+use stm32f4xx_hal::{
+    pac::{DBGMCU, IWDG},
+    prelude::*,
+    watchdog::IndependentWatchdog,
+};
+
+/// A watchdog supervisor for up to `N` tasks, meant to live in a `static`.
+///
+/// ```ignore
+/// static WATCHDOG: Watchdog<2> = Watchdog::new();
+///
+/// let task = WATCHDOG.register("dma", 100);
+/// WATCHDOG.start(dp.IWDG, &dp.DBGMCU, 1000);
+///
+/// // In the supervised task:
+/// WATCHDOG.check_in(task);
+///
+/// // Every 100 ms:
+/// WATCHDOG.check(100);
+/// ```
+pub struct Watchdog<const N: usize> {
+    supervisor: Mutex<RefCell<Supervisor<N>>>,
+    iwdg: Mutex<RefCell<Option<IndependentWatchdog>>>,
+    // Whether we've logged the current overdue task.
+    reported: Mutex<RefCell<bool>>,
+}
+
+impl<const N: usize> Watchdog<N> {
+    pub const fn new() -> Self {
+        Self {
+            supervisor: Mutex::new(RefCell::new(Supervisor::new())),
+            iwdg: Mutex::new(RefCell::new(None)),
+            reported: Mutex::new(RefCell::new(false)),
+        }
+    }
+
+    /// Adds a task that must call [`check_in`](Self::check_in) at least
+    /// every `deadline_ms`.
+    ///
+    /// Panics if more than `N` tasks register.
+    pub fn register(&self, name: &'static str, deadline_ms: u32) -> TaskId {
+        cortex_m::interrupt::free(|cs| {
+            self.supervisor
+                .borrow(cs)
+                .borrow_mut()
+                .register(name, deadline_ms)
+                .unwrap()
+        })
+    }
+
+    /// Starts the IWDG with a `timeout_ms` reset timeout. Once started it
+    /// can't be stopped, except by a reset. It's paused while a debugger has
+    /// the core halted.
+    pub fn start(&self, iwdg: IWDG, dbgmcu: &DBGMCU, timeout_ms: u32) {
+        let mut iwdg = IndependentWatchdog::new(iwdg);
+        iwdg.stop_on_debug(dbgmcu, true);
+        iwdg.start(timeout_ms.millis());
+        cortex_m::interrupt::free(|cs| self.iwdg.borrow(cs).replace(Some(iwdg)));
+    }
+
+    pub fn check_in(&self, task: TaskId) {
+        cortex_m::interrupt::free(|cs| self.supervisor.borrow(cs).borrow_mut().check_in(task));
+    }
+
+    /// Advances the supervisor's clock and feeds the IWDG if every task is
+    /// on time. Call this every `elapsed_ms`, more often than the IWDG
+    /// timeout. Returns whether it fed the IWDG.
+    pub fn check(&self, elapsed_ms: u32) -> bool {
+        cortex_m::interrupt::free(|cs| {
+            let status = self.supervisor.borrow(cs).borrow_mut().advance(elapsed_ms);
+            let mut reported = self.reported.borrow(cs).borrow_mut();
+            match status {
+                Ok(()) => {
+                    if let Some(iwdg) = self.iwdg.borrow(cs).borrow_mut().as_mut() {
+                        iwdg.feed();
+                    }
+                    *reported = false;
+                    true
+                }
+                Err(overdue) => {
+                    // Log once; the IWDG resets the board soon after.
+                    if !*reported {
+                        defmt::error!(
+                            "Task {=str} hasn't checked in for {=u32} ms; not feeding the watchdog",
+                            overdue.name,
+                            overdue.silent_ms
+                        );
+                        *reported = true;
+                    }
+                    false
+                }
+            }
+        })
+    }
+}
+
+impl<const N: usize> Default for Watchdog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}